@@ -1,7 +1,11 @@
+use std::time::Duration;
+
 use reqwest::Client;
 
 use crate::repo::RepoApi;
 use crate::session::Session;
+use crate::sync::SyncApi;
+use crate::xrpc::{RetryPolicy, Transport};
 
 /// Main interface for ATProto API operations.
 ///
@@ -40,21 +44,28 @@ use crate::session::Session;
 /// ```
 pub struct Agent<S: Session> {
     session: S,
-    http: Client,
+    http: Transport,
 }
 
 impl<S: Session> Agent<S> {
-    /// Create a new agent with the given session.
+    /// Create a new agent with the given session and the default retry policy.
     pub fn new(session: S) -> Self {
-        Self {
-            session,
-            http: Client::new(),
-        }
+        Self::builder(session).build()
     }
 
     /// Create a new agent with a custom HTTP client.
     pub fn with_http_client(session: S, http: Client) -> Self {
-        Self { session, http }
+        Self::builder(session).http_client(http).build()
+    }
+
+    /// Configure the HTTP client, retry policy and timeouts of a new agent.
+    pub fn builder(session: S) -> AgentBuilder<S> {
+        AgentBuilder {
+            session,
+            http: None,
+            retry: RetryPolicy::default(),
+            timeout: None,
+        }
     }
 
     /// Get the current user's DID.
@@ -72,8 +83,57 @@ impl<S: Session> Agent<S> {
         RepoApi::new(&self.session, &self.http)
     }
 
+    /// Access repository export operations (com.atproto.sync.*).
+    pub fn sync(&self) -> SyncApi<'_, S> {
+        SyncApi::new(&self.session, &self.http)
+    }
+
     /// Get a reference to the underlying session.
     pub fn session(&self) -> &S {
         &self.session
     }
 }
+
+/// Builder for an `Agent` with a non-default request policy.
+///
+/// ```ignore
+/// let agent = Agent::builder(session)
+///     .http_client(client)
+///     .retry_policy(RetryPolicy { max_retries: 5, ..Default::default() })
+///     .timeout(Duration::from_secs(10))
+///     .build();
+/// ```
+pub struct AgentBuilder<S: Session> {
+    session: S,
+    http: Option<Client>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+}
+
+impl<S: Session> AgentBuilder<S> {
+    /// Send requests through this client instead of a fresh one.
+    pub fn http_client(mut self, http: Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Replace the default retry policy.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Give up on a single attempt after this long. Each retry gets the full
+    /// timeout again.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> Agent<S> {
+        Agent {
+            session: self.session,
+            http: Transport::new(self.http.unwrap_or_default(), self.retry, self.timeout),
+        }
+    }
+}
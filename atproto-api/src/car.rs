@@ -0,0 +1,192 @@
+//! CAR v1 (Content Addressable aRchive) files, the format of repo exports
+//! and `com.atproto.sync.*` responses.
+
+use std::collections::HashMap;
+
+use crate::dag_cbor::{self, Value};
+use crate::types::Cid;
+use crate::varint;
+use crate::Error;
+
+/// A parsed CAR file: its root CIDs and the blocks it contains.
+#[derive(Debug, Clone, Default)]
+pub struct Car {
+    roots: Vec<Cid>,
+    /// Blocks in file order, so `to_bytes` writes the file back unchanged
+    blocks: Vec<(Cid, Vec<u8>)>,
+    index: HashMap<Cid, usize>,
+}
+
+impl Car {
+    /// Parse a CAR v1 file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let mut data = bytes;
+        let header_len = varint::read(&mut data).map_err(|e| Error::Car(e.to_string()))? as usize;
+        if data.len() < header_len {
+            return Err(Error::Car("truncated header".to_string()));
+        }
+        let (header, mut data) = data.split_at(header_len);
+        let header = dag_cbor::decode(header)?;
+        if header.get("version").and_then(Value::as_i64) != Some(1) {
+            return Err(Error::Car("not a CAR v1 file".to_string()));
+        }
+        let roots = header
+            .get("roots")
+            .and_then(Value::as_list)
+            .ok_or_else(|| Error::Car("header has no roots".to_string()))?
+            .iter()
+            .map(|root| {
+                root.as_link()
+                    .cloned()
+                    .ok_or_else(|| Error::Car("root is not a CID link".to_string()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let mut car = Self {
+            roots,
+            ..Self::default()
+        };
+        while !data.is_empty() {
+            let len = varint::read(&mut data).map_err(|e| Error::Car(e.to_string()))? as usize;
+            if data.len() < len {
+                return Err(Error::Car("truncated block".to_string()));
+            }
+            let (mut section, rest) = data.split_at(len);
+            let cid = Cid::read_bytes(&mut section)?;
+            car.insert(cid, section.to_vec());
+            data = rest;
+        }
+        Ok(car)
+    }
+
+    /// Serialize back to a CAR v1 file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        use ciborium::Value as Cbor;
+
+        let link = |cid: &Cid| {
+            let mut bytes = vec![0];
+            bytes.extend_from_slice(cid.as_bytes());
+            Cbor::Tag(42, Box::new(Cbor::Bytes(bytes)))
+        };
+        // DAG-CBOR orders map keys by length first, so "roots" precedes "version"
+        let header = Cbor::Map(vec![
+            (
+                Cbor::Text("roots".into()),
+                Cbor::Array(self.roots.iter().map(link).collect()),
+            ),
+            (Cbor::Text("version".into()), Cbor::Integer(1.into())),
+        ]);
+        let mut header_bytes = Vec::new();
+        ciborium::into_writer(&header, &mut header_bytes).expect("writing to a Vec can't fail");
+
+        let mut out = Vec::new();
+        varint::write(header_bytes.len() as u64, &mut out);
+        out.extend(header_bytes);
+        for (cid, block) in &self.blocks {
+            varint::write((cid.as_bytes().len() + block.len()) as u64, &mut out);
+            out.extend_from_slice(cid.as_bytes());
+            out.extend_from_slice(block);
+        }
+        out
+    }
+
+    pub fn roots(&self) -> &[Cid] {
+        &self.roots
+    }
+
+    /// The first root; for repo exports, the signed commit.
+    pub fn root(&self) -> Option<&Cid> {
+        self.roots.first()
+    }
+
+    /// Raw bytes of a block.
+    pub fn get(&self, cid: &Cid) -> Option<&[u8]> {
+        self.index.get(cid).map(|&i| self.blocks[i].1.as_slice())
+    }
+
+    /// Decode a block as DAG-CBOR.
+    pub fn decode(&self, cid: &Cid) -> Result<Value, Error> {
+        let block = self
+            .get(cid)
+            .ok_or_else(|| Error::Car(format!("block {} not found", cid)))?;
+        dag_cbor::decode(block)
+    }
+
+    /// All blocks, in file order.
+    pub fn blocks(&self) -> impl Iterator<Item = (&Cid, &[u8])> {
+        self.blocks.iter().map(|(cid, block)| (cid, block.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    fn insert(&mut self, cid: Cid, block: Vec<u8>) {
+        // A block may appear more than once; the first copy is kept
+        if !self.index.contains_key(&cid) {
+            self.index.insert(cid.clone(), self.blocks.len());
+            self.blocks.push((cid, block));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cid(codec: u8, fill: u8) -> Cid {
+        let mut bytes = vec![0x01, codec, 0x12, 0x20];
+        bytes.extend([fill; 32]);
+        Cid::from_bytes(&bytes).unwrap()
+    }
+
+    fn sample() -> Car {
+        let record = {
+            let mut out = Vec::new();
+            let value = ciborium::Value::Map(vec![(
+                ciborium::Value::Text("name".into()),
+                ciborium::Value::Text("Pancakes".into()),
+            )]);
+            ciborium::into_writer(&value, &mut out).unwrap();
+            out
+        };
+        let mut car = Car {
+            roots: vec![cid(0x71, 1)],
+            ..Car::default()
+        };
+        car.insert(cid(0x71, 1), record);
+        car.insert(cid(0x55, 2), b"not cbor".to_vec());
+        car
+    }
+
+    #[test]
+    fn test_car_roundtrip() {
+        let bytes = sample().to_bytes();
+        let car = Car::parse(&bytes).unwrap();
+
+        assert_eq!(car.root(), Some(&cid(0x71, 1)));
+        assert_eq!(car.len(), 2);
+        assert_eq!(car.get(&cid(0x55, 2)), Some(&b"not cbor"[..]));
+        let record = car.decode(&cid(0x71, 1)).unwrap();
+        assert_eq!(record.get("name").and_then(Value::as_str), Some("Pancakes"));
+        assert_eq!(car.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_missing_block() {
+        let car = sample();
+        assert!(car.get(&cid(0x71, 3)).is_none());
+        assert!(matches!(car.decode(&cid(0x71, 3)), Err(Error::Car(_))));
+    }
+
+    #[test]
+    fn test_truncated_car() {
+        let bytes = sample().to_bytes();
+        assert!(Car::parse(&bytes[..bytes.len() - 4]).is_err());
+        assert!(Car::parse(&bytes[..3]).is_err());
+    }
+}
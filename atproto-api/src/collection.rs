@@ -0,0 +1,39 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::types::{AtUri, Nsid, RecordKey};
+
+/// Binds a record type to the NSID of the collection it is stored in.
+///
+/// Implement this on a marker type per lexicon record, then use the typed
+/// helpers on `RepoApi` instead of passing the NSID around as a string:
+///
+/// ```ignore
+/// use atproto_api::Collection;
+///
+/// struct Status;
+///
+/// impl Collection for Status {
+///     const NSID: &'static str = "xyz.statusphere.status";
+///     type Record = StatusRecord;
+/// }
+///
+/// let output = agent.repo().create::<Status>(&record).await?;
+/// let record = agent.repo().get::<Status>(&rkey).await?;
+/// ```
+pub trait Collection {
+    /// NSID of the collection, e.g. "app.bsky.feed.post"
+    const NSID: &'static str;
+
+    /// The record stored under each key of the collection
+    type Record: Serialize + DeserializeOwned + Send;
+
+    /// URI of the record at `rkey` in `repo`.
+    ///
+    /// # Panics
+    /// If `NSID` is not a valid NSID.
+    fn uri(repo: &str, rkey: &RecordKey) -> AtUri {
+        let nsid = Nsid::new(Self::NSID).expect("Collection::NSID must be a valid NSID");
+        AtUri::record(repo, nsid, rkey.clone())
+    }
+}
@@ -0,0 +1,161 @@
+//! Repo signing keys, as published in DID documents.
+
+use k256::ecdsa::signature::Verifier;
+
+use crate::Error;
+
+/// Multicodec prefixes of compressed public keys in multikey encoding
+const SECP256K1_PUB: [u8; 2] = [0xe7, 0x01];
+const P256_PUB: [u8; 2] = [0x80, 0x24];
+
+const BASE58_CHARS: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// A public key that repo commits are signed with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    K256(k256::ecdsa::VerifyingKey),
+    P256(p256::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    /// Parse a multibase-encoded multikey (`z...`), the format of
+    /// `publicKeyMultibase` in DID documents.
+    pub fn from_multikey(multikey: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidKey(multikey.to_string());
+        let encoded = multikey.strip_prefix('z').ok_or_else(invalid)?;
+        let bytes = base58_decode(encoded).ok_or_else(invalid)?;
+        if bytes.len() < 2 {
+            return Err(invalid());
+        }
+        let (codec, key) = bytes.split_at(2);
+        if codec == SECP256K1_PUB {
+            let key = k256::ecdsa::VerifyingKey::from_sec1_bytes(key).map_err(|_| invalid())?;
+            Ok(PublicKey::K256(key))
+        } else if codec == P256_PUB {
+            let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(key).map_err(|_| invalid())?;
+            Ok(PublicKey::P256(key))
+        } else {
+            Err(invalid())
+        }
+    }
+
+    /// Parse a `did:key:z...` identifier.
+    pub fn from_did_key(did_key: &str) -> Result<Self, Error> {
+        let multikey = did_key
+            .strip_prefix("did:key:")
+            .ok_or_else(|| Error::InvalidKey(did_key.to_string()))?;
+        Self::from_multikey(multikey)
+    }
+
+    /// Find the `#atproto` signing key in a DID document.
+    pub fn from_did_document(document: &serde_json::Value) -> Result<Self, Error> {
+        let method = document["verificationMethod"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|method| {
+                method["id"]
+                    .as_str()
+                    .is_some_and(|id| id.ends_with("#atproto"))
+            })
+            .ok_or_else(|| Error::InvalidKey("DID document has no #atproto key".to_string()))?;
+        let multikey = method["publicKeyMultibase"]
+            .as_str()
+            .ok_or_else(|| Error::InvalidKey("#atproto key has no publicKeyMultibase".to_string()))?;
+        Self::from_multikey(multikey)
+    }
+
+    /// Check a compact (`r || s`) ECDSA signature over SHA-256 of `message`.
+    ///
+    /// ATProto only accepts low-S signatures, so the high-S twin of a valid
+    /// signature is rejected.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let valid = match self {
+            PublicKey::K256(key) => k256::ecdsa::Signature::from_slice(signature)
+                .ok()
+                .filter(|sig| sig.normalize_s().is_none())
+                .is_some_and(|sig| key.verify(message, &sig).is_ok()),
+            PublicKey::P256(key) => p256::ecdsa::Signature::from_slice(signature)
+                .ok()
+                .filter(|sig| sig.normalize_s().is_none())
+                .is_some_and(|sig| key.verify(message, &sig).is_ok()),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+}
+
+fn base58_decode(encoded: &str) -> Option<Vec<u8>> {
+    // Little-endian base-256 digits of the number
+    let mut digits: Vec<u8> = Vec::new();
+    for c in encoded.bytes() {
+        let mut carry = BASE58_CHARS.iter().position(|&b| b == c)? as u32;
+        for digit in &mut digits {
+            carry += u32::from(*digit) * 58;
+            *digit = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            digits.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    // Each leading '1' stands for a leading zero byte
+    let zeros = encoded.bytes().take_while(|&c| c == b'1').count();
+    digits.extend(std::iter::repeat_n(0, zeros));
+    digits.reverse();
+    Some(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors from the atproto cryptography spec
+    const K256_DID_KEY: &str = "did:key:zQ3shqwJEJyMBsBXCWyCBpUBMqxcon9oHB7mCvx4sSpMdLJwc";
+    const P256_DID_KEY: &str = "did:key:zDnaembgSGUhZULN2Caob4HLJPaxBh92N7rtH21TErzqf8HQo";
+
+    #[test]
+    fn test_parse_did_keys() {
+        assert!(matches!(PublicKey::from_did_key(K256_DID_KEY), Ok(PublicKey::K256(_))));
+        assert!(matches!(PublicKey::from_did_key(P256_DID_KEY), Ok(PublicKey::P256(_))));
+        assert!(PublicKey::from_did_key("did:key:zQ3sh").is_err());
+        assert!(PublicKey::from_did_key("did:plc:abc").is_err());
+    }
+
+    #[test]
+    fn test_key_from_did_document() {
+        let document = serde_json::json!({
+            "id": "did:plc:abc",
+            "verificationMethod": [{
+                "id": "did:plc:abc#atproto",
+                "type": "Multikey",
+                "controller": "did:plc:abc",
+                "publicKeyMultibase": K256_DID_KEY.trim_start_matches("did:key:"),
+            }],
+        });
+        assert!(PublicKey::from_did_document(&document).is_ok());
+        assert!(PublicKey::from_did_document(&serde_json::json!({})).is_err());
+    }
+
+    #[test]
+    fn test_verify_low_s_only() {
+        use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+        let signing_key = SigningKey::from_slice(&[7u8; 32]).unwrap();
+        let key = PublicKey::K256(*signing_key.verifying_key());
+        let signature: Signature = signing_key.sign(b"commit");
+        let signature = signature.normalize_s().unwrap_or(signature);
+
+        assert!(key.verify(b"commit", &signature.to_bytes()).is_ok());
+        assert!(matches!(key.verify(b"tampered", &signature.to_bytes()), Err(Error::InvalidSignature)));
+
+        // Same signature with s replaced by n - s
+        let (r, s) = signature.split_scalars();
+        let high = Signature::from_scalars(r, -*s).unwrap();
+        assert!(key.verify(b"commit", &high.to_bytes()).is_err());
+    }
+}
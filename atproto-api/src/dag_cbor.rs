@@ -0,0 +1,330 @@
+//! DAG-CBOR, the binary encoding of records and repo blocks.
+
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::types::Cid;
+use crate::Error;
+
+/// CBOR tag marking a CID link
+const CID_TAG: u64 = 42;
+
+/// A decoded DAG-CBOR value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    Link(Cid),
+}
+
+/// Decode a single DAG-CBOR value that makes up all of `bytes`.
+pub fn decode(bytes: &[u8]) -> Result<Value, Error> {
+    let mut data = bytes;
+    let value = read(&mut data)?;
+    if !data.is_empty() {
+        return Err(Error::DagCbor("trailing bytes after value".to_string()));
+    }
+    Ok(value)
+}
+
+/// Decode one DAG-CBOR value from the front of `data`, advancing past it.
+pub fn read(data: &mut &[u8]) -> Result<Value, Error> {
+    let value: ciborium::Value =
+        ciborium::from_reader(&mut *data).map_err(|e| Error::DagCbor(e.to_string()))?;
+    Value::try_from(value)
+}
+
+/// Encode a value in canonical DAG-CBOR form: shortest-length integers and
+/// headers, 64-bit floats, and map keys sorted by length, then bytewise.
+///
+/// Decoding a canonical block and encoding it again gives the same bytes,
+/// which is what commit signatures and CIDs are computed over.
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_value(value, &mut out);
+    out
+}
+
+fn write_header(major: u8, len: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    if len < 24 {
+        out.push(major | len as u8);
+    } else if len <= u64::from(u8::MAX) {
+        out.extend([major | 24, len as u8]);
+    } else if len <= u64::from(u16::MAX) {
+        out.push(major | 25);
+        out.extend((len as u16).to_be_bytes());
+    } else if len <= u64::from(u32::MAX) {
+        out.push(major | 26);
+        out.extend((len as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend(len.to_be_bytes());
+    }
+}
+
+fn write_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xf6),
+        Value::Bool(false) => out.push(0xf4),
+        Value::Bool(true) => out.push(0xf5),
+        Value::Integer(i) if *i >= 0 => write_header(0, *i as u64, out),
+        Value::Integer(i) => write_header(1, (-1 - *i) as u64, out),
+        Value::Float(f) => {
+            out.push(0xfb);
+            out.extend(f.to_be_bytes());
+        }
+        Value::String(s) => {
+            write_header(3, s.len() as u64, out);
+            out.extend(s.as_bytes());
+        }
+        Value::Bytes(b) => {
+            write_header(2, b.len() as u64, out);
+            out.extend(b);
+        }
+        Value::List(items) => {
+            write_header(4, items.len() as u64, out);
+            for item in items {
+                write_value(item, out);
+            }
+        }
+        Value::Map(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+            write_header(5, entries.len() as u64, out);
+            for (key, value) in entries {
+                write_header(3, key.len() as u64, out);
+                out.extend(key.as_bytes());
+                write_value(value, out);
+            }
+        }
+        Value::Link(cid) => {
+            write_header(6, CID_TAG, out);
+            write_header(2, cid.as_bytes().len() as u64 + 1, out);
+            out.push(0);
+            out.extend(cid.as_bytes());
+        }
+    }
+}
+
+impl TryFrom<ciborium::Value> for Value {
+    type Error = Error;
+
+    fn try_from(value: ciborium::Value) -> Result<Self, Error> {
+        use ciborium::Value as Cbor;
+
+        Ok(match value {
+            Cbor::Null => Value::Null,
+            Cbor::Bool(b) => Value::Bool(b),
+            Cbor::Integer(i) => Value::Integer(
+                i64::try_from(i128::from(i))
+                    .map_err(|_| Error::DagCbor("integer out of range".to_string()))?,
+            ),
+            Cbor::Float(f) => Value::Float(f),
+            Cbor::Text(s) => Value::String(s),
+            Cbor::Bytes(b) => Value::Bytes(b),
+            Cbor::Array(items) => Value::List(
+                items
+                    .into_iter()
+                    .map(Value::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Cbor::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    let Cbor::Text(key) = key else {
+                        return Err(Error::DagCbor("map key is not a string".to_string()));
+                    };
+                    map.insert(key, Value::try_from(value)?);
+                }
+                Value::Map(map)
+            }
+            Cbor::Tag(CID_TAG, inner) => match *inner {
+                // Links carry the binary CID behind a zero byte (the identity multibase)
+                Cbor::Bytes(bytes) if bytes.first() == Some(&0) => {
+                    Value::Link(Cid::from_bytes(&bytes[1..])?)
+                }
+                _ => return Err(Error::DagCbor("malformed CID link".to_string())),
+            },
+            Cbor::Tag(tag, _) => return Err(Error::DagCbor(format!("unsupported tag {}", tag))),
+            other => return Err(Error::DagCbor(format!("unsupported value {:?}", other))),
+        })
+    }
+}
+
+impl Value {
+    /// Look up a key of a map.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_link(&self) -> Option<&Cid> {
+        match self {
+            Value::Link(cid) => Some(cid),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Convert to the JSON form records take in XRPC responses, where links
+    /// are `{"$link": cid}` and bytes `{"$bytes": base64}`.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+
+        match self {
+            Value::Null => Json::Null,
+            Value::Bool(b) => Json::Bool(*b),
+            Value::Integer(i) => Json::from(*i),
+            Value::Float(f) => serde_json::Number::from_f64(*f)
+                .map(Json::Number)
+                .unwrap_or(Json::Null),
+            Value::String(s) => Json::String(s.clone()),
+            Value::Bytes(b) => serde_json::json!({ "$bytes": base64_encode(b) }),
+            Value::List(items) => Json::Array(items.iter().map(Value::to_json).collect()),
+            Value::Map(map) => Json::Object(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.to_json()))
+                    .collect(),
+            ),
+            Value::Link(cid) => serde_json::json!({ "$link": cid.to_string() }),
+        }
+    }
+
+    /// Deserialize into a record type, through its JSON form.
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        Ok(serde_json::from_value(self.to_json())?)
+    }
+}
+
+/// Standard base64 without padding, as used for `$bytes`
+fn base64_encode(bytes: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut s = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (u32::from(b) << (16 - 8 * i)));
+        for i in 0..=chunk.len() {
+            s.push(CHARS[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode_cbor(value: &ciborium::Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        ciborium::into_writer(value, &mut out).unwrap();
+        out
+    }
+
+    fn link_bytes() -> Vec<u8> {
+        let mut bytes = vec![0x00, 0x01, 0x55, 0x12, 0x20];
+        bytes.extend([0u8; 32]);
+        bytes
+    }
+
+    #[test]
+    fn test_decode_record() {
+        use ciborium::Value as Cbor;
+
+        let record = Cbor::Map(vec![
+            (Cbor::Text("name".into()), Cbor::Text("Soup".into())),
+            (Cbor::Text("portions".into()), Cbor::Integer(2.into())),
+            (
+                Cbor::Text("image".into()),
+                Cbor::Tag(CID_TAG, Box::new(Cbor::Bytes(link_bytes()))),
+            ),
+            (Cbor::Text("data".into()), Cbor::Bytes(b"hi!?".to_vec())),
+        ]);
+        let value = decode(&encode_cbor(&record)).unwrap();
+
+        assert_eq!(value.get("name").and_then(Value::as_str), Some("Soup"));
+        assert_eq!(value.get("portions").and_then(Value::as_i64), Some(2));
+        assert_eq!(value.get("image").and_then(Value::as_link).unwrap().codec(), 0x55);
+
+        let json = value.to_json();
+        assert!(json["image"]["$link"].as_str().unwrap().starts_with("bafkrei"));
+        assert_eq!(json["data"]["$bytes"], "aGkhPw");
+    }
+
+    #[test]
+    fn test_read_consecutive_values() {
+        let mut bytes = encode_cbor(&ciborium::Value::Text("header".into()));
+        bytes.extend(encode_cbor(&ciborium::Value::Integer(7.into())));
+        let mut data = bytes.as_slice();
+
+        assert_eq!(read(&mut data).unwrap(), Value::String("header".into()));
+        assert_eq!(read(&mut data).unwrap(), Value::Integer(7));
+        assert!(data.is_empty());
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_encode_is_canonical() {
+        use ciborium::Value as Cbor;
+
+        // Keys in canonical order, with a link, a negative and a 16-bit integer
+        let original = encode_cbor(&Cbor::Map(vec![
+            (Cbor::Text("e".into()), Cbor::Integer((-3).into())),
+            (Cbor::Text("ab".into()), Cbor::Integer(1000.into())),
+            (
+                Cbor::Text("link".into()),
+                Cbor::Tag(CID_TAG, Box::new(Cbor::Bytes(link_bytes()))),
+            ),
+            (Cbor::Text("longer".into()), Cbor::Array(vec![Cbor::Null, Cbor::Bool(true)])),
+        ]));
+        assert_eq!(encode(&decode(&original).unwrap()), original);
+    }
+
+    #[test]
+    fn test_reject_non_string_keys() {
+        let map = ciborium::Value::Map(vec![(
+            ciborium::Value::Integer(1.into()),
+            ciborium::Value::Null,
+        )]);
+        assert!(decode(&encode_cbor(&map)).is_err());
+    }
+}
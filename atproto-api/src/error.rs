@@ -1,3 +1,5 @@
+use std::fmt;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -14,7 +16,7 @@ pub enum Error {
     #[error("XRPC error: {error} (status {status})")]
     Xrpc {
         status: u16,
-        error: String,
+        error: XrpcErrorCode,
         message: Option<String>,
     },
 
@@ -30,6 +32,48 @@ pub enum Error {
     #[error("Invalid handle: {0}")]
     InvalidHandle(String),
 
+    #[error("Invalid NSID: {0}")]
+    InvalidNsid(String),
+
+    #[error("Invalid record key: {0}")]
+    InvalidRecordKey(String),
+
+    #[error("Identity resolution failed: {0}")]
+    Identity(String),
+
+    #[error("DNS lookup failed: {0}")]
+    Dns(String),
+
+    #[error("Invalid CID: {0}")]
+    InvalidCid(String),
+
+    #[error("Invalid CAR file: {0}")]
+    Car(String),
+
+    #[error("Invalid DAG-CBOR: {0}")]
+    DagCbor(String),
+
+    #[error("Invalid public key: {0}")]
+    InvalidKey(String),
+
+    #[error("Invalid commit signature")]
+    InvalidSignature,
+
+    #[error("Invalid commit: {0}")]
+    InvalidCommit(String),
+
+    #[error("Invalid repo tree: {0}")]
+    InvalidMst(String),
+
+    #[error("Block {0} is missing from the CAR file")]
+    MissingBlock(String),
+
+    #[error("Block {0} does not match its CID")]
+    CidMismatch(String),
+
+    #[error("Response is larger than {0} bytes")]
+    ResponseTooLarge(usize),
+
     #[error("Session error: {0}")]
     Session(String),
 
@@ -37,8 +81,157 @@ pub enum Error {
     Internal(String),
 }
 
+impl Error {
+    /// The XRPC error code, if this error came back from the server.
+    pub fn xrpc_code(&self) -> Option<&XrpcErrorCode> {
+        match self {
+            Error::Xrpc { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Whether the same request may succeed if sent again later.
+    ///
+    /// True for network failures (including failed DNS lookups), 5xx
+    /// responses, rate limiting and DPoP nonce challenges. Auth and
+    /// validation failures are never retryable.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Http(e) => e.is_timeout() || e.is_connect(),
+            Error::Dns(_) => true,
+            Error::Xrpc { status, error, .. } => match error {
+                XrpcErrorCode::RateLimitExceeded | XrpcErrorCode::UseDpopNonce => true,
+                _ if error.is_auth() => false,
+                _ => *status == 429 || *status >= 500,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the request failed because the credentials are missing,
+    /// expired or rejected, so the user has to refresh or sign in again.
+    pub fn is_auth_error(&self) -> bool {
+        match self {
+            Error::Xrpc { status, error, .. } => {
+                error.is_auth() || (*status == 401 && *error != XrpcErrorCode::UseDpopNonce)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Error codes returned in the `error` field of an XRPC error response.
+///
+/// Codes this crate doesn't know about are kept verbatim in `Other`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XrpcErrorCode {
+    /// The requested record does not exist
+    RecordNotFound,
+    /// A `swapRecord`/`swapCommit` precondition didn't match
+    InvalidSwap,
+    /// The access token has expired and must be refreshed
+    ExpiredToken,
+    /// The access token was rejected
+    InvalidToken,
+    /// The request needs authentication
+    AuthRequired,
+    /// Too many requests; back off before retrying
+    RateLimitExceeded,
+    /// The uploaded blob exceeds the server's size limit
+    BlobTooLarge,
+    /// The request was malformed
+    InvalidRequest,
+    /// The server requires a fresh DPoP nonce
+    UseDpopNonce,
+    Other(String),
+}
+
+impl XrpcErrorCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            XrpcErrorCode::RecordNotFound => "RecordNotFound",
+            XrpcErrorCode::InvalidSwap => "InvalidSwap",
+            XrpcErrorCode::ExpiredToken => "ExpiredToken",
+            XrpcErrorCode::InvalidToken => "InvalidToken",
+            XrpcErrorCode::AuthRequired => "AuthRequired",
+            XrpcErrorCode::RateLimitExceeded => "RateLimitExceeded",
+            XrpcErrorCode::BlobTooLarge => "BlobTooLarge",
+            XrpcErrorCode::InvalidRequest => "InvalidRequest",
+            XrpcErrorCode::UseDpopNonce => "use_dpop_nonce",
+            XrpcErrorCode::Other(code) => code,
+        }
+    }
+
+    fn is_auth(&self) -> bool {
+        matches!(
+            self,
+            XrpcErrorCode::ExpiredToken | XrpcErrorCode::InvalidToken | XrpcErrorCode::AuthRequired
+        )
+    }
+}
+
+impl From<&str> for XrpcErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "RecordNotFound" => XrpcErrorCode::RecordNotFound,
+            "InvalidSwap" => XrpcErrorCode::InvalidSwap,
+            "ExpiredToken" => XrpcErrorCode::ExpiredToken,
+            "InvalidToken" => XrpcErrorCode::InvalidToken,
+            "AuthRequired" => XrpcErrorCode::AuthRequired,
+            "RateLimitExceeded" => XrpcErrorCode::RateLimitExceeded,
+            "BlobTooLarge" => XrpcErrorCode::BlobTooLarge,
+            "InvalidRequest" => XrpcErrorCode::InvalidRequest,
+            "use_dpop_nonce" => XrpcErrorCode::UseDpopNonce,
+            other => XrpcErrorCode::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for XrpcErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct XrpcErrorResponse {
     pub error: String,
     pub message: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xrpc(status: u16, code: &str) -> Error {
+        Error::Xrpc { status, error: code.into(), message: None }
+    }
+
+    #[test]
+    fn test_error_code_roundtrip() {
+        for code in ["RecordNotFound", "InvalidSwap", "BlobTooLarge", "use_dpop_nonce", "SomethingNew"] {
+            assert_eq!(XrpcErrorCode::from(code).as_str(), code);
+        }
+        assert_eq!(XrpcErrorCode::from("use_dpop_nonce"), XrpcErrorCode::UseDpopNonce);
+        assert_eq!(XrpcErrorCode::from("Nope"), XrpcErrorCode::Other("Nope".into()));
+    }
+
+    #[test]
+    fn test_retryable() {
+        assert!(xrpc(429, "RateLimitExceeded").is_retryable());
+        assert!(xrpc(502, "UpstreamFailure").is_retryable());
+        assert!(xrpc(401, "use_dpop_nonce").is_retryable());
+        assert!(!xrpc(400, "InvalidSwap").is_retryable());
+        assert!(!xrpc(400, "RecordNotFound").is_retryable());
+        assert!(!xrpc(401, "ExpiredToken").is_retryable());
+    }
+
+    #[test]
+    fn test_auth_error() {
+        assert!(xrpc(400, "ExpiredToken").is_auth_error());
+        assert!(xrpc(401, "InvalidToken").is_auth_error());
+        assert!(xrpc(401, "Unknown").is_auth_error());
+        assert!(!xrpc(401, "use_dpop_nonce").is_auth_error());
+        assert!(!xrpc(413, "BlobTooLarge").is_auth_error());
+    }
+}
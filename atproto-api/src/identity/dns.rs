@@ -0,0 +1,91 @@
+#[cfg(any(test, feature = "test-util"))]
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+use hickory_resolver::error::ResolveErrorKind;
+use hickory_resolver::TokioAsyncResolver;
+
+use crate::Error;
+
+/// Source of DNS TXT records for handle resolution.
+///
+/// Implement this to resolve through something other than the system
+/// resolver, e.g. DNS-over-HTTPS or a stub in tests.
+#[async_trait]
+pub trait TxtResolver: Send + Sync {
+    /// All TXT records for `name`, each joined into one string. A name
+    /// without records is `Ok(vec![])`, not an error; a lookup that failed
+    /// (timeout, SERVFAIL) should be `Error::Dns`.
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>, Error>;
+}
+
+/// TXT lookups through the system's DNS configuration.
+pub struct SystemTxtResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl SystemTxtResolver {
+    /// Uses /etc/resolv.conf, or public resolvers if it can't be read.
+    pub fn new() -> Self {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().unwrap_or_else(|e| {
+            tracing::warn!("Failed to read system DNS configuration, using defaults: {}", e);
+            TokioAsyncResolver::tokio(ResolverConfig::default(), ResolverOpts::default())
+        });
+        Self { resolver }
+    }
+}
+
+impl Default for SystemTxtResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl TxtResolver for SystemTxtResolver {
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>, Error> {
+        match self.resolver.txt_lookup(name).await {
+            Ok(lookup) => Ok(lookup
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|part| String::from_utf8_lossy(part))
+                        .collect::<String>()
+                })
+                .collect()),
+            Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(vec![]),
+            Err(e) => Err(Error::Dns(format!("TXT lookup for {} failed: {}", name, e))),
+        }
+    }
+}
+
+/// TXT records from a fixed table, for tests. Names not in the table have
+/// no records.
+#[cfg(any(test, feature = "test-util"))]
+pub struct StaticTxtResolver {
+    records: HashMap<String, Vec<String>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl StaticTxtResolver {
+    /// `records` lists `(name, values)` pairs; names are fully qualified,
+    /// e.g. `_atproto.alice.test.`
+    pub fn new(records: &[(&str, &[&str])]) -> Self {
+        Self {
+            records: records
+                .iter()
+                .map(|(name, values)| (name.to_string(), values.iter().map(|v| v.to_string()).collect()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+#[async_trait]
+impl TxtResolver for StaticTxtResolver {
+    async fn lookup_txt(&self, name: &str) -> Result<Vec<String>, Error> {
+        Ok(self.records.get(name).cloned().unwrap_or_default())
+    }
+}
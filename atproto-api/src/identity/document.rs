@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::PublicKey;
+use crate::types::{Did, Handle};
+use crate::Error;
+
+/// A DID document as served by the PLC directory or a `did:web` host.
+///
+/// Fields ATProto doesn't use (`@context`, `authentication`, ...) are
+/// dropped when parsing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidDocument {
+    pub id: Did,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verification_method: Vec<VerificationMethod>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub service: Vec<Service>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerificationMethod {
+    /// Either absolute (`did:plc:abc#atproto`) or relative (`#atproto`)
+    pub id: String,
+    #[serde(rename = "type")]
+    pub method_type: String,
+    pub controller: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key_multibase: Option<String>,
+}
+
+impl VerificationMethod {
+    /// Decode `publicKeyMultibase`.
+    pub fn public_key(&self) -> Result<PublicKey, Error> {
+        let multikey = self
+            .public_key_multibase
+            .as_deref()
+            .ok_or_else(|| Error::InvalidKey(format!("{} has no publicKeyMultibase", self.id)))?;
+        PublicKey::from_multikey(multikey)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Service {
+    /// Either absolute (`did:plc:abc#atproto_pds`) or relative (`#atproto_pds`)
+    pub id: String,
+    #[serde(rename = "type")]
+    pub service_type: String,
+    /// A URL for every service ATProto defines, but DID Core also allows
+    /// maps and lists here
+    pub service_endpoint: serde_json::Value,
+}
+
+impl Service {
+    /// The endpoint, if it is a plain URL.
+    pub fn endpoint(&self) -> Option<&str> {
+        self.service_endpoint.as_str()
+    }
+}
+
+impl DidDocument {
+    /// The first `at://` entry of `alsoKnownAs` that is a valid handle.
+    ///
+    /// This is only what the document claims; `IdentityResolver::resolve`
+    /// checks that the handle points back at the DID.
+    pub fn handle(&self) -> Option<Handle> {
+        self.also_known_as
+            .iter()
+            .filter_map(|aka| aka.strip_prefix("at://"))
+            .find_map(|handle| Handle::new(handle.to_ascii_lowercase()).ok())
+    }
+
+    /// The verification method with the given fragment, e.g. `atproto`.
+    pub fn verification_method(&self, fragment: &str) -> Option<&VerificationMethod> {
+        self.verification_method
+            .iter()
+            .find(|method| self.has_fragment(&method.id, fragment))
+    }
+
+    /// The service with the given fragment, e.g. `atproto_pds`.
+    pub fn service(&self, fragment: &str) -> Option<&Service> {
+        self.service
+            .iter()
+            .find(|service| self.has_fragment(&service.id, fragment))
+    }
+
+    /// Endpoint of the account's PDS (`#atproto_pds`), without trailing slash.
+    pub fn pds_endpoint(&self) -> Option<&str> {
+        self.service("atproto_pds")
+            .filter(|service| service.service_type == "AtprotoPersonalDataServer")
+            .and_then(Service::endpoint)
+            .map(|endpoint| endpoint.trim_end_matches('/'))
+    }
+
+    /// The key repo commits are signed with (`#atproto`).
+    pub fn signing_key(&self) -> Result<PublicKey, Error> {
+        self.verification_method("atproto")
+            .ok_or_else(|| Error::InvalidKey("DID document has no #atproto key".to_string()))?
+            .public_key()
+    }
+
+    fn has_fragment(&self, id: &str, fragment: &str) -> bool {
+        let relative = id.strip_prefix(self.id.as_str()).unwrap_or(id);
+        relative.strip_prefix('#') == Some(fragment)
+    }
+}
+
+/// A DID together with its document and verified handle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedIdentity {
+    pub did: Did,
+    /// The document's handle, if it resolves back to `did`. `None` when the
+    /// document claims no handle or the claim doesn't check out.
+    pub handle: Option<Handle>,
+    pub document: DidDocument,
+}
+
+impl ResolvedIdentity {
+    pub fn pds_endpoint(&self) -> Option<&str> {
+        self.document.pds_endpoint()
+    }
+
+    pub fn signing_key(&self) -> Result<PublicKey, Error> {
+        self.document.signing_key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> DidDocument {
+        serde_json::from_value(serde_json::json!({
+            "@context": ["https://www.w3.org/ns/did/v1"],
+            "id": "did:plc:abc123",
+            "alsoKnownAs": ["https://example.com", "at://Alice.Example.com"],
+            "verificationMethod": [
+                {
+                    "id": "did:plc:abc123#atproto",
+                    "type": "Multikey",
+                    "controller": "did:plc:abc123",
+                    "publicKeyMultibase": "zQ3shqwJEJyMBsBXCWyCBpUBMqxcon9oHB7mCvx4sSpMdLJwc",
+                },
+                {
+                    "id": "#backup",
+                    "type": "JsonWebKey2020",
+                    "controller": "did:plc:abc123",
+                    "publicKeyJwk": {"kty": "EC"},
+                },
+            ],
+            "service": [
+                {"id": "#atproto_labeler", "type": "AtprotoLabeler", "serviceEndpoint": "https://labeler.example.com"},
+                {"id": "#atproto_pds", "type": "AtprotoPersonalDataServer", "serviceEndpoint": "https://pds.example.com/"},
+                {"id": "#hub", "type": "Hub", "serviceEndpoint": {"origins": ["https://hub.example.com"]}},
+            ],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let doc = document();
+        assert_eq!(doc.handle().unwrap().as_str(), "alice.example.com");
+        assert_eq!(doc.pds_endpoint(), Some("https://pds.example.com"));
+        assert_eq!(doc.service("atproto_labeler").unwrap().endpoint(), Some("https://labeler.example.com"));
+        assert_eq!(doc.service("hub").unwrap().endpoint(), None);
+        assert!(matches!(doc.signing_key(), Ok(PublicKey::K256(_))));
+        assert!(doc.verification_method("backup").unwrap().public_key().is_err());
+        assert!(doc.verification_method("atproto_pds").is_none());
+    }
+
+    #[test]
+    fn test_minimal_document() {
+        let doc: DidDocument = serde_json::from_value(serde_json::json!({"id": "did:web:example.com"})).unwrap();
+        assert_eq!(doc.handle(), None);
+        assert_eq!(doc.pds_endpoint(), None);
+        assert!(doc.signing_key().is_err());
+        assert_eq!(serde_json::to_value(&doc).unwrap(), serde_json::json!({"id": "did:web:example.com"}));
+    }
+
+    #[test]
+    fn test_fragment_must_belong_to_document() {
+        let mut doc = document();
+        doc.service[1].id = "did:plc:other#atproto_pds".to_string();
+        assert_eq!(doc.pds_endpoint(), None);
+    }
+}
@@ -0,0 +1,13 @@
+//! Handle and DID resolution.
+
+mod dns;
+mod document;
+mod resolver;
+
+#[cfg(any(test, feature = "test-util"))]
+pub use dns::StaticTxtResolver;
+pub use dns::{SystemTxtResolver, TxtResolver};
+pub use document::{DidDocument, ResolvedIdentity, Service, VerificationMethod};
+pub use resolver::{
+    IdentityResolver, IdentityResolverBuilder, DEFAULT_HANDLE_FALLBACK, DEFAULT_PLC_DIRECTORY,
+};
@@ -0,0 +1,582 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+use url::{Host, Url};
+
+use super::dns::{SystemTxtResolver, TxtResolver};
+use super::document::{DidDocument, ResolvedIdentity};
+use crate::types::{Did, Handle};
+use crate::xrpc::{parse_error_response, RetryPolicy, Transport};
+use crate::Error;
+
+/// Public PLC directory used to resolve `did:plc` identifiers.
+pub const DEFAULT_PLC_DIRECTORY: &str = "https://plc.directory";
+
+/// Public AppView that answers `com.atproto.identity.resolveHandle`.
+pub const DEFAULT_HANDLE_FALLBACK: &str = "https://public.api.bsky.app";
+
+#[derive(Deserialize)]
+struct ResolveHandleOutput {
+    did: String,
+}
+
+/// Resolves handles to DIDs and DIDs to their documents.
+///
+/// Handles are looked up through the `_atproto.{handle}` TXT record, then
+/// `https://{handle}/.well-known/atproto-did`, then optionally through an
+/// AppView's `com.atproto.identity.resolveHandle`. `did:plc` documents come
+/// from a PLC directory, `did:web` documents from the host named in the DID.
+/// `did:web` hosts on loopback or private addresses are refused unless the
+/// builder allows them, since anyone can publish such a DID.
+///
+/// Nothing is cached; wrap the resolver if lookups are frequent.
+///
+/// # Example
+///
+/// ```ignore
+/// use atproto_api::identity::IdentityResolver;
+///
+/// let resolver = IdentityResolver::new();
+/// let identity = resolver.resolve("alice.bsky.social").await?;
+/// println!("{} lives on {:?}", identity.did, identity.pds_endpoint());
+/// ```
+pub struct IdentityResolver {
+    http: Transport,
+    dns: Arc<dyn TxtResolver>,
+    plc_directory: String,
+    handle_fallback: Option<String>,
+    allow_local_did_web: bool,
+}
+
+impl IdentityResolver {
+    /// Resolve through system DNS and the public PLC directory, without an
+    /// AppView fallback for handles.
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> IdentityResolverBuilder {
+        IdentityResolverBuilder {
+            http: None,
+            retry: RetryPolicy::default(),
+            timeout: None,
+            dns: None,
+            plc_directory: DEFAULT_PLC_DIRECTORY.to_string(),
+            handle_fallback: None,
+            allow_local_did_web: false,
+        }
+    }
+
+    /// Resolve a handle or DID, verifying that the handle and the DID
+    /// document agree with each other.
+    pub async fn resolve(&self, actor: &str) -> Result<ResolvedIdentity, Error> {
+        if actor.starts_with("did:") {
+            let document = self.resolve_did(actor).await?;
+            let handle = self.verified_handle(&document).await;
+            return Ok(ResolvedIdentity {
+                did: document.id.clone(),
+                handle,
+                document,
+            });
+        }
+
+        let handle = Handle::new(actor.to_ascii_lowercase())?;
+        let did = self.resolve_handle(handle.as_str()).await?;
+        let document = self.resolve_did(did.as_str()).await?;
+        let handle = (document.handle().as_ref() == Some(&handle)).then_some(handle);
+        Ok(ResolvedIdentity { did, handle, document })
+    }
+
+    /// Resolve a handle to a DID.
+    ///
+    /// DNS and HTTPS failures fall through to the next method; only the
+    /// last method's error is returned. Without a fallback, a failed DNS
+    /// lookup is returned as `Error::Dns` rather than "did not resolve", so
+    /// callers can tell an outage from a handle that doesn't exist.
+    pub async fn resolve_handle(&self, handle: &str) -> Result<Did, Error> {
+        let handle = Handle::new(handle.to_ascii_lowercase())?;
+
+        let mut dns_error = None;
+        match self.resolve_handle_dns(&handle).await {
+            Ok(Some(did)) => return Ok(did),
+            Ok(None) => {}
+            Err(e) => {
+                tracing::debug!("DNS handle lookup for {} failed: {}", handle, e);
+                dns_error = Some(e);
+            }
+        }
+        match self.resolve_handle_https(&handle).await {
+            Ok(Some(did)) => return Ok(did),
+            Ok(None) => {}
+            Err(e) => tracing::debug!("HTTPS handle lookup for {} failed: {}", handle, e),
+        }
+
+        let Some(fallback) = &self.handle_fallback else {
+            return Err(dns_error.unwrap_or_else(|| {
+                Error::Identity(format!("handle {} did not resolve via DNS or HTTPS", handle))
+            }));
+        };
+        let mut url = Url::parse(&format!(
+            "{}/xrpc/com.atproto.identity.resolveHandle",
+            fallback.trim_end_matches('/')
+        ))?;
+        url.query_pairs_mut().append_pair("handle", handle.as_str());
+        let resp = self.http.get(url).await?;
+        if !resp.status().is_success() {
+            return Err(parse_error_response(resp).await);
+        }
+        let output: ResolveHandleOutput = resp.json().await?;
+        Did::new(output.did)
+    }
+
+    /// Fetch the document of a `did:plc` or `did:web` identifier.
+    pub async fn resolve_did(&self, did: &str) -> Result<DidDocument, Error> {
+        let did = Did::new(did)?;
+        let url = if did.as_str().starts_with("did:plc:") {
+            Url::parse(&format!("{}/{}", self.plc_directory.trim_end_matches('/'), did))?
+        } else if did.as_str().starts_with("did:web:") {
+            self.check_did_web_host(did_web_url(&did)?).await?
+        } else {
+            return Err(Error::Identity(format!("unsupported DID method: {}", did)));
+        };
+
+        let resp = self.http.get(url).await?;
+        let status = resp.status();
+        if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            // Kept as an XRPC error so `is_retryable` reports the outage
+            return Err(parse_error_response(resp).await);
+        }
+        if !status.is_success() {
+            return Err(Error::Identity(format!(
+                "DID document fetch for {} failed: {}",
+                did, status
+            )));
+        }
+        let document: DidDocument = resp.json().await?;
+        if document.id != did {
+            return Err(Error::Identity(format!(
+                "DID document for {} is for {}",
+                did, document.id
+            )));
+        }
+        Ok(document)
+    }
+
+    /// Refuse `did:web` hosts that are, or resolve to, loopback or private
+    /// addresses, unless `allow_local_did_web` is set. Allowed loopback hosts
+    /// are fetched over plain HTTP.
+    async fn check_did_web_host(&self, mut url: Url) -> Result<Url, Error> {
+        let local = match url.host() {
+            Some(Host::Domain(domain)) if domain == "localhost" || domain.ends_with(".localhost") => true,
+            Some(Host::Domain(domain)) => {
+                let port = url.port_or_known_default().unwrap_or(443);
+                let addrs = tokio::net::lookup_host((domain, port))
+                    .await
+                    .map_err(|e| Error::Identity(format!("failed to resolve {}: {}", domain, e)))?;
+                addrs.into_iter().any(|addr| is_local_ip(addr.ip()))
+            }
+            Some(Host::Ipv4(ip)) => is_local_ip(IpAddr::V4(ip)),
+            Some(Host::Ipv6(ip)) => is_local_ip(IpAddr::V6(ip)),
+            None => true,
+        };
+        if !local {
+            return Ok(url);
+        }
+        if !self.allow_local_did_web {
+            return Err(Error::Identity(format!("did:web host {} is not a public address", url)));
+        }
+        let loopback = match url.host() {
+            Some(Host::Domain(domain)) => domain == "localhost",
+            Some(Host::Ipv4(ip)) => ip.is_loopback(),
+            Some(Host::Ipv6(ip)) => ip.is_loopback(),
+            None => false,
+        };
+        if loopback {
+            url.set_scheme("http").map_err(|_| Error::Identity(format!("invalid did:web URL {}", url)))?;
+        }
+        Ok(url)
+    }
+
+    /// Look up the `did=` value of the `_atproto.{handle}` TXT record.
+    async fn resolve_handle_dns(&self, handle: &Handle) -> Result<Option<Did>, Error> {
+        let records = self.dns.lookup_txt(&format!("_atproto.{}.", handle)).await?;
+        let dids: Vec<&str> = records
+            .iter()
+            .filter_map(|value| value.strip_prefix("did="))
+            .map(str::trim)
+            .collect();
+        // More than one DID is ambiguous and treated as no record at all
+        match dids.as_slice() {
+            [did] => Ok(Did::new(*did).ok()),
+            _ => Ok(None),
+        }
+    }
+
+    async fn resolve_handle_https(&self, handle: &Handle) -> Result<Option<Did>, Error> {
+        // A single attempt: most handles have no HTTPS record, and failing
+        // here just moves on to the fallback
+        let url = Url::parse(&format!("https://{}/.well-known/atproto-did", handle))?;
+        let resp = self.http.get_once(url).await?;
+        if !resp.status().is_success() {
+            return Ok(None);
+        }
+        Ok(Did::new(resp.text().await?.trim()).ok())
+    }
+
+    /// The document's handle, if it resolves back to the document's DID.
+    async fn verified_handle(&self, document: &DidDocument) -> Option<Handle> {
+        let claimed = document.handle()?;
+        match self.resolve_handle(claimed.as_str()).await {
+            Ok(did) if did == document.id => Some(claimed),
+            Ok(did) => {
+                tracing::warn!("{} claims handle {}, which belongs to {}", document.id, claimed, did);
+                None
+            }
+            Err(e) => {
+                tracing::debug!("Handle {} of {} does not resolve: {}", claimed, document.id, e);
+                None
+            }
+        }
+    }
+}
+
+impl Default for IdentityResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builder for an `IdentityResolver` with non-default sources.
+///
+/// ```ignore
+/// let resolver = IdentityResolver::builder()
+///     .plc_directory("https://plc.example.com")
+///     .handle_fallback(DEFAULT_HANDLE_FALLBACK)
+///     .build();
+/// ```
+pub struct IdentityResolverBuilder {
+    http: Option<Client>,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    dns: Option<Arc<dyn TxtResolver>>,
+    plc_directory: String,
+    handle_fallback: Option<String>,
+    allow_local_did_web: bool,
+}
+
+impl IdentityResolverBuilder {
+    /// Send requests through this client instead of a fresh one.
+    pub fn http_client(mut self, http: Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Retry DID document and handle lookups under this policy instead of
+    /// the default one.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Give up on a single attempt after this long.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Look up TXT records here instead of through the system resolver.
+    pub fn dns(mut self, dns: Arc<dyn TxtResolver>) -> Self {
+        self.dns = Some(dns);
+        self
+    }
+
+    /// Fetch `did:plc` documents from this directory.
+    pub fn plc_directory(mut self, url: impl Into<String>) -> Self {
+        self.plc_directory = url.into();
+        self
+    }
+
+    /// Ask this AppView to resolve handles that don't resolve via DNS or
+    /// HTTPS. The AppView is trusted to answer honestly.
+    pub fn handle_fallback(mut self, url: impl Into<String>) -> Self {
+        self.handle_fallback = Some(url.into());
+        self
+    }
+
+    /// Resolve `did:web` DIDs hosted on loopback and private addresses,
+    /// fetching loopback ones over plain HTTP. For tests and local
+    /// development only: with untrusted DIDs this lets anyone make the
+    /// resolver send requests into the local network.
+    pub fn allow_local_did_web(mut self, allow: bool) -> Self {
+        self.allow_local_did_web = allow;
+        self
+    }
+
+    pub fn build(self) -> IdentityResolver {
+        IdentityResolver {
+            http: Transport::new(self.http.unwrap_or_default(), self.retry, self.timeout),
+            dns: self.dns.unwrap_or_else(|| Arc::new(SystemTxtResolver::new())),
+            plc_directory: self.plc_directory,
+            handle_fallback: self.handle_fallback,
+            allow_local_did_web: self.allow_local_did_web,
+        }
+    }
+}
+
+/// Where a `did:web` document is served.
+///
+/// `did:web:example.com` maps to `https://example.com/.well-known/did.json`,
+/// `did:web:example.com%3A8443:users:alice` to
+/// `https://example.com:8443/users/alice/did.json`.
+pub(crate) fn did_web_url(did: &Did) -> Result<Url, Error> {
+    let invalid = || Error::InvalidDid(did.to_string());
+    let id = did.as_str().strip_prefix("did:web:").ok_or_else(invalid)?;
+    let mut segments = id.split(':');
+    // The only escape allowed in the host is the colon before a port
+    let host = segments
+        .next()
+        .unwrap_or_default()
+        .replace("%3A", ":")
+        .replace("%3a", ":");
+    let path: Vec<&str> = segments.collect();
+    if host.is_empty()
+        || host.contains(['%', '/', '?', '#', '@'])
+        || path.iter().any(|segment| segment.is_empty() || segment.contains(['/', '?', '#']))
+    {
+        return Err(invalid());
+    }
+
+    let path = if path.is_empty() {
+        "/.well-known/did.json".to_string()
+    } else {
+        format!("/{}/did.json", path.join("/"))
+    };
+    let url = Url::parse(&format!("https://{}{}", host, path)).map_err(|_| invalid())?;
+    if url.host().is_none() {
+        return Err(invalid());
+    }
+    Ok(url)
+}
+
+/// Loopback, private, link-local and other addresses that aren't reachable
+/// on the public internet.
+fn is_local_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_local_ipv4(ip),
+        IpAddr::V6(ip) => is_local_ipv6(ip),
+    }
+}
+
+fn is_local_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        // "This network" 0.0.0.0/8 and carrier-grade NAT 100.64.0.0/10
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+}
+
+fn is_local_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || ip.to_ipv4_mapped().is_some_and(is_local_ipv4)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::identity::StaticTxtResolver;
+    use crate::test_server::{self, Response};
+
+    fn stub(records: &[(&str, &[&str])]) -> Arc<dyn TxtResolver> {
+        Arc::new(StaticTxtResolver::new(records))
+    }
+
+    /// A PLC directory and AppView in one. Mallory's document claims
+    /// Alice's handle.
+    fn spawn_directory() -> String {
+        test_server::spawn(|request| {
+            if request.path() == "/xrpc/com.atproto.identity.resolveHandle" {
+                return match request.query("handle").as_deref() {
+                    Some("bob.test") => Response::json(200, serde_json::json!({"did": "did:plc:bob"})),
+                    _ => Response::error(400, "InvalidRequest"),
+                };
+            }
+            let did = request.path().trim_start_matches('/');
+            let handle = match did {
+                "did:plc:alice" | "did:plc:mallory" => "alice.test",
+                "did:plc:bob" => "bob.test",
+                "did:plc:imposter" => return Response::json(200, serde_json::json!({"id": "did:plc:alice"})),
+                "did:plc:flaky" => return Response::error(503, "Unavailable"),
+                _ => return Response::error(404, "NotFound"),
+            };
+            Response::json(200, serde_json::json!({
+                "id": did,
+                "alsoKnownAs": [format!("at://{}", handle)],
+                "service": [{
+                    "id": "#atproto_pds",
+                    "type": "AtprotoPersonalDataServer",
+                    "serviceEndpoint": "https://pds.example.com",
+                }],
+            }))
+        })
+    }
+
+    fn resolver(directory: &str) -> IdentityResolver {
+        IdentityResolver::builder()
+            .dns(stub(&[
+                ("_atproto.alice.test.", &["did=did:plc:alice"]),
+                ("_atproto.carol.test.", &["did=did:plc:carol", "did=did:plc:mallory"]),
+            ]))
+            .plc_directory(directory)
+            .retry_policy(RetryPolicy::none())
+            .build()
+    }
+
+    #[test]
+    fn test_did_web_url() {
+        let url = |did: &str| did_web_url(&Did::new(did).unwrap()).map(String::from);
+        assert_eq!(url("did:web:example.com").unwrap(), "https://example.com/.well-known/did.json");
+        assert_eq!(url("did:web:example.com%3A8443").unwrap(), "https://example.com:8443/.well-known/did.json");
+        assert_eq!(url("did:web:example.com:users:alice").unwrap(), "https://example.com/users/alice/did.json");
+        assert_eq!(url("did:web:localhost%3A3000").unwrap(), "https://localhost:3000/.well-known/did.json");
+        assert_eq!(url("did:web:127.0.0.1%3a3000:u").unwrap(), "https://127.0.0.1:3000/u/did.json");
+        assert!(url("did:web:example.com%2Fevil").is_err());
+        assert!(url("did:web:user@example.com").is_err());
+        assert!(url("did:web:example.com::alice").is_err());
+        assert!(url("did:web:example.com%3Anotaport").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_handle_from_txt_record() {
+        let resolver = resolver("http://127.0.0.1:9");
+        assert_eq!(resolver.resolve_handle("Alice.Test").await.unwrap().as_str(), "did:plc:alice");
+    }
+
+    #[tokio::test]
+    async fn test_failed_dns_lookup_is_retryable() {
+        struct FailingDns;
+
+        #[async_trait::async_trait]
+        impl TxtResolver for FailingDns {
+            async fn lookup_txt(&self, name: &str) -> Result<Vec<String>, Error> {
+                Err(Error::Dns(format!("SERVFAIL for {}", name)))
+            }
+        }
+
+        let resolver = IdentityResolver::builder().dns(Arc::new(FailingDns)).build();
+        assert!(resolver.resolve_handle("alice.invalid").await.unwrap_err().is_retryable());
+        let resolver = IdentityResolver::builder().dns(stub(&[])).build();
+        assert!(!resolver.resolve_handle("alice.invalid").await.unwrap_err().is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_ambiguous_txt_records_are_ignored() {
+        let resolver = resolver("http://127.0.0.1:9");
+        assert!(resolver.resolve_handle("carol.test").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_handle_through_fallback() {
+        let directory = spawn_directory();
+        let resolver = IdentityResolver::builder()
+            .dns(stub(&[]))
+            .handle_fallback(&directory)
+            .build();
+        assert_eq!(resolver.resolve_handle("bob.test").await.unwrap().as_str(), "did:plc:bob");
+        let err = resolver.resolve_handle("nobody.test").await.unwrap_err();
+        assert_eq!(err.xrpc_code(), Some(&crate::XrpcErrorCode::InvalidRequest));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_did_plc() {
+        let resolver = resolver(&spawn_directory());
+        let document = resolver.resolve_did("did:plc:bob").await.unwrap();
+        assert_eq!(document.pds_endpoint(), Some("https://pds.example.com"));
+        assert!(!resolver.resolve_did("did:plc:nobody").await.unwrap_err().is_retryable());
+        assert!(resolver.resolve_did("did:plc:flaky").await.unwrap_err().is_retryable());
+        assert!(resolver.resolve_did("did:plc:imposter").await.is_err());
+        assert!(resolver.resolve_did("did:key:zQ3sh").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_did_web() {
+        let host = test_server::spawn(|request| match request.path() {
+            "/users/alice/did.json" => Response::json(200, serde_json::json!({
+                "id": request.header("host").map(|host| format!("did:web:{}:users:alice", host.replace(':', "%3A"))),
+            })),
+            _ => Response::error(404, "NotFound"),
+        });
+        let did = format!("did:web:{}:users:alice", host.trim_start_matches("http://").replace(':', "%3A"));
+        let resolver = IdentityResolver::builder().dns(stub(&[])).allow_local_did_web(true).build();
+        let document = resolver.resolve_did(&did).await.unwrap();
+        assert_eq!(document.id.as_str(), did);
+    }
+
+    #[tokio::test]
+    async fn test_local_did_web_is_refused_by_default() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        let host = test_server::spawn(move |_| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            Response::error(404, "NotFound")
+        });
+        let resolver = resolver("http://127.0.0.1:9");
+        for did in [
+            format!("did:web:{}", host.trim_start_matches("http://").replace(':', "%3A")),
+            "did:web:localhost%3A6379".to_string(),
+            "did:web:redis.localhost".to_string(),
+            "did:web:10.0.0.1".to_string(),
+            "did:web:192.168.1.1%3A8080:admin".to_string(),
+            "did:web:169.254.169.254".to_string(),
+        ] {
+            let err = resolver.resolve_did(&did).await.unwrap_err();
+            assert!(matches!(err, Error::Identity(_)), "{}: {}", did, err);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_local_addresses() {
+        let local = [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.0.1", "169.254.169.254",
+            "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+        ];
+        for ip in local {
+            assert!(is_local_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "172.32.0.1", "100.128.0.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(!is_local_ip(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_verifies_handle() {
+        let resolver = resolver(&spawn_directory());
+
+        let alice = resolver.resolve("did:plc:alice").await.unwrap();
+        assert_eq!(alice.pds_endpoint(), Some("https://pds.example.com"));
+        assert_eq!(alice.handle.unwrap().as_str(), "alice.test");
+
+        let mallory = resolver.resolve("did:plc:mallory").await.unwrap();
+        assert_eq!(mallory.did.as_str(), "did:plc:mallory");
+        assert_eq!(mallory.handle, None);
+
+        let by_handle = resolver.resolve("ALICE.test").await.unwrap();
+        assert_eq!(by_handle.did.as_str(), "did:plc:alice");
+        assert_eq!(by_handle.handle.unwrap().as_str(), "alice.test");
+    }
+}
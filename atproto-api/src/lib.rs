@@ -3,13 +3,14 @@
 //! A Rust crate for reading and writing records to ATProto repositories.
 //!
 //! This crate does not bundle OAuth - it accepts credentials via a `Session` trait.
-//! Implement `Session` in your OAuth crate, or use `BearerSession` for simple
-//! bearer token authentication (app passwords, testing).
+//! Implement `Session` in your OAuth crate, use `PasswordSession` to log in
+//! with an app password, or `BearerSession` for a fixed bearer token (testing).
+//! `IdentityResolver` turns handles and DIDs into DID documents.
 //!
 //! # Example
 //!
-//! ```ignore
-//! use atproto_api::{Agent, BearerSession, Tid};
+//! ```no_run
+//! use atproto_api::{Agent, PasswordSession, Tid};
 //! use serde::{Deserialize, Serialize};
 //!
 //! #[derive(Serialize, Deserialize)]
@@ -21,12 +22,12 @@
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     // Create a session (your OAuth implementation would provide this)
-//!     let session = BearerSession::new(
-//!         "did:plc:abc123",
+//!     // Log in with an app password (your OAuth `Session` would go here instead)
+//!     let session = PasswordSession::login(
 //!         "https://bsky.social",
-//!         "your-access-token",
-//!     );
+//!         "alice.example.com",
+//!         "app-password",
+//!     ).await?;
 //!
 //!     // Create agent
 //!     let agent = Agent::new(session);
@@ -46,20 +47,44 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! `agent.sync()` reads repos and blobs, and `Agent::builder` sets the
+//! `RetryPolicy` and timeout. See the README for more examples.
 
 mod agent;
+pub mod car;
+mod collection;
+pub mod crypto;
+pub mod dag_cbor;
 mod error;
+pub mod identity;
+pub mod mst;
 pub mod repo;
 mod session;
+pub mod sync;
 pub mod types;
+#[cfg(test)]
+mod test_server;
+#[cfg(doctest)]
+#[doc = include_str!("../README.md")]
+struct ReadmeDoctests;
+mod varint;
 mod xrpc;
 
-pub use agent::Agent;
-pub use error::Error;
-pub use session::{BearerSession, Session};
-pub use types::{AtUri, BlobRef, Did, Handle, Tid};
+pub use agent::{Agent, AgentBuilder};
+pub use error::{Error, XrpcErrorCode};
+pub use session::{AnonymousSession, BearerSession, PasswordSession, PasswordSessionData, Session};
+pub use car::Car;
+pub use collection::Collection;
+pub use crypto::PublicKey;
+pub use identity::{DidDocument, IdentityResolver, ResolvedIdentity};
+pub use mst::{verify_record, verify_repo, VerifiedRepo};
+pub use xrpc::RetryPolicy;
+pub use types::{AtUri, BlobRef, Cid, Did, Handle, Nsid, RecordKey, Tid};
 
 // Re-export repo types at top level for convenience
 pub use repo::{
-    CreateRecordOutput, GetRecordOutput, ListRecordsOutput, ListRecordsRecord, PutRecordOutput,
+    ApplyWritesOutput, CreateRecordOutput, GetRecordOutput, ListRecordsOptions, ListRecordsOutput,
+    ListRecordsRecord, PutRecordOptions, PutRecordOutput, Write, WriteResult,
 };
+pub use sync::{ListBlobsOutput, ListReposByCollectionOutput};
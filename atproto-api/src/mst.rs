@@ -0,0 +1,668 @@
+//! Verification of signed repos: the commit signature, and the Merkle Search
+//! Tree (MST) that maps `collection/rkey` paths to record CIDs.
+
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+
+use crate::car::Car;
+use crate::crypto::PublicKey;
+use crate::dag_cbor::{self, Value};
+use crate::types::Cid;
+use crate::Error;
+
+/// A signed repo commit.
+#[derive(Debug, Clone)]
+pub struct Commit {
+    pub did: String,
+    pub version: i64,
+    /// Root of the MST
+    pub data: Cid,
+    pub rev: String,
+    pub prev: Option<Cid>,
+    pub sig: Vec<u8>,
+}
+
+impl Commit {
+    fn from_value(value: &Value) -> Result<Self, Error> {
+        let field = |name: &str| {
+            value
+                .get(name)
+                .ok_or_else(|| Error::InvalidCommit(format!("missing {}", name)))
+        };
+        let invalid = |name: &str| Error::InvalidCommit(format!("invalid {}", name));
+
+        Ok(Self {
+            did: field("did")?.as_str().ok_or_else(|| invalid("did"))?.to_string(),
+            version: field("version")?.as_i64().ok_or_else(|| invalid("version"))?,
+            data: field("data")?.as_link().ok_or_else(|| invalid("data"))?.clone(),
+            rev: field("rev")?.as_str().ok_or_else(|| invalid("rev"))?.to_string(),
+            prev: match value.get("prev") {
+                None | Some(Value::Null) => None,
+                Some(prev) => Some(prev.as_link().ok_or_else(|| invalid("prev"))?.clone()),
+            },
+            sig: field("sig")?.as_bytes().ok_or_else(|| invalid("sig"))?.to_vec(),
+        })
+    }
+}
+
+/// A repo whose commit signature and tree have been checked. Every record
+/// listed here is present in the CAR and matches its CID.
+#[derive(Debug, Clone)]
+pub struct VerifiedRepo {
+    commit: Commit,
+    records: BTreeMap<String, Cid>,
+    car: Car,
+}
+
+impl VerifiedRepo {
+    pub fn commit(&self) -> &Commit {
+        &self.commit
+    }
+
+    /// All records as `(collection/rkey, cid)`, in key order.
+    pub fn records(&self) -> impl Iterator<Item = (&str, &Cid)> {
+        self.records.iter().map(|(key, cid)| (key.as_str(), cid))
+    }
+
+    /// Records of one collection as `(rkey, record)`.
+    pub fn collection<'a>(
+        &'a self,
+        collection: &'a str,
+    ) -> impl Iterator<Item = Result<(&'a str, Value), Error>> + 'a {
+        let prefix = format!("{}/", collection);
+        self.records
+            .range(prefix.clone()..)
+            .take_while(move |(key, _)| key.starts_with(&prefix))
+            .map(move |(key, cid)| Ok((&key[collection.len() + 1..], self.car.decode(cid)?)))
+    }
+
+    /// Look up and decode one record.
+    pub fn get(&self, collection: &str, rkey: &str) -> Result<Option<Value>, Error> {
+        match self.records.get(&format!("{}/{}", collection, rkey)) {
+            Some(cid) => Ok(Some(self.car.decode(cid)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn car(&self) -> &Car {
+        &self.car
+    }
+}
+
+/// Verify a full repo export, e.g. from `com.atproto.sync.getRepo`.
+///
+/// Checks that the root commit belongs to `did` and is signed by `key`, then
+/// walks the whole tree, checking every node and record against its CID and
+/// the tree's key ordering and layering.
+pub fn verify_repo(car: Car, did: &str, key: &PublicKey) -> Result<VerifiedRepo, Error> {
+    let commit = verify_commit(&car, did, key)?;
+    let mut walk = Walk {
+        car: &car,
+        records: BTreeMap::new(),
+    };
+    walk.node(&commit.data, None)?;
+    let records = walk.records;
+    Ok(VerifiedRepo {
+        commit,
+        records,
+        car,
+    })
+}
+
+/// Verify a record proof, e.g. from `com.atproto.sync.getRecord`.
+///
+/// Only the path from the commit to the record needs to be in the CAR.
+/// Returns `None` if the signed tree proves the record doesn't exist.
+pub fn verify_record(
+    car: &Car,
+    did: &str,
+    key: &PublicKey,
+    collection: &str,
+    rkey: &str,
+) -> Result<Option<Value>, Error> {
+    let commit = verify_commit(car, did, key)?;
+    let target = format!("{}/{}", collection, rkey);
+
+    let mut cid = commit.data;
+    loop {
+        let node = Node::decode(checked_block(car, &cid)?)?;
+        let keys = node.keys()?;
+        // The subtree to descend into is the one left of the first larger key
+        let position = keys.iter().position(|k| k.as_str() >= target.as_str());
+        if let Some(i) = position {
+            if keys[i] == target {
+                let record = &node.entries[i].value;
+                return Ok(Some(dag_cbor::decode(checked_block(car, record)?)?));
+            }
+        }
+        let subtree = match position {
+            Some(0) => node.left.as_ref(),
+            Some(i) => node.entries[i - 1].tree.as_ref(),
+            None => node.entries.last().map_or(node.left.as_ref(), |e| e.tree.as_ref()),
+        };
+        match subtree {
+            Some(next) => cid = next.clone(),
+            None => return Ok(None),
+        }
+    }
+}
+
+/// Decode the root commit and check its signature.
+fn verify_commit(car: &Car, did: &str, key: &PublicKey) -> Result<Commit, Error> {
+    let root = car
+        .root()
+        .ok_or_else(|| Error::InvalidCommit("CAR has no root".to_string()))?;
+    let value = dag_cbor::decode(checked_block(car, root)?)?;
+    let commit = Commit::from_value(&value)?;
+    if commit.did != did {
+        return Err(Error::InvalidCommit(format!(
+            "commit is for {}, expected {}",
+            commit.did, did
+        )));
+    }
+    if !matches!(commit.version, 2 | 3) {
+        return Err(Error::InvalidCommit(format!(
+            "unsupported version {}",
+            commit.version
+        )));
+    }
+
+    // The signature covers the commit encoded without its `sig` field
+    let Value::Map(mut unsigned) = value else {
+        return Err(Error::InvalidCommit("commit is not a map".to_string()));
+    };
+    unsigned.remove("sig");
+    key.verify(&dag_cbor::encode(&Value::Map(unsigned)), &commit.sig)?;
+    Ok(commit)
+}
+
+/// Fetch a block and check that it hashes to its CID.
+fn checked_block<'a>(car: &'a Car, cid: &Cid) -> Result<&'a [u8], Error> {
+    let block = car
+        .get(cid)
+        .ok_or_else(|| Error::MissingBlock(cid.to_string()))?;
+    if !cid.verify(block) {
+        return Err(Error::CidMismatch(cid.to_string()));
+    }
+    Ok(block)
+}
+
+/// Layer of a key in the tree: the number of leading zero bit pairs in its
+/// SHA-256 hash, which gives a fanout of 4.
+fn key_layer(key: &str) -> u32 {
+    let mut zeros = 0;
+    for &byte in Sha256::digest(key.as_bytes()).iter() {
+        if byte < 64 {
+            zeros += 1;
+        }
+        if byte < 16 {
+            zeros += 1;
+        }
+        if byte < 4 {
+            zeros += 1;
+        }
+        if byte != 0 {
+            break;
+        }
+        zeros += 1;
+    }
+    zeros
+}
+
+/// An MST node: a left subtree, then entries each followed by the subtree
+/// of keys between it and the next entry.
+struct Node {
+    left: Option<Cid>,
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    /// Bytes shared with the previous entry's key
+    prefix_len: usize,
+    key_suffix: Vec<u8>,
+    value: Cid,
+    tree: Option<Cid>,
+}
+
+impl Node {
+    fn decode(block: &[u8]) -> Result<Self, Error> {
+        let value = dag_cbor::decode(block)?;
+        let invalid = |what: &str| Error::InvalidMst(format!("invalid {}", what));
+        let link = |value: Option<&Value>, what: &str| match value {
+            None | Some(Value::Null) => Ok(None),
+            Some(v) => v.as_link().cloned().map(Some).ok_or_else(|| invalid(what)),
+        };
+
+        let entries = value
+            .get("e")
+            .and_then(Value::as_list)
+            .ok_or_else(|| invalid("node entries"))?
+            .iter()
+            .map(|entry| {
+                Ok(Entry {
+                    prefix_len: entry
+                        .get("p")
+                        .and_then(Value::as_i64)
+                        .and_then(|p| usize::try_from(p).ok())
+                        .ok_or_else(|| invalid("entry prefix"))?,
+                    key_suffix: entry
+                        .get("k")
+                        .and_then(Value::as_bytes)
+                        .ok_or_else(|| invalid("entry key"))?
+                        .to_vec(),
+                    value: link(entry.get("v"), "entry value")?.ok_or_else(|| invalid("entry value"))?,
+                    tree: link(entry.get("t"), "entry subtree")?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self {
+            left: link(value.get("l"), "left subtree")?,
+            entries,
+        })
+    }
+
+    /// Full keys of the entries, undoing the prefix compression.
+    fn keys(&self) -> Result<Vec<String>, Error> {
+        let mut keys: Vec<String> = Vec::with_capacity(self.entries.len());
+        let mut previous: &[u8] = &[];
+        for entry in &self.entries {
+            if entry.prefix_len > previous.len() {
+                return Err(Error::InvalidMst("key prefix longer than previous key".to_string()));
+            }
+            let mut key = previous[..entry.prefix_len].to_vec();
+            key.extend_from_slice(&entry.key_suffix);
+            let key = String::from_utf8(key)
+                .map_err(|_| Error::InvalidMst("key is not UTF-8".to_string()))?;
+            if !key.split_once('/').is_some_and(|(c, r)| !c.is_empty() && !r.is_empty()) {
+                return Err(Error::InvalidMst(format!("malformed key {}", key)));
+            }
+            keys.push(key);
+            previous = keys.last().unwrap().as_bytes();
+        }
+        Ok(keys)
+    }
+}
+
+/// In-order walk of the whole tree.
+struct Walk<'a> {
+    car: &'a Car,
+    records: BTreeMap<String, Cid>,
+}
+
+impl Walk<'_> {
+    /// Visit a node whose keys must all sit below `max_layer`, if given.
+    fn node(&mut self, cid: &Cid, max_layer: Option<u32>) -> Result<(), Error> {
+        let node = Node::decode(checked_block(self.car, cid)?)?;
+        let keys = node.keys()?;
+
+        let layer = match keys.first() {
+            Some(first) => {
+                let layer = key_layer(first);
+                if keys.iter().any(|k| key_layer(k) != layer) {
+                    return Err(Error::InvalidMst("node mixes keys of different layers".to_string()));
+                }
+                if max_layer.is_some_and(|max| layer >= max) {
+                    return Err(Error::InvalidMst("subtree is not below its parent".to_string()));
+                }
+                Some(layer)
+            }
+            // An empty node is only valid as the root of an empty repo, or
+            // as a placeholder on the way down to a lower layer
+            None => max_layer.map(|max| max.saturating_sub(1)),
+        };
+        if layer == Some(0) && node.left.is_some() {
+            return Err(Error::InvalidMst("leaf node has a subtree".to_string()));
+        }
+
+        if let Some(left) = &node.left {
+            self.node(left, layer)?;
+        }
+        for (key, entry) in keys.into_iter().zip(&node.entries) {
+            // Keys must come out of an in-order walk strictly increasing
+            if self.records.last_key_value().is_some_and(|(last, _)| *last >= key) {
+                return Err(Error::InvalidMst(format!("key {} is out of order", key)));
+            }
+            checked_block(self.car, &entry.value)?;
+            self.records.insert(key, entry.value.clone());
+            if let Some(tree) = &entry.tree {
+                self.node(tree, layer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::{signature::Signer, Signature, SigningKey};
+
+    use super::*;
+
+    const DID: &str = "did:plc:alice";
+
+    fn cid_for(block: &[u8]) -> Cid {
+        Cid::compute(Cid::DAG_CBOR, block)
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(entries.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    /// Keys that all land on layer 0, so the tree is a single node
+    fn leaf_keys() -> Vec<String> {
+        let keys: Vec<String> = ["3k1", "3k2", "3k3", "3k4", "3k5", "3k6", "3k7", "3k8"]
+            .iter()
+            .map(|rkey| format!("eu.atchef.recipe/{}", rkey))
+            .filter(|key| key_layer(key) == 0)
+            .take(3)
+            .collect();
+        assert_eq!(keys.len(), 3);
+        keys
+    }
+
+    /// Build a signed repo: blocks in CAR order, with the commit first,
+    /// then the tree nodes from the root down, then the records.
+    fn build_repo(keys: &[String]) -> Vec<(Cid, Vec<u8>)> {
+        build_repo_with_layers(keys, key_layer)
+    }
+
+    /// `build_repo`, placing keys on the layers `layer_of` says.
+    fn build_repo_with_layers(keys: &[String], layer_of: fn(&str) -> u32) -> Vec<(Cid, Vec<u8>)> {
+        let mut records = Vec::new();
+        let mut leaves = Vec::new();
+        for key in keys {
+            let record = dag_cbor::encode(&map(vec![
+                ("$type", Value::String("eu.atchef.recipe".into())),
+                ("name", Value::String(key.clone())),
+            ]));
+            let record_cid = cid_for(&record);
+            leaves.push((key.clone(), record_cid.clone()));
+            records.push((record_cid, record));
+        }
+        let mut nodes = Vec::new();
+        let root_layer = keys.iter().map(|k| layer_of(k)).max().unwrap_or(0);
+        let root = build_node(&leaves, root_layer, layer_of, &mut nodes).unwrap_or_else(|| {
+            let empty = dag_cbor::encode(&map(vec![("e", Value::List(Vec::new())), ("l", Value::Null)]));
+            nodes.push((cid_for(&empty), empty));
+            nodes[0].0.clone()
+        });
+        nodes.reverse();
+
+        let mut commit = map(vec![
+            ("did", Value::String(DID.into())),
+            ("version", Value::Integer(3)),
+            ("data", Value::Link(root)),
+            ("rev", Value::String("3kaaaaaaaaaaa".into())),
+            ("prev", Value::Null),
+        ]);
+        let signature: Signature = signing_key().sign(&dag_cbor::encode(&commit));
+        let signature = signature.normalize_s().unwrap_or(signature);
+        if let Value::Map(fields) = &mut commit {
+            fields.insert("sig".into(), Value::Bytes(signature.to_bytes().to_vec()));
+        }
+        let commit = dag_cbor::encode(&commit);
+        let mut blocks = vec![(cid_for(&commit), commit)];
+        blocks.extend(nodes);
+        blocks.extend(records);
+        blocks
+    }
+
+    /// Build the node holding the `layer` keys of `leaves`, with the keys in
+    /// between in subtrees one layer down. A run of keys that are all lower
+    /// than that gets an empty node on the way. Nodes are pushed children
+    /// first.
+    fn build_node(
+        leaves: &[(String, Cid)],
+        layer: u32,
+        layer_of: fn(&str) -> u32,
+        nodes: &mut Vec<(Cid, Vec<u8>)>,
+    ) -> Option<Cid> {
+        if leaves.is_empty() {
+            return None;
+        }
+        let mut here = Vec::new();
+        let mut runs = vec![Vec::new()];
+        for (key, cid) in leaves {
+            if layer_of(key) == layer {
+                here.push((key.as_str(), cid.clone()));
+                runs.push(Vec::new());
+            } else {
+                runs.last_mut().unwrap().push((key.clone(), cid.clone()));
+            }
+        }
+        let mut subtrees = runs
+            .iter()
+            .map(|run| build_node(run, layer.wrapping_sub(1), layer_of, nodes).map_or(Value::Null, Value::Link));
+
+        let left = subtrees.next().unwrap();
+        let mut entries = Vec::new();
+        let mut previous = "";
+        for ((key, cid), tree) in here.into_iter().zip(subtrees) {
+            let shared = key
+                .bytes()
+                .zip(previous.bytes())
+                .take_while(|(a, b)| a == b)
+                .count();
+            entries.push(map(vec![
+                ("k", Value::Bytes(key.as_bytes()[shared..].to_vec())),
+                ("p", Value::Integer(shared as i64)),
+                ("t", tree),
+                ("v", Value::Link(cid)),
+            ]));
+            previous = key;
+        }
+        let node = dag_cbor::encode(&map(vec![("e", Value::List(entries)), ("l", left)]));
+        let cid = cid_for(&node);
+        nodes.push((cid.clone(), node));
+        Some(cid)
+    }
+
+    /// The first `count` recipe keys on `layer`, searching rkeys in order.
+    fn keys_on_layer(layer: u32, count: usize) -> Vec<String> {
+        (0..100_000)
+            .map(|i| format!("eu.atchef.recipe/3k{:05}", i))
+            .filter(|key| key_layer(key) == layer)
+            .take(count)
+            .collect()
+    }
+
+    /// Number of tree nodes in a repo built by `build_repo`, and how many of
+    /// them have no entries.
+    fn count_nodes(blocks: &[(Cid, Vec<u8>)]) -> (usize, usize) {
+        let nodes: Vec<Node> = blocks
+            .iter()
+            .filter_map(|(_, block)| {
+                let value = dag_cbor::decode(block).ok()?;
+                value.get("e")?;
+                Node::decode(block).ok()
+            })
+            .collect();
+        (nodes.len(), nodes.iter().filter(|n| n.entries.is_empty()).count())
+    }
+
+    fn to_car(blocks: &[(Cid, Vec<u8>)]) -> Car {
+        let mut bytes = Vec::new();
+        let header = dag_cbor::encode(&map(vec![
+            ("roots", Value::List(vec![Value::Link(blocks[0].0.clone())])),
+            ("version", Value::Integer(1)),
+        ]));
+        crate::varint::write(header.len() as u64, &mut bytes);
+        bytes.extend(header);
+        for (cid, block) in blocks {
+            crate::varint::write((cid.as_bytes().len() + block.len()) as u64, &mut bytes);
+            bytes.extend(cid.as_bytes());
+            bytes.extend(block);
+        }
+        Car::parse(&bytes).unwrap()
+    }
+
+    fn public_key() -> PublicKey {
+        PublicKey::K256(*signing_key().verifying_key())
+    }
+
+    #[test]
+    fn test_key_layer_spec_vectors() {
+        // From the atproto repository spec
+        assert_eq!(key_layer("2653ae71"), 0);
+        assert_eq!(key_layer("blue"), 1);
+        assert_eq!(key_layer("app.bsky.feed.post/454397e440ec"), 4);
+        assert_eq!(key_layer("app.bsky.feed.post/9adeb165882c"), 8);
+    }
+
+    #[test]
+    fn test_verify_repo() {
+        let keys = leaf_keys();
+        let repo = verify_repo(to_car(&build_repo(&keys)), DID, &public_key()).unwrap();
+
+        assert_eq!(repo.commit().rev, "3kaaaaaaaaaaa");
+        let listed: Vec<&str> = repo.records().map(|(key, _)| key).collect();
+        assert_eq!(listed, keys);
+        let rkey = keys[1].trim_start_matches("eu.atchef.recipe/");
+        let record = repo.get("eu.atchef.recipe", rkey).unwrap().unwrap();
+        assert_eq!(record.get("name").and_then(Value::as_str), Some(keys[1].as_str()));
+        assert_eq!(repo.collection("eu.atchef.recipe").count(), 3);
+        assert_eq!(repo.collection("app.bsky.feed.post").count(), 0);
+    }
+
+    #[test]
+    fn test_verify_record_proof() {
+        let keys = leaf_keys();
+        let blocks = build_repo(&keys);
+        let rkey = keys[0].trim_start_matches("eu.atchef.recipe/");
+
+        // Commit, tree node and the one record
+        let proof = to_car(&blocks[..3]);
+        let record = verify_record(&proof, DID, &public_key(), "eu.atchef.recipe", rkey).unwrap();
+        assert!(record.is_some());
+        let missing = verify_record(&proof, DID, &public_key(), "eu.atchef.recipe", "zzz").unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn test_wrong_key_or_did() {
+        let car = to_car(&build_repo(&leaf_keys()));
+        let other = PublicKey::K256(*SigningKey::from_slice(&[9u8; 32]).unwrap().verifying_key());
+
+        assert!(matches!(verify_repo(car.clone(), DID, &other), Err(Error::InvalidSignature)));
+        assert!(matches!(
+            verify_repo(car, "did:plc:mallory", &public_key()),
+            Err(Error::InvalidCommit(_))
+        ));
+    }
+
+    #[test]
+    fn test_tampered_record() {
+        let mut blocks = build_repo(&leaf_keys());
+        let last = blocks.last_mut().unwrap();
+        last.1 = dag_cbor::encode(&map(vec![("name", Value::String("Poisoned".into()))]));
+
+        assert!(matches!(
+            verify_repo(to_car(&blocks), DID, &public_key()),
+            Err(Error::CidMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_repo() {
+        let mut blocks = build_repo(&leaf_keys());
+        blocks.pop();
+
+        assert!(matches!(
+            verify_repo(to_car(&blocks), DID, &public_key()),
+            Err(Error::MissingBlock(_))
+        ));
+    }
+
+    #[test]
+    fn test_unordered_keys() {
+        let mut keys = leaf_keys();
+        keys.swap(0, 1);
+
+        assert!(matches!(
+            verify_repo(to_car(&build_repo(&keys)), DID, &public_key()),
+            Err(Error::InvalidMst(_))
+        ));
+    }
+
+    #[test]
+    fn test_multi_layer_tree() {
+        let mut keys: Vec<String> = [keys_on_layer(0, 6), keys_on_layer(1, 3), keys_on_layer(2, 1)].concat();
+        keys.sort();
+        let blocks = build_repo(&keys);
+        let (nodes, _) = count_nodes(&blocks);
+        assert!(nodes > 2, "expected a tree of several nodes, got {}", nodes);
+
+        let repo = verify_repo(to_car(&blocks), DID, &public_key()).unwrap();
+        let listed: Vec<&str> = repo.records().map(|(key, _)| key).collect();
+        assert_eq!(listed, keys);
+        for key in &keys {
+            let rkey = key.trim_start_matches("eu.atchef.recipe/");
+            assert!(repo.get("eu.atchef.recipe", rkey).unwrap().is_some(), "{} not found", key);
+        }
+    }
+
+    #[test]
+    fn test_empty_intermediate_node() {
+        // A layer 2 root with only layer 0 keys below it needs an empty
+        // layer 1 node in between
+        let mut keys: Vec<String> = [keys_on_layer(0, 3), keys_on_layer(2, 1)].concat();
+        keys.sort();
+        let blocks = build_repo(&keys);
+        let (nodes, empty) = count_nodes(&blocks);
+        assert!(empty >= 1 && nodes >= 3, "{} nodes, {} empty", nodes, empty);
+
+        let repo = verify_repo(to_car(&blocks), DID, &public_key()).unwrap();
+        assert_eq!(repo.records().count(), keys.len());
+    }
+
+    #[test]
+    fn test_verify_record_through_subtree() {
+        let mut keys: Vec<String> = [keys_on_layer(0, 6), keys_on_layer(1, 3), keys_on_layer(2, 1)].concat();
+        keys.sort();
+        let blocks = build_repo(&keys);
+        let (nodes, _) = count_nodes(&blocks);
+        let target = keys_on_layer(0, 1).remove(0);
+        let target_cid = verify_repo(to_car(&blocks), DID, &public_key())
+            .unwrap()
+            .records()
+            .find(|(key, _)| *key == target)
+            .map(|(_, cid)| cid.clone())
+            .unwrap();
+
+        // Commit and tree nodes, and of the records only the one asked for
+        let mut proof: Vec<_> = blocks[..1 + nodes].to_vec();
+        proof.extend(blocks.iter().filter(|(cid, _)| *cid == target_cid).cloned());
+        let proof = to_car(&proof);
+        let rkey = target.trim_start_matches("eu.atchef.recipe/");
+        let record = verify_record(&proof, DID, &public_key(), "eu.atchef.recipe", rkey).unwrap().unwrap();
+        assert_eq!(record.get("name").and_then(Value::as_str), Some(target.as_str()));
+
+        // A key that would sit in the same subtree proves absent, while
+        // reaching a record the proof left out is an error
+        let absent = format!("{}0", rkey);
+        assert!(verify_record(&proof, DID, &public_key(), "eu.atchef.recipe", &absent).unwrap().is_none());
+        let other = keys.iter().find(|k| **k != target).unwrap().trim_start_matches("eu.atchef.recipe/");
+        assert!(matches!(
+            verify_record(&proof, DID, &public_key(), "eu.atchef.recipe", other),
+            Err(Error::MissingBlock(_))
+        ));
+    }
+
+    #[test]
+    fn test_keys_on_the_wrong_layer() {
+        // All keys in one node, although one belongs a layer up
+        let mut keys: Vec<String> = [keys_on_layer(0, 2), keys_on_layer(1, 1)].concat();
+        keys.sort();
+        let blocks = build_repo_with_layers(&keys, |_| 0);
+
+        assert!(matches!(
+            verify_repo(to_car(&blocks), DID, &public_key()),
+            Err(Error::InvalidMst(_))
+        ));
+    }
+}
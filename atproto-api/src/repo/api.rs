@@ -1,21 +1,26 @@
-use reqwest::Client;
+use futures_util::future::Either;
+use futures_util::stream::{self, Stream, TryStreamExt};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 use super::types::*;
+use crate::collection::Collection;
 use crate::session::Session;
-use crate::types::BlobRef;
-use crate::xrpc::XrpcClient;
+use crate::types::{BlobRef, Nsid, RecordKey};
+use crate::xrpc::{Transport, XrpcClient};
 use crate::Error;
 
 /// Repository operations (com.atproto.repo.*)
+///
+/// Collections and record keys are accepted as `&str`, `Nsid` or `RecordKey`
+/// and checked against the ATProto syntax before anything is sent.
 pub struct RepoApi<'a, S: Session> {
     session: &'a S,
-    http: &'a Client,
+    http: &'a Transport,
 }
 
 impl<'a, S: Session> RepoApi<'a, S> {
-    pub(crate) fn new(session: &'a S, http: &'a Client) -> Self {
+    pub(crate) fn new(session: &'a S, http: &'a Transport) -> Self {
         Self { session, http }
     }
 
@@ -28,9 +33,11 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn get_record<T: DeserializeOwned>(
         &self,
         repo: &str,
-        collection: &str,
-        rkey: &str,
+        collection: impl AsRef<str>,
+        rkey: impl AsRef<str>,
     ) -> Result<GetRecordOutput<T>, Error> {
+        let (collection, rkey) = (collection.as_ref(), rkey.as_ref());
+        check_record(collection, Some(rkey))?;
         let client = XrpcClient::new(self.session, self.http);
         client
             .get(
@@ -50,43 +57,35 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn put_record<T: Serialize>(
         &self,
         repo: &str,
-        collection: &str,
-        rkey: &str,
+        collection: impl AsRef<str>,
+        rkey: impl AsRef<str>,
         record: &T,
     ) -> Result<PutRecordOutput, Error> {
-        let client = XrpcClient::new(self.session, self.http);
-        let input = PutRecordInput {
-            repo,
-            collection,
-            rkey,
-            record,
-            swap_record: None,
-            swap_commit: None,
-            validate: None,
-        };
-        client.post("com.atproto.repo.putRecord", &input).await
+        self.put_record_with_options(repo, collection, rkey, record, PutRecordOptions::default())
+            .await
     }
 
-    /// Create or update a record with additional options.
+    /// Create or update a record with additional options, e.g. a
+    /// compare-and-swap on the record's current CID.
     pub async fn put_record_with_options<T: Serialize>(
         &self,
         repo: &str,
-        collection: &str,
-        rkey: &str,
+        collection: impl AsRef<str>,
+        rkey: impl AsRef<str>,
         record: &T,
-        swap_record: Option<&str>,
-        swap_commit: Option<&str>,
-        validate: Option<bool>,
+        options: PutRecordOptions<'_>,
     ) -> Result<PutRecordOutput, Error> {
+        let (collection, rkey) = (collection.as_ref(), rkey.as_ref());
+        check_record(collection, Some(rkey))?;
         let client = XrpcClient::new(self.session, self.http);
         let input = PutRecordInput {
             repo,
             collection,
             rkey,
             record,
-            swap_record,
-            swap_commit,
-            validate,
+            swap_record: options.swap_record,
+            swap_commit: options.swap_commit,
+            validate: options.validate,
         };
         client.post("com.atproto.repo.putRecord", &input).await
     }
@@ -100,9 +99,11 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn create_record<T: Serialize>(
         &self,
         repo: &str,
-        collection: &str,
+        collection: impl AsRef<str>,
         record: &T,
     ) -> Result<CreateRecordOutput, Error> {
+        let collection = collection.as_ref();
+        check_record(collection, None)?;
         let client = XrpcClient::new(self.session, self.http);
         let input = CreateRecordInput {
             repo,
@@ -119,10 +120,12 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn create_record_with_rkey<T: Serialize>(
         &self,
         repo: &str,
-        collection: &str,
-        rkey: &str,
+        collection: impl AsRef<str>,
+        rkey: impl AsRef<str>,
         record: &T,
     ) -> Result<CreateRecordOutput, Error> {
+        let (collection, rkey) = (collection.as_ref(), rkey.as_ref());
+        check_record(collection, Some(rkey))?;
         let client = XrpcClient::new(self.session, self.http);
         let input = CreateRecordInput {
             repo,
@@ -144,9 +147,11 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn delete_record(
         &self,
         repo: &str,
-        collection: &str,
-        rkey: &str,
+        collection: impl AsRef<str>,
+        rkey: impl AsRef<str>,
     ) -> Result<(), Error> {
+        let (collection, rkey) = (collection.as_ref(), rkey.as_ref());
+        check_record(collection, Some(rkey))?;
         let client = XrpcClient::new(self.session, self.http);
         let input = DeleteRecordInput {
             repo,
@@ -168,8 +173,10 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn list_records<T: DeserializeOwned>(
         &self,
         repo: &str,
-        collection: &str,
+        collection: impl AsRef<str>,
     ) -> Result<ListRecordsOutput<T>, Error> {
+        let collection = collection.as_ref();
+        check_record(collection, None)?;
         let client = XrpcClient::new(self.session, self.http);
         client
             .get(
@@ -190,11 +197,13 @@ impl<'a, S: Session> RepoApi<'a, S> {
     pub async fn list_records_with_options<T: DeserializeOwned>(
         &self,
         repo: &str,
-        collection: &str,
+        collection: impl AsRef<str>,
         limit: Option<u32>,
         cursor: Option<&str>,
         reverse: Option<bool>,
     ) -> Result<ListRecordsOutput<T>, Error> {
+        let collection = collection.as_ref();
+        check_record(collection, None)?;
         let client = XrpcClient::new(self.session, self.http);
 
         let mut params: Vec<(&str, &str)> = vec![("repo", repo), ("collection", collection)];
@@ -218,6 +227,176 @@ impl<'a, S: Session> RepoApi<'a, S> {
         client.get("com.atproto.repo.listRecords", &params).await
     }
 
+    /// Stream every record in a collection, following `cursor` across pages.
+    ///
+    /// Pages are fetched lazily as the stream is polled. The stream ends when
+    /// the PDS stops returning a cursor, or once `max_items` records have been
+    /// yielded. A failed page request, or an invalid `collection`, is yielded as
+    /// an error and ends the stream.
+    ///
+    /// # Arguments
+    /// * `repo` - The DID of the repo
+    /// * `collection` - The NSID of the collection
+    /// * `options` - Page size, ordering and an optional cap on total records
+    pub fn list_records_stream<T: DeserializeOwned + Send + 'a>(
+        &self,
+        repo: &'a str,
+        collection: impl AsRef<str>,
+        options: ListRecordsOptions,
+    ) -> impl Stream<Item = Result<ListRecordsRecord<T>, Error>> + Send + 'a {
+        self.list_records_pages(repo, collection, options, None)
+            .map_ok(|page| stream::iter(page.records.into_iter().map(Ok)))
+            .try_flatten()
+    }
+
+    /// Like `list_records_stream`, but yields whole pages and starts at
+    /// `cursor`. Saving each page's `cursor` lets a later call resume after
+    /// the last page that was processed.
+    ///
+    /// # Arguments
+    /// * `repo` - The DID of the repo
+    /// * `collection` - The NSID of the collection
+    /// * `options` - Page size, ordering and an optional cap on total records
+    /// * `cursor` - Pagination cursor to start from
+    pub fn list_records_pages<T: DeserializeOwned + Send + 'a>(
+        &self,
+        repo: &'a str,
+        collection: impl AsRef<str>,
+        options: ListRecordsOptions,
+        cursor: Option<String>,
+    ) -> impl Stream<Item = Result<ListRecordsOutput<T>, Error>> + Send + 'a {
+        let collection = match Nsid::new(collection.as_ref()) {
+            Ok(collection) => collection,
+            Err(e) => return Either::Left(stream::once(async { Err(e) })),
+        };
+
+        struct State {
+            collection: Nsid,
+            cursor: Option<String>,
+            remaining: Option<usize>,
+            done: bool,
+        }
+
+        let api = RepoApi::new(self.session, self.http);
+        let state = State {
+            collection,
+            cursor,
+            remaining: options.max_items,
+            done: false,
+        };
+
+        Either::Right(stream::unfold((api, state), move |(api, mut state)| async move {
+            if state.done || state.remaining == Some(0) {
+                return None;
+            }
+
+            // Don't ask for more than the cap still allows
+            let limit = options.limit.map(|limit| match state.remaining {
+                Some(remaining) => limit.min(u32::try_from(remaining).unwrap_or(u32::MAX)),
+                None => limit,
+            });
+            let page = api
+                .list_records_with_options::<T>(
+                    repo,
+                    &state.collection,
+                    limit,
+                    state.cursor.as_deref(),
+                    options.reverse,
+                )
+                .await;
+            match page {
+                Ok(mut page) => {
+                    // An empty page or a cursor that doesn't advance would loop forever
+                    state.done = page.records.is_empty()
+                        || page.cursor.is_none()
+                        || page.cursor == state.cursor;
+                    if let Some(remaining) = state.remaining.as_mut() {
+                        page.records.truncate(*remaining);
+                        *remaining -= page.records.len();
+                    }
+                    if page.records.is_empty() {
+                        return None;
+                    }
+                    state.cursor = page.cursor.clone();
+                    Some((Ok(page), (api, state)))
+                }
+                Err(e) => {
+                    state.done = true;
+                    Some((Err(e), (api, state)))
+                }
+            }
+        }))
+    }
+
+    /// Get a record of collection `C` from the session's repo.
+    ///
+    /// To read another account's repo, use an `AnonymousSession` for it.
+    pub async fn get<C: Collection>(&self, rkey: impl AsRef<str>) -> Result<GetRecordOutput<C::Record>, Error> {
+        self.get_record(self.session.did(), C::NSID, rkey).await
+    }
+
+    /// Create a record of collection `C` in the session's repo, with a
+    /// key chosen by the PDS.
+    pub async fn create<C: Collection>(&self, record: &C::Record) -> Result<CreateRecordOutput, Error> {
+        self.create_record(self.session.did(), C::NSID, record).await
+    }
+
+    /// Create or update the record of collection `C` at `rkey` in the
+    /// session's repo.
+    pub async fn put<C: Collection>(&self, rkey: impl AsRef<str>, record: &C::Record) -> Result<PutRecordOutput, Error> {
+        self.put_record(self.session.did(), C::NSID, rkey, record).await
+    }
+
+    /// Delete the record of collection `C` at `rkey` from the session's repo.
+    pub async fn delete<C: Collection>(&self, rkey: impl AsRef<str>) -> Result<(), Error> {
+        self.delete_record(self.session.did(), C::NSID, rkey).await
+    }
+
+    /// Stream every record of collection `C` in the session's repo; see
+    /// `list_records_stream`.
+    pub fn list<C: Collection>(
+        &self,
+        options: ListRecordsOptions,
+    ) -> impl Stream<Item = Result<ListRecordsRecord<C::Record>, Error>> + Send + 'a
+    where
+        C::Record: 'a,
+    {
+        self.list_records_stream(self.session.did(), C::NSID, options)
+    }
+
+    /// Apply several creates, updates and deletes in a single commit.
+    ///
+    /// Either every write is applied or none is. With `swap_commit`, the
+    /// batch only applies if the repo's current commit has that CID.
+    ///
+    /// # Arguments
+    /// * `repo` - The DID of the repo
+    /// * `writes` - The operations, applied in order
+    /// * `swap_commit` - Expected CID of the current commit
+    pub async fn apply_writes(
+        &self,
+        repo: &str,
+        writes: &[Write],
+        swap_commit: Option<&str>,
+    ) -> Result<ApplyWritesOutput, Error> {
+        for write in writes {
+            match write {
+                Write::Create { collection, rkey, .. } => check_record(collection, rkey.as_deref())?,
+                Write::Update { collection, rkey, .. } | Write::Delete { collection, rkey } => {
+                    check_record(collection, Some(rkey))?
+                }
+            }
+        }
+        let client = XrpcClient::new(self.session, self.http);
+        let input = ApplyWritesInput {
+            repo,
+            writes,
+            swap_commit,
+            validate: None,
+        };
+        client.post("com.atproto.repo.applyWrites", &input).await
+    }
+
     /// Upload a blob (image, file, etc.).
     ///
     /// # Arguments
@@ -234,3 +413,220 @@ impl<'a, S: Session> RepoApi<'a, S> {
         Ok(output.blob)
     }
 }
+
+/// Reject a malformed collection or record key before it reaches the PDS.
+fn check_record(collection: &str, rkey: Option<&str>) -> Result<(), Error> {
+    Nsid::new(collection)?;
+    if let Some(rkey) = rkey {
+        RecordKey::new(rkey)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures_util::TryStreamExt;
+
+    use super::*;
+    use crate::test_server::{self, Response};
+    use crate::{Agent, AnonymousSession};
+
+    /// Serves five records in pages of at most two, chained by opaque cursors
+    /// that are deliberately unrelated to the record URIs.
+    fn spawn_pds() -> (String, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let url = test_server::spawn(move |request| {
+            let (start, next) = match request.query("cursor").as_deref() {
+                None => (1, Some("page-2")),
+                Some("page-2") => (3, Some("page-3")),
+                _ => (5, None),
+            };
+            let limit: usize = request.query("limit").map_or(2, |l| l.parse().unwrap()).min(2);
+            let records: Vec<_> = (start..=5)
+                .take(limit)
+                .map(|i| serde_json::json!({
+                    "uri": format!("at://did:plc:abc123/eu.atchef.recipe/r{}", i),
+                    "cid": "bafyreirecord",
+                    "value": {"n": i},
+                }))
+                .collect();
+            seen.lock().unwrap().push(request.target);
+            Response::json(200, serde_json::json!({"records": records, "cursor": next}))
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_list_records_stream_follows_cursor() {
+        let (pds_url, requests) = spawn_pds();
+        let agent = Agent::new(AnonymousSession::new("did:plc:abc123", pds_url));
+        let options = ListRecordsOptions { limit: Some(2), ..Default::default() };
+
+        let records: Vec<ListRecordsRecord<serde_json::Value>> = agent
+            .repo()
+            .list_records_stream("did:plc:abc123", "eu.atchef.recipe", options)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let values: Vec<_> = records.iter().map(|r| r.value["n"].as_i64().unwrap()).collect();
+        assert_eq!(values, vec![1, 2, 3, 4, 5]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        assert!(!requests[0].contains("cursor="));
+        assert!(requests[1].contains("cursor=page-2"));
+        assert!(requests[2].contains("cursor=page-3"));
+    }
+
+    #[tokio::test]
+    async fn test_list_records_stream_max_items() {
+        let (pds_url, requests) = spawn_pds();
+        let agent = Agent::new(AnonymousSession::new("did:plc:abc123", pds_url));
+        let options = ListRecordsOptions {
+            limit: Some(2),
+            reverse: Some(true),
+            max_items: Some(3),
+        };
+
+        let records: Vec<ListRecordsRecord<serde_json::Value>> = agent
+            .repo()
+            .list_records_stream("did:plc:abc123", "eu.atchef.recipe", options)
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("reverse=true"));
+        // The second page only asks for what the cap still allows
+        assert!(requests[1].contains("limit=1"));
+    }
+
+    #[tokio::test]
+    async fn test_list_records_pages_resume_from_cursor() {
+        let (pds_url, requests) = spawn_pds();
+        let agent = Agent::new(AnonymousSession::new("did:plc:abc123", pds_url));
+        let options = ListRecordsOptions { limit: Some(2), ..Default::default() };
+
+        let pages: Vec<ListRecordsOutput<serde_json::Value>> = agent
+            .repo()
+            .list_records_pages("did:plc:abc123", "eu.atchef.recipe", options, Some("page-2".into()))
+            .try_collect()
+            .await
+            .unwrap();
+
+        let cursors: Vec<_> = pages.iter().map(|p| p.cursor.as_deref()).collect();
+        assert_eq!(cursors, vec![Some("page-3"), None]);
+        assert_eq!(pages[0].records[0].value["n"], 3);
+        assert!(requests.lock().unwrap()[0].contains("cursor=page-2"));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_identifiers_rejected_before_request() {
+        let (pds_url, requests) = spawn_pds();
+        let agent = Agent::new(AnonymousSession::new("did:plc:abc123", pds_url));
+        let repo = agent.repo();
+
+        let result = repo.get_record::<serde_json::Value>("did:plc:abc123", "eu.atchef.recipe", "../../admin").await;
+        assert!(matches!(result, Err(Error::InvalidRecordKey(_))));
+        let result = repo.delete_record("did:plc:abc123", "eu.atchef.recipe", "a?b=c").await;
+        assert!(matches!(result, Err(Error::InvalidRecordKey(_))));
+        let result = repo.create_record("did:plc:abc123", "recipe", &serde_json::json!({})).await;
+        assert!(matches!(result, Err(Error::InvalidNsid(_))));
+        let writes = [
+            Write::delete("eu.atchef.recipe", "r1"),
+            Write::delete("eu.atchef.recipe", "r/2"),
+        ];
+        let result = repo.apply_writes("did:plc:abc123", &writes, None).await;
+        assert!(matches!(result, Err(Error::InvalidRecordKey(_))));
+        let mut stream = Box::pin(repo.list_records_stream::<serde_json::Value>(
+            "did:plc:abc123",
+            "eu.atchef.",
+            ListRecordsOptions::default(),
+        ));
+        assert!(matches!(stream.try_next().await, Err(Error::InvalidNsid(_))));
+
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    struct Notes;
+
+    impl Collection for Notes {
+        const NSID: &'static str = "com.example.note";
+        type Record = Note;
+    }
+
+    #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+    struct Note {
+        text: String,
+    }
+
+    #[tokio::test]
+    async fn test_typed_collection_helpers() {
+        let url = test_server::spawn(|request| match request.path() {
+            "/xrpc/com.atproto.repo.createRecord" => {
+                let input = request.json();
+                assert_eq!(input["repo"], "did:plc:abc123");
+                assert_eq!(input["collection"], "com.example.note");
+                assert_eq!(input["record"]["text"], "hi");
+                Response::json(200, serde_json::json!({
+                    "uri": "at://did:plc:abc123/com.example.note/n1",
+                    "cid": "bafyreirecord",
+                }))
+            }
+            "/xrpc/com.atproto.repo.getRecord" => {
+                assert_eq!(request.query("repo").as_deref(), Some("did:plc:abc123"));
+                assert_eq!(request.query("collection").as_deref(), Some("com.example.note"));
+                Response::json(200, serde_json::json!({
+                    "uri": "at://did:plc:abc123/com.example.note/n1",
+                    "value": {"text": "hi"},
+                }))
+            }
+            "/xrpc/com.atproto.repo.deleteRecord" => {
+                assert_eq!(request.json()["collection"], "com.example.note");
+                Response::json(200, serde_json::json!({}))
+            }
+            _ => Response::error(404, "MethodNotImplemented"),
+        });
+        let agent = Agent::new(AnonymousSession::new("did:plc:abc123", url));
+        let note = Note { text: "hi".to_string() };
+
+        let output = agent.repo().create::<Notes>(&note).await.unwrap();
+        assert_eq!(output.uri, Notes::uri("did:plc:abc123", &"n1".parse().unwrap()).to_string());
+        let record = agent.repo().get::<Notes>("n1").await.unwrap();
+        assert_eq!(record.value, note);
+        agent.repo().delete::<Notes>("n1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_put_record_options_are_sent() {
+        let url = test_server::spawn(|request| {
+            let input = request.json();
+            assert_eq!(input["rkey"], "n1");
+            assert_eq!(input["swapRecord"], "bafyreiold");
+            assert_eq!(input["validate"], false);
+            assert!(input.get("swapCommit").is_none());
+            Response::json(200, serde_json::json!({
+                "uri": "at://did:plc:abc123/com.example.note/n1",
+                "cid": "bafyreinew",
+            }))
+        });
+        let agent = Agent::new(AnonymousSession::new("did:plc:abc123", url));
+        let options = PutRecordOptions {
+            swap_record: Some("bafyreiold"),
+            validate: Some(false),
+            ..Default::default()
+        };
+
+        let output = agent
+            .repo()
+            .put_record_with_options("did:plc:abc123", "com.example.note", "n1", &Note { text: "hi".into() }, options)
+            .await
+            .unwrap();
+        assert_eq!(output.cid, "bafyreinew");
+    }
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 use crate::types::BlobRef;
+use crate::Error;
 
 /// Response from com.atproto.repo.getRecord
 #[derive(Debug, Clone, Deserialize)]
@@ -25,6 +26,17 @@ pub struct PutRecordInput<'a, T> {
     pub validate: Option<bool>,
 }
 
+/// Optional parameters of `RepoApi::put_record_with_options`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PutRecordOptions<'a> {
+    /// Only write if the record's current CID is this one
+    pub swap_record: Option<&'a str>,
+    /// Only write if the repo's current commit is this one
+    pub swap_commit: Option<&'a str>,
+    /// Ask the PDS to validate the record against its lexicon
+    pub validate: Option<bool>,
+}
+
 /// Response from com.atproto.repo.putRecord
 #[derive(Debug, Clone, Deserialize)]
 pub struct PutRecordOutput {
@@ -72,6 +84,17 @@ pub struct ListRecordsOutput<T> {
     pub cursor: Option<String>,
 }
 
+/// Paging options for `RepoApi::list_records_stream`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListRecordsOptions {
+    /// Records requested per page (the PDS default applies when unset)
+    pub limit: Option<u32>,
+    /// If true, return oldest records first
+    pub reverse: Option<bool>,
+    /// Stop after yielding this many records in total
+    pub max_items: Option<usize>,
+}
+
 /// A single record in listRecords response
 #[derive(Debug, Clone, Deserialize)]
 pub struct ListRecordsRecord<T> {
@@ -85,3 +108,167 @@ pub struct ListRecordsRecord<T> {
 pub struct UploadBlobOutput {
     pub blob: BlobRef,
 }
+
+/// A single operation in a com.atproto.repo.applyWrites batch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "$type")]
+pub enum Write {
+    #[serde(rename = "com.atproto.repo.applyWrites#create")]
+    Create {
+        collection: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rkey: Option<String>,
+        value: serde_json::Value,
+    },
+    #[serde(rename = "com.atproto.repo.applyWrites#update")]
+    Update {
+        collection: String,
+        rkey: String,
+        value: serde_json::Value,
+    },
+    #[serde(rename = "com.atproto.repo.applyWrites#delete")]
+    Delete { collection: String, rkey: String },
+}
+
+impl Write {
+    /// Create a record, at `rkey` or under a TID chosen by the PDS.
+    pub fn create<T: Serialize>(collection: &str, rkey: Option<&str>, record: &T) -> Result<Self, Error> {
+        Ok(Write::Create {
+            collection: collection.to_string(),
+            rkey: rkey.map(str::to_string),
+            value: serde_json::to_value(record)?,
+        })
+    }
+
+    /// Replace the record at `rkey`.
+    pub fn update<T: Serialize>(collection: &str, rkey: &str, record: &T) -> Result<Self, Error> {
+        Ok(Write::Update {
+            collection: collection.to_string(),
+            rkey: rkey.to_string(),
+            value: serde_json::to_value(record)?,
+        })
+    }
+
+    /// Delete the record at `rkey`.
+    pub fn delete(collection: &str, rkey: &str) -> Self {
+        Write::Delete {
+            collection: collection.to_string(),
+            rkey: rkey.to_string(),
+        }
+    }
+}
+
+/// Input for com.atproto.repo.applyWrites
+#[derive(Debug, Clone, Serialize)]
+pub struct ApplyWritesInput<'a> {
+    pub repo: &'a str,
+    pub writes: &'a [Write],
+    #[serde(rename = "swapCommit", skip_serializing_if = "Option::is_none")]
+    pub swap_commit: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validate: Option<bool>,
+}
+
+/// Response from com.atproto.repo.applyWrites
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApplyWritesOutput {
+    pub commit: Option<CommitMeta>,
+    /// One result per write, in the order the writes were given
+    #[serde(default)]
+    pub results: Vec<WriteResult>,
+}
+
+/// The commit a write landed in
+#[derive(Debug, Clone, Deserialize)]
+pub struct CommitMeta {
+    pub cid: String,
+    pub rev: String,
+}
+
+/// Outcome of a single operation in an applyWrites batch
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "$type")]
+pub enum WriteResult {
+    #[serde(rename = "com.atproto.repo.applyWrites#createResult")]
+    Create {
+        uri: String,
+        cid: String,
+        #[serde(rename = "validationStatus")]
+        validation_status: Option<String>,
+    },
+    #[serde(rename = "com.atproto.repo.applyWrites#updateResult")]
+    Update {
+        uri: String,
+        cid: String,
+        #[serde(rename = "validationStatus")]
+        validation_status: Option<String>,
+    },
+    #[serde(rename = "com.atproto.repo.applyWrites#deleteResult")]
+    Delete {},
+}
+
+impl WriteResult {
+    /// URI of the created or updated record; `None` for deletes.
+    pub fn uri(&self) -> Option<&str> {
+        match self {
+            WriteResult::Create { uri, .. } | WriteResult::Update { uri, .. } => Some(uri),
+            WriteResult::Delete {} => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_writes_input() {
+        let writes = [
+            Write::create("eu.atchef.recipe", None, &serde_json::json!({"name": "Soup"})).unwrap(),
+            Write::update("eu.atchef.recipe", "3k1", &serde_json::json!({"name": "Stew"})).unwrap(),
+            Write::delete("eu.atchef.recipe", "3k2"),
+        ];
+        let input = ApplyWritesInput {
+            repo: "did:plc:abc123",
+            writes: &writes,
+            swap_commit: Some("bafyreicommit"),
+            validate: None,
+        };
+
+        let json = serde_json::to_value(&input).unwrap();
+        assert_eq!(json["swapCommit"], "bafyreicommit");
+        assert!(json.get("validate").is_none());
+        assert_eq!(json["writes"][0]["$type"], "com.atproto.repo.applyWrites#create");
+        assert!(json["writes"][0].get("rkey").is_none());
+        assert_eq!(json["writes"][1]["value"]["name"], "Stew");
+        assert_eq!(
+            json["writes"][2],
+            serde_json::json!({
+                "$type": "com.atproto.repo.applyWrites#delete",
+                "collection": "eu.atchef.recipe",
+                "rkey": "3k2",
+            })
+        );
+    }
+
+    #[test]
+    fn test_apply_writes_output() {
+        let output: ApplyWritesOutput = serde_json::from_value(serde_json::json!({
+            "commit": {"cid": "bafyreicommit", "rev": "3kabc"},
+            "results": [
+                {
+                    "$type": "com.atproto.repo.applyWrites#createResult",
+                    "uri": "at://did:plc:abc123/eu.atchef.recipe/3k3",
+                    "cid": "bafyreirecord",
+                    "validationStatus": "valid",
+                },
+                {"$type": "com.atproto.repo.applyWrites#deleteResult"},
+            ],
+        }))
+        .unwrap();
+
+        assert_eq!(output.commit.unwrap().rev, "3kabc");
+        assert_eq!(output.results[0].uri(), Some("at://did:plc:abc123/eu.atchef.recipe/3k3"));
+        assert_eq!(output.results[1], WriteResult::Delete {});
+    }
+}
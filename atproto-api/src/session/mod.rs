@@ -1,11 +1,16 @@
+mod password;
+
 use async_trait::async_trait;
 
+pub use password::{PasswordSession, PasswordSessionData};
+
 use crate::Error;
 
 /// Trait for providing authentication to API requests.
 ///
 /// Implement this in your OAuth crate to provide DPoP-based authentication,
-/// or use `BearerSession` for simple bearer token auth (app passwords, testing).
+/// use `PasswordSession` to log in with an app password, or `BearerSession`
+/// for a fixed bearer token (testing).
 #[async_trait]
 pub trait Session: Send + Sync {
     /// The authenticated user's DID.
@@ -29,6 +34,15 @@ pub trait Session: Send + Sync {
         url: &str,
         nonce: Option<&str>,
     ) -> Result<Vec<(String, String)>, Error>;
+
+    /// Called when the server rejects the current credentials, e.g. with
+    /// `ExpiredToken` or a 401.
+    ///
+    /// Return `Ok(true)` after obtaining new credentials; the request is then
+    /// sent once more. The default can't refresh and returns `Ok(false)`.
+    async fn refresh(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
 }
 
 /// Simple bearer token session for testing or app passwords.
@@ -74,3 +88,41 @@ impl Session for BearerSession {
         )])
     }
 }
+
+/// Unauthenticated session for public reads against a known PDS.
+///
+/// Sends no authorization headers, so only endpoints that allow anonymous
+/// access (getRecord, listRecords, sync.*) will succeed.
+pub struct AnonymousSession {
+    did: String,
+    pds_url: String,
+}
+
+impl AnonymousSession {
+    pub fn new(did: impl Into<String>, pds_url: impl Into<String>) -> Self {
+        Self {
+            did: did.into(),
+            pds_url: pds_url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Session for AnonymousSession {
+    fn did(&self) -> &str {
+        &self.did
+    }
+
+    fn pds_url(&self) -> &str {
+        &self.pds_url
+    }
+
+    async fn get_auth_headers(
+        &self,
+        _method: &str,
+        _url: &str,
+        _nonce: Option<&str>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        Ok(Vec::new())
+    }
+}
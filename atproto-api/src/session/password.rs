@@ -0,0 +1,440 @@
+use std::sync::RwLock;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Session;
+use crate::xrpc::parse_error_response;
+use crate::Error;
+
+/// Everything needed to resume a `PasswordSession` later, e.g. after a
+/// restart. Contains live credentials, so store it like a password.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordSessionData {
+    pub did: String,
+    pub handle: String,
+    pub pds_url: String,
+    pub access_jwt: String,
+    pub refresh_jwt: String,
+    /// Where the session was created, e.g. an entryway in front of the
+    /// PDS; refreshes go there too. Sessions stored without it refresh at
+    /// `pds_url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+}
+
+/// Called with the new session data after a refresh.
+type OnRefresh = Box<dyn Fn(&PasswordSessionData) + Send + Sync>;
+
+/// Session logged in with an identifier and (app) password through
+/// `com.atproto.server.createSession`.
+///
+/// The access token is renewed through `com.atproto.server.refreshSession`
+/// when the PDS reports it expired. Serializes to `PasswordSessionData`.
+pub struct PasswordSession {
+    http: Client,
+    did: String,
+    handle: String,
+    pds_url: String,
+    service: Option<String>,
+    tokens: RwLock<Tokens>,
+    /// Held for the whole refresh so concurrent requests don't spend the
+    /// refresh token twice
+    refresh_lock: tokio::sync::Mutex<()>,
+    on_refresh: Option<OnRefresh>,
+}
+
+#[derive(Clone)]
+struct Tokens {
+    access_jwt: String,
+    refresh_jwt: String,
+}
+
+#[derive(Serialize)]
+struct CreateSessionInput<'a> {
+    identifier: &'a str,
+    password: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionOutput {
+    did: String,
+    handle: String,
+    access_jwt: String,
+    refresh_jwt: String,
+    did_doc: Option<serde_json::Value>,
+}
+
+impl PasswordSession {
+    /// Log in at `service` (a PDS or entryway URL, e.g. "https://bsky.social").
+    ///
+    /// # Arguments
+    /// * `service` - Where to send createSession
+    /// * `identifier` - Handle, DID or email of the account
+    /// * `password` - Preferably an app password
+    pub async fn login(service: &str, identifier: &str, password: &str) -> Result<Self, Error> {
+        Self::login_with_http_client(Client::new(), service, identifier, password).await
+    }
+
+    /// Log in using a custom HTTP client, which is also used for refreshes.
+    pub async fn login_with_http_client(
+        http: Client,
+        service: &str,
+        identifier: &str,
+        password: &str,
+    ) -> Result<Self, Error> {
+        let service = service.trim_end_matches('/');
+        let resp = http
+            .post(format!("{}/xrpc/com.atproto.server.createSession", service))
+            .json(&CreateSessionInput { identifier, password })
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(parse_error_response(resp).await);
+        }
+        let output: SessionOutput = resp.json().await?;
+
+        // An entryway like bsky.social isn't the account's PDS; the DID
+        // document says where the repo actually lives
+        let pds_url = output
+            .did_doc
+            .as_ref()
+            .and_then(pds_endpoint)
+            .unwrap_or_else(|| service.to_string());
+
+        Ok(Self::resume_with_http_client(
+            http,
+            PasswordSessionData {
+                did: output.did,
+                handle: output.handle,
+                pds_url,
+                access_jwt: output.access_jwt,
+                refresh_jwt: output.refresh_jwt,
+                service: Some(service.to_string()),
+            },
+        ))
+    }
+
+    /// Pick up a session stored with `data()`.
+    pub fn resume(data: PasswordSessionData) -> Self {
+        Self::resume_with_http_client(Client::new(), data)
+    }
+
+    pub fn resume_with_http_client(http: Client, data: PasswordSessionData) -> Self {
+        Self {
+            http,
+            did: data.did,
+            handle: data.handle,
+            pds_url: data.pds_url,
+            service: data.service,
+            tokens: RwLock::new(Tokens {
+                access_jwt: data.access_jwt,
+                refresh_jwt: data.refresh_jwt,
+            }),
+            refresh_lock: tokio::sync::Mutex::new(()),
+            on_refresh: None,
+        }
+    }
+
+    /// Call `callback` with the new session data after every refresh, so
+    /// the rotated refresh token can be persisted.
+    pub fn on_refresh(mut self, callback: impl Fn(&PasswordSessionData) + Send + Sync + 'static) -> Self {
+        self.on_refresh = Some(Box::new(callback));
+        self
+    }
+
+    pub fn handle(&self) -> &str {
+        &self.handle
+    }
+
+    /// Snapshot of the current credentials for persisting.
+    pub fn data(&self) -> PasswordSessionData {
+        let tokens = self.tokens.read().unwrap().clone();
+        PasswordSessionData {
+            did: self.did.clone(),
+            handle: self.handle.clone(),
+            pds_url: self.pds_url.clone(),
+            access_jwt: tokens.access_jwt,
+            refresh_jwt: tokens.refresh_jwt,
+            service: self.service.clone(),
+        }
+    }
+
+    fn access_jwt(&self) -> String {
+        self.tokens.read().unwrap().access_jwt.clone()
+    }
+}
+
+#[async_trait]
+impl Session for PasswordSession {
+    fn did(&self) -> &str {
+        &self.did
+    }
+
+    fn pds_url(&self) -> &str {
+        &self.pds_url
+    }
+
+    async fn get_auth_headers(
+        &self,
+        _method: &str,
+        _url: &str,
+        _nonce: Option<&str>,
+    ) -> Result<Vec<(String, String)>, Error> {
+        Ok(vec![(
+            "Authorization".to_string(),
+            format!("Bearer {}", self.access_jwt()),
+        )])
+    }
+
+    /// A refresh token the PDS no longer accepts returns `Ok(false)`, so the
+    /// caller sees the original auth error and knows to log in again.
+    async fn refresh(&self) -> Result<bool, Error> {
+        let rejected = self.access_jwt();
+        let _guard = self.refresh_lock.lock().await;
+        if self.access_jwt() != rejected {
+            // Another request refreshed while we waited for the lock
+            return Ok(true);
+        }
+
+        let refresh_jwt = self.tokens.read().unwrap().refresh_jwt.clone();
+        let service = self.service.as_deref().unwrap_or(&self.pds_url);
+        let resp = self
+            .http
+            .post(format!("{}/xrpc/com.atproto.server.refreshSession", service.trim_end_matches('/')))
+            .bearer_auth(refresh_jwt)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let err = parse_error_response(resp).await;
+            if err.is_auth_error() {
+                tracing::warn!("Refresh token for {} was rejected: {}", self.did, err);
+                return Ok(false);
+            }
+            return Err(err);
+        }
+        let output: SessionOutput = resp.json().await?;
+
+        *self.tokens.write().unwrap() = Tokens {
+            access_jwt: output.access_jwt,
+            refresh_jwt: output.refresh_jwt,
+        };
+        if let Some(callback) = &self.on_refresh {
+            callback(&self.data());
+        }
+        Ok(true)
+    }
+}
+
+impl Serialize for PasswordSession {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PasswordSession {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        PasswordSessionData::deserialize(deserializer).map(Self::resume)
+    }
+}
+
+/// The `#atproto_pds` service endpoint of a DID document.
+fn pds_endpoint(did_doc: &serde_json::Value) -> Option<String> {
+    did_doc["service"]
+        .as_array()?
+        .iter()
+        .find(|service| {
+            service["id"]
+                .as_str()
+                .is_some_and(|id| id == "#atproto_pds" || id.ends_with("#atproto_pds"))
+        })
+        .and_then(|service| service["serviceEndpoint"].as_str())
+        .map(|endpoint| endpoint.trim_end_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::test_server::{self, Response};
+    use crate::Agent;
+
+    /// A PDS whose first access token has already expired.
+    fn spawn_pds() -> String {
+        test_server::spawn(|request| match request.path() {
+            "/xrpc/com.atproto.server.createSession" => {
+                let input = request.json();
+                if request.method != "POST" || input["password"] != "app-pass" {
+                    return Response::error(401, "AuthenticationRequired");
+                }
+                Response::json(200, serde_json::json!({
+                    "did": "did:plc:abc123",
+                    "handle": input["identifier"],
+                    "accessJwt": "access-1",
+                    "refreshJwt": "refresh-1",
+                }))
+            }
+            "/xrpc/com.atproto.server.refreshSession" => match request.header("authorization") {
+                Some("Bearer refresh-1") => Response::json(200, serde_json::json!({
+                    "did": "did:plc:abc123",
+                    "handle": "alice.test",
+                    "accessJwt": "access-2",
+                    "refreshJwt": "refresh-2",
+                })),
+                _ => Response::error(400, "ExpiredToken"),
+            },
+            "/xrpc/com.atproto.repo.getRecord" => match request.header("authorization") {
+                Some("Bearer access-2") => Response::json(200, serde_json::json!({
+                    "uri": "at://did:plc:abc123/eu.atchef.recipe/r1",
+                    "value": {"name": "Soup"},
+                })),
+                _ => Response::error(400, "ExpiredToken"),
+            },
+            _ => Response::error(404, "MethodNotImplemented"),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_login_and_refresh() {
+        let pds_url = spawn_pds();
+        let refreshed = Arc::new(Mutex::new(None));
+        let seen = refreshed.clone();
+        let session = PasswordSession::login(&pds_url, "alice.test", "app-pass")
+            .await
+            .unwrap()
+            .on_refresh(move |data| *seen.lock().unwrap() = Some(data.clone()));
+        assert_eq!(session.did(), "did:plc:abc123");
+        assert_eq!(session.handle(), "alice.test");
+        assert_eq!(session.pds_url(), pds_url);
+        assert_eq!(session.data().service.as_deref(), Some(pds_url.as_str()));
+
+        let agent = Agent::new(session);
+        let record = agent
+            .repo()
+            .get_record::<serde_json::Value>("did:plc:abc123", "eu.atchef.recipe", "r1")
+            .await
+            .unwrap();
+        assert_eq!(record.value["name"], "Soup");
+
+        let data = agent.session().data();
+        assert_eq!(data.access_jwt, "access-2");
+        assert_eq!(data.refresh_jwt, "refresh-2");
+        assert_eq!(refreshed.lock().unwrap().as_ref(), Some(&data));
+    }
+
+    #[tokio::test]
+    async fn test_login_rejected() {
+        let pds_url = spawn_pds();
+        let err = PasswordSession::login(&pds_url, "alice.test", "wrong")
+            .await
+            .err()
+            .unwrap();
+        assert!(err.is_auth_error());
+    }
+
+    #[tokio::test]
+    async fn test_expired_refresh_token_surfaces_auth_error() {
+        let pds_url = spawn_pds();
+        let session = PasswordSession::resume(PasswordSessionData {
+            did: "did:plc:abc123".into(),
+            handle: "alice.test".into(),
+            pds_url,
+            access_jwt: "access-0".into(),
+            refresh_jwt: "refresh-0".into(),
+            service: None,
+        });
+        let err = Agent::new(session)
+            .repo()
+            .get_record::<serde_json::Value>("did:plc:abc123", "eu.atchef.recipe", "r1")
+            .await
+            .unwrap_err();
+        assert!(err.is_auth_error());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_goes_to_the_login_service() {
+        let pds_url = test_server::spawn(|request| match request.path() {
+            "/xrpc/com.atproto.repo.getRecord" => match request.header("authorization") {
+                Some("Bearer access-2") => Response::json(200, serde_json::json!({
+                    "uri": "at://did:plc:abc123/eu.atchef.recipe/r1",
+                    "value": {"name": "Soup"},
+                })),
+                _ => Response::error(400, "ExpiredToken"),
+            },
+            _ => Response::error(404, "MethodNotImplemented"),
+        });
+        let did_doc = serde_json::json!({
+            "id": "did:plc:abc123",
+            "service": [{"id": "#atproto_pds", "type": "AtprotoPersonalDataServer", "serviceEndpoint": pds_url}],
+        });
+        // An entryway: sessions are created and refreshed here, records live on the PDS
+        let entryway = test_server::spawn(move |request| match request.path() {
+            "/xrpc/com.atproto.server.createSession" => Response::json(200, serde_json::json!({
+                "did": "did:plc:abc123",
+                "handle": "alice.test",
+                "accessJwt": "access-1",
+                "refreshJwt": "refresh-1",
+                "didDoc": did_doc.clone(),
+            })),
+            "/xrpc/com.atproto.server.refreshSession" => match request.header("authorization") {
+                Some("Bearer refresh-1") => Response::json(200, serde_json::json!({
+                    "did": "did:plc:abc123",
+                    "handle": "alice.test",
+                    "accessJwt": "access-2",
+                    "refreshJwt": "refresh-2",
+                })),
+                _ => Response::error(400, "ExpiredToken"),
+            },
+            _ => Response::error(404, "MethodNotImplemented"),
+        });
+
+        let session = PasswordSession::login(&entryway, "alice.test", "app-pass").await.unwrap();
+        assert_eq!(session.pds_url(), pds_url);
+
+        // Resume from storage, as the server does on the next request
+        let stored = serde_json::to_value(&session).unwrap();
+        assert_eq!(stored["service"], entryway.as_str());
+        let agent = Agent::new(serde_json::from_value::<PasswordSession>(stored).unwrap());
+        let record = agent
+            .repo()
+            .get_record::<serde_json::Value>("did:plc:abc123", "eu.atchef.recipe", "r1")
+            .await
+            .unwrap();
+        assert_eq!(record.value["name"], "Soup");
+        assert_eq!(agent.session().data().refresh_jwt, "refresh-2");
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let data = PasswordSessionData {
+            did: "did:plc:abc123".into(),
+            handle: "alice.test".into(),
+            pds_url: "https://pds.example.com".into(),
+            access_jwt: "access".into(),
+            refresh_jwt: "refresh".into(),
+            service: None,
+        };
+        let json = serde_json::to_value(PasswordSession::resume(data.clone())).unwrap();
+        assert_eq!(json["accessJwt"], "access");
+        assert_eq!(json["pdsUrl"], "https://pds.example.com");
+        let session: PasswordSession = serde_json::from_value(json).unwrap();
+        assert_eq!(session.data(), data);
+    }
+
+    #[test]
+    fn test_pds_endpoint() {
+        let did_doc = serde_json::json!({
+            "id": "did:plc:abc123",
+            "service": [
+                {"id": "#atproto_labeler", "type": "AtprotoLabeler", "serviceEndpoint": "https://labeler.example.com"},
+                {"id": "#atproto_pds", "type": "AtprotoPersonalDataServer", "serviceEndpoint": "https://pds.example.com/"},
+            ],
+        });
+        assert_eq!(pds_endpoint(&did_doc).as_deref(), Some("https://pds.example.com"));
+        assert_eq!(pds_endpoint(&serde_json::json!({})), None);
+    }
+}
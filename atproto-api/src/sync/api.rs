@@ -0,0 +1,152 @@
+
+use super::types::*;
+use crate::car::Car;
+use crate::session::Session;
+use crate::types::{Nsid, RecordKey};
+use crate::xrpc::{Transport, XrpcClient};
+use crate::Error;
+
+/// Repository export operations (com.atproto.sync.*)
+pub struct SyncApi<'a, S: Session> {
+    session: &'a S,
+    http: &'a Transport,
+}
+
+impl<'a, S: Session> SyncApi<'a, S> {
+    pub(crate) fn new(session: &'a S, http: &'a Transport) -> Self {
+        Self { session, http }
+    }
+
+    /// Download a whole repo as a CAR file.
+    ///
+    /// # Arguments
+    /// * `did` - The DID of the repo
+    /// * `since` - Only include blocks changed after this revision
+    pub async fn get_repo(&self, did: &str, since: Option<&str>) -> Result<Car, Error> {
+        let client = XrpcClient::new(self.session, self.http);
+        let mut params = vec![("did", did)];
+        if let Some(s) = since {
+            params.push(("since", s));
+        }
+        let bytes = client.get_bytes("com.atproto.sync.getRepo", &params).await?;
+        Car::parse(&bytes)
+    }
+
+    /// Get a record together with the proof of its inclusion in the repo:
+    /// a CAR with the signed commit, the tree nodes leading to the record,
+    /// and the record itself.
+    ///
+    /// # Arguments
+    /// * `did` - The DID of the repo
+    /// * `collection` - The NSID of the collection
+    /// * `rkey` - The record key
+    pub async fn get_record(
+        &self,
+        did: &str,
+        collection: impl AsRef<str>,
+        rkey: impl AsRef<str>,
+    ) -> Result<Car, Error> {
+        let collection = Nsid::new(collection.as_ref())?;
+        let rkey = RecordKey::new(rkey.as_ref())?;
+        let client = XrpcClient::new(self.session, self.http);
+        let bytes = client
+            .get_bytes(
+                "com.atproto.sync.getRecord",
+                &[("did", did), ("collection", collection.as_str()), ("rkey", rkey.as_str())],
+            )
+            .await?;
+        Car::parse(&bytes)
+    }
+
+    /// Download a blob.
+    ///
+    /// # Arguments
+    /// * `did` - The DID of the repo
+    /// * `cid` - The CID of the blob
+    pub async fn get_blob(&self, did: &str, cid: &str) -> Result<Vec<u8>, Error> {
+        let client = XrpcClient::new(self.session, self.http);
+        client
+            .get_bytes("com.atproto.sync.getBlob", &[("did", did), ("cid", cid)])
+            .await
+    }
+
+    /// Download a blob, giving up with `Error::ResponseTooLarge` once it
+    /// turns out to be larger than `max_bytes`.
+    ///
+    /// # Arguments
+    /// * `did` - The DID of the repo
+    /// * `cid` - The CID of the blob
+    /// * `max_bytes` - Largest blob accepted
+    pub async fn get_blob_limited(&self, did: &str, cid: &str, max_bytes: usize) -> Result<Vec<u8>, Error> {
+        let client = XrpcClient::new(self.session, self.http);
+        client
+            .get_bytes_limited("com.atproto.sync.getBlob", &[("did", did), ("cid", cid)], max_bytes)
+            .await
+    }
+
+    /// List the CIDs of the blobs in a repo, with pagination.
+    ///
+    /// # Arguments
+    /// * `did` - The DID of the repo
+    /// * `since` - Only list blobs added after this revision
+    /// * `limit` - Maximum number of CIDs to return
+    /// * `cursor` - Pagination cursor from previous response
+    pub async fn list_blobs(
+        &self,
+        did: &str,
+        since: Option<&str>,
+        limit: Option<u32>,
+        cursor: Option<&str>,
+    ) -> Result<ListBlobsOutput, Error> {
+        let client = XrpcClient::new(self.session, self.http);
+
+        let mut params: Vec<(&str, &str)> = vec![("did", did)];
+
+        if let Some(s) = since {
+            params.push(("since", s));
+        }
+
+        let limit_str;
+        if let Some(l) = limit {
+            limit_str = l.to_string();
+            params.push(("limit", &limit_str));
+        }
+
+        if let Some(c) = cursor {
+            params.push(("cursor", c));
+        }
+
+        client.get("com.atproto.sync.listBlobs", &params).await
+    }
+
+    /// List the repos that have records in a collection, with pagination.
+    /// Served by relays rather than PDSes.
+    ///
+    /// # Arguments
+    /// * `collection` - The NSID of the collection
+    /// * `limit` - Maximum number of repos to return
+    /// * `cursor` - Pagination cursor from previous response
+    pub async fn list_repos_by_collection(
+        &self,
+        collection: impl AsRef<str>,
+        limit: Option<u32>,
+        cursor: Option<&str>,
+    ) -> Result<ListReposByCollectionOutput, Error> {
+        let collection = Nsid::new(collection.as_ref())?;
+        let client = XrpcClient::new(self.session, self.http);
+
+        let mut params: Vec<(&str, &str)> = vec![("collection", collection.as_str())];
+
+        let limit_str;
+        if let Some(l) = limit {
+            limit_str = l.to_string();
+            params.push(("limit", &limit_str));
+        }
+
+        if let Some(c) = cursor {
+            params.push(("cursor", c));
+        }
+
+        client.get("com.atproto.sync.listReposByCollection", &params).await
+    }
+}
@@ -0,0 +1,5 @@
+mod api;
+mod types;
+
+pub use api::SyncApi;
+pub use types::*;
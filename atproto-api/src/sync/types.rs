@@ -0,0 +1,21 @@
+use serde::Deserialize;
+
+/// Response from com.atproto.sync.listBlobs
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListBlobsOutput {
+    pub cids: Vec<String>,
+    pub cursor: Option<String>,
+}
+
+/// Response from com.atproto.sync.listReposByCollection
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListReposByCollectionOutput {
+    pub repos: Vec<ListedRepo>,
+    pub cursor: Option<String>,
+}
+
+/// A repo in a listReposByCollection response
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListedRepo {
+    pub did: String,
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 use std::str::FromStr;
 
+use super::{Nsid, RecordKey};
 use crate::Error;
 
 /// An AT Protocol URI.
@@ -14,32 +15,41 @@ pub struct AtUri {
 
 impl AtUri {
     /// Create a new AT URI from components.
+    ///
+    /// Fails on an empty authority, or a record key without a collection.
     pub fn new(
         authority: impl Into<String>,
-        collection: Option<impl Into<String>>,
-        rkey: Option<impl Into<String>>,
-    ) -> Self {
-        Self {
-            authority: authority.into(),
-            collection: collection.map(Into::into),
-            rkey: rkey.map(Into::into),
+        collection: Option<Nsid>,
+        rkey: Option<RecordKey>,
+    ) -> Result<Self, Error> {
+        let authority = authority.into();
+        if authority.is_empty() {
+            return Err(Error::InvalidAtUri("missing authority".into()));
+        }
+        if rkey.is_some() && collection.is_none() {
+            return Err(Error::InvalidAtUri("rkey without collection".into()));
         }
+        Ok(Self {
+            authority,
+            collection: collection.map(|c| c.as_str().to_string()),
+            rkey: rkey.map(|r| r.as_str().to_string()),
+        })
     }
 
     /// Create an AT URI for a specific record.
-    pub fn record(repo: impl Into<String>, collection: impl Into<String>, rkey: impl Into<String>) -> Self {
+    pub fn record(repo: impl Into<String>, collection: Nsid, rkey: RecordKey) -> Self {
         Self {
             authority: repo.into(),
-            collection: Some(collection.into()),
-            rkey: Some(rkey.into()),
+            collection: Some(collection.as_str().to_string()),
+            rkey: Some(rkey.as_str().to_string()),
         }
     }
 
     /// Create an AT URI for a collection.
-    pub fn for_collection(repo: impl Into<String>, collection: impl Into<String>) -> Self {
+    pub fn for_collection(repo: impl Into<String>, collection: Nsid) -> Self {
         Self {
             authority: repo.into(),
-            collection: Some(collection.into()),
+            collection: Some(collection.as_str().to_string()),
             rkey: None,
         }
     }
@@ -64,6 +74,16 @@ impl AtUri {
     pub fn rkey(&self) -> Option<&str> {
         self.rkey.as_deref()
     }
+
+    /// The collection as an `Nsid`, if it is a valid one.
+    pub fn nsid(&self) -> Option<Nsid> {
+        Nsid::new(self.collection.as_deref()?).ok()
+    }
+
+    /// The record key as a `RecordKey`, if it is a valid one.
+    pub fn record_key(&self) -> Option<RecordKey> {
+        RecordKey::new(self.rkey.as_deref()?).ok()
+    }
 }
 
 impl fmt::Display for AtUri {
@@ -98,6 +118,13 @@ impl FromStr for AtUri {
         let collection = parts.next().filter(|s| !s.is_empty()).map(String::from);
         let rkey = parts.next().filter(|s| !s.is_empty()).map(String::from);
 
+        if let Some(collection) = &collection {
+            Nsid::new(collection.as_str())?;
+        }
+        if let Some(rkey) = &rkey {
+            RecordKey::new(rkey.as_str())?;
+        }
+
         // Can't have rkey without collection
         if rkey.is_some() && collection.is_none() {
             return Err(Error::InvalidAtUri("rkey without collection".into()));
@@ -169,5 +196,28 @@ mod tests {
     fn test_invalid_uri() {
         assert!(AtUri::from_str("https://example.com").is_err());
         assert!(AtUri::from_str("at://").is_err());
+        assert!(AtUri::from_str("at://did:plc:abc123/not-an-nsid/3k2f5v").is_err());
+        assert!(AtUri::from_str("at://did:plc:abc123/app.bsky.feed.post/a/b").is_err());
+        assert!(AtUri::from_str("at://did:plc:abc123/app.bsky.feed.post/..").is_err());
+    }
+
+    #[test]
+    fn test_typed_parts() {
+        let collection = Nsid::new("app.bsky.feed.post").unwrap();
+        let rkey = RecordKey::new("3k2f5v").unwrap();
+        let uri = AtUri::record("did:plc:abc123", collection.clone(), rkey.clone());
+        assert_eq!(uri.nsid(), Some(collection));
+        assert_eq!(uri.record_key(), Some(rkey));
+        assert_eq!(uri.to_string(), "at://did:plc:abc123/app.bsky.feed.post/3k2f5v");
+    }
+
+    #[test]
+    fn test_new_validates_shape() {
+        let collection = Nsid::new("app.bsky.feed.post").unwrap();
+        let rkey = RecordKey::new("3k2f5v").unwrap();
+        let uri = AtUri::new("did:plc:abc123", Some(collection.clone()), None).unwrap();
+        assert_eq!(uri.to_string(), "at://did:plc:abc123/app.bsky.feed.post");
+        assert!(AtUri::new("did:plc:abc123", None, Some(rkey.clone())).is_err());
+        assert!(AtUri::new("", Some(collection), Some(rkey)).is_err());
     }
 }
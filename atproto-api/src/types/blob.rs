@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::Cid;
+
 /// Reference to an uploaded blob (image, file, etc.).
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct BlobRef {
@@ -13,16 +15,16 @@ pub struct BlobRef {
 }
 
 impl BlobRef {
-    pub fn new(cid: impl Into<String>, mime_type: impl Into<String>, size: u64) -> Self {
+    pub fn new(cid: Cid, mime_type: impl Into<String>, size: u64) -> Self {
         Self {
             type_marker: "blob".to_string(),
-            cid: CidLink { link: cid.into() },
+            cid: CidLink { link: cid },
             mime_type: mime_type.into(),
             size,
         }
     }
 
-    pub fn cid(&self) -> &str {
+    pub fn cid(&self) -> &Cid {
         &self.cid.link
     }
 }
@@ -31,7 +33,7 @@ impl BlobRef {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CidLink {
     #[serde(rename = "$link")]
-    pub link: String,
+    pub link: Cid,
 }
 
 #[cfg(test)]
@@ -41,7 +43,7 @@ mod tests {
     #[test]
     fn test_blob_ref_serialization() {
         let blob = BlobRef::new(
-            "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku",
+            "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse().unwrap(),
             "image/jpeg",
             12345,
         );
@@ -54,4 +56,10 @@ mod tests {
         let parsed: BlobRef = serde_json::from_str(&json).unwrap();
         assert_eq!(parsed, blob);
     }
+
+    #[test]
+    fn test_blob_ref_rejects_invalid_cid() {
+        let json = r#"{"$type":"blob","ref":{"$link":"not-a-cid"},"mimeType":"image/png","size":1}"#;
+        assert!(serde_json::from_str::<BlobRef>(json).is_err());
+    }
 }
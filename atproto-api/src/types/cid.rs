@@ -0,0 +1,239 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::varint;
+use crate::Error;
+
+/// RFC 4648 base32 alphabet, lowercase, as used by the `b` multibase prefix
+const BASE32_CHARS: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Multihash code for SHA-256
+const SHA2_256: u64 = 0x12;
+
+/// A content identifier (CIDv1) for a record, blob, or repo block.
+///
+/// Stored in its binary form: version, codec, and the multihash of the
+/// content. Displays and serializes as the usual base32 string (`bafy...`).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cid {
+    bytes: Vec<u8>,
+    codec: u64,
+    /// Multihash function code, e.g. `0x12` for SHA-256
+    hash_code: u64,
+    /// Where the digest starts in `bytes`
+    digest_offset: usize,
+}
+
+impl Cid {
+    /// Codec of blobs: the bytes as uploaded
+    pub const RAW: u64 = 0x55;
+    /// Codec of records and repo blocks
+    pub const DAG_CBOR: u64 = 0x71;
+
+    /// Compute the SHA-256 CID of some content.
+    pub fn compute(codec: u64, data: &[u8]) -> Self {
+        let mut bytes = Vec::with_capacity(36);
+        varint::write(1, &mut bytes);
+        varint::write(codec, &mut bytes);
+        varint::write(SHA2_256, &mut bytes);
+        varint::write(32, &mut bytes);
+        bytes.extend_from_slice(&Sha256::digest(data));
+        Self::from_bytes(&bytes).expect("a freshly built CID is valid")
+    }
+
+    /// Whether `data` is the content this CID identifies.
+    ///
+    /// Only SHA-256 CIDs can be checked; any other hash never verifies.
+    pub fn verify(&self, data: &[u8]) -> bool {
+        self.hash_code == SHA2_256 && Sha256::digest(data)[..] == self.bytes[self.digest_offset..]
+    }
+
+    /// Read a binary CID from the front of `data`, advancing past it.
+    pub fn read_bytes(data: &mut &[u8]) -> Result<Self, Error> {
+        let start = *data;
+        let invalid = |reason: &str| Error::InvalidCid(reason.to_string());
+
+        let version = varint::read(data).map_err(|_| invalid("truncated version"))?;
+        if version != 1 {
+            return Err(Error::InvalidCid(format!("unsupported CID version {}", version)));
+        }
+        let codec = varint::read(data).map_err(|_| invalid("truncated codec"))?;
+        let hash_code = varint::read(data).map_err(|_| invalid("truncated multihash"))?;
+        let digest_len = varint::read(data).map_err(|_| invalid("truncated multihash"))? as usize;
+        if data.len() < digest_len {
+            return Err(invalid("truncated digest"));
+        }
+        let digest_offset = start.len() - data.len();
+        *data = &data[digest_len..];
+
+        let len = start.len() - data.len();
+        Ok(Self {
+            bytes: start[..len].to_vec(),
+            codec,
+            hash_code,
+            digest_offset,
+        })
+    }
+
+    /// Parse a binary CID that makes up all of `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut data = bytes;
+        let cid = Self::read_bytes(&mut data)?;
+        if !data.is_empty() {
+            return Err(Error::InvalidCid("trailing bytes after CID".to_string()));
+        }
+        Ok(cid)
+    }
+
+    /// The binary form, as embedded in CAR files and DAG-CBOR links.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Multicodec of the content, e.g. `0x71` for DAG-CBOR or `0x55` for raw.
+    pub fn codec(&self) -> u64 {
+        self.codec
+    }
+}
+
+impl fmt::Display for Cid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut s = String::with_capacity(1 + (self.bytes.len() * 8).div_ceil(5));
+        s.push('b');
+        let mut buffer = 0u32;
+        let mut bits = 0;
+        for &byte in &self.bytes {
+            buffer = (buffer << 8) | u32::from(byte);
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                s.push(BASE32_CHARS[((buffer >> bits) & 0x1f) as usize] as char);
+            }
+        }
+        if bits > 0 {
+            s.push(BASE32_CHARS[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+        }
+        f.write_str(&s)
+    }
+}
+
+impl FromStr for Cid {
+    type Err = Error;
+
+    /// Parse the base32 string form. Only the `b` multibase prefix is
+    /// accepted, as that's the only one ATProto uses.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidCid(s.to_string());
+        let encoded = s.strip_prefix('b').ok_or_else(invalid)?;
+
+        let mut bytes = Vec::with_capacity(encoded.len() * 5 / 8);
+        let mut buffer = 0u32;
+        let mut bits = 0;
+        for c in encoded.bytes() {
+            let value = BASE32_CHARS
+                .iter()
+                .position(|&b| b == c)
+                .ok_or_else(invalid)? as u32;
+            buffer = (buffer << 5) | value;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+            }
+        }
+        // Leftover bits are padding and must be zero
+        if buffer & ((1 << bits) - 1) != 0 {
+            return Err(invalid());
+        }
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl Serialize for Cid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CIDv1, dag-cbor, sha2-256 with an all-zero digest
+    fn zero_cid_bytes() -> Vec<u8> {
+        let mut bytes = vec![0x01, 0x71, 0x12, 0x20];
+        bytes.extend([0u8; 32]);
+        bytes
+    }
+
+    #[test]
+    fn test_cid_display() {
+        let cid = Cid::from_bytes(&zero_cid_bytes()).unwrap();
+        assert_eq!(cid.codec(), 0x71);
+        assert_eq!(
+            cid.to_string(),
+            "bafyreiaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+        );
+    }
+
+    #[test]
+    fn test_cid_read_bytes_advances() {
+        let mut bytes = zero_cid_bytes();
+        bytes.extend(b"block");
+        let mut data = bytes.as_slice();
+        let cid = Cid::read_bytes(&mut data).unwrap();
+        assert_eq!(cid.as_bytes().len(), 36);
+        assert_eq!(data, b"block");
+    }
+
+    #[test]
+    fn test_compute_and_parse() {
+        // Raw CID of empty content
+        let empty = "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku";
+        let cid = Cid::compute(Cid::RAW, b"");
+        assert_eq!(cid.to_string(), empty);
+        assert_eq!(empty.parse::<Cid>().unwrap(), cid);
+        assert!(cid.verify(b""));
+        assert!(!cid.verify(b"tampered"));
+
+        let record = Cid::compute(Cid::DAG_CBOR, b"\xa0");
+        assert_eq!(record.codec(), Cid::DAG_CBOR);
+        assert_eq!(record.to_string().parse::<Cid>().unwrap(), record);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("".parse::<Cid>().is_err());
+        assert!("zQmHash".parse::<Cid>().is_err());
+        assert!("bafkrei!".parse::<Cid>().is_err());
+        assert!("bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyk".parse::<Cid>().is_err());
+    }
+
+    #[test]
+    fn test_serde_as_string() {
+        let cid = Cid::compute(Cid::RAW, b"");
+        let json = serde_json::to_string(&cid).unwrap();
+        assert_eq!(json, format!("\"{}\"", cid));
+        assert_eq!(serde_json::from_str::<Cid>(&json).unwrap(), cid);
+        assert!(serde_json::from_str::<Cid>("\"nope\"").is_err());
+    }
+
+    #[test]
+    fn test_invalid_cid() {
+        assert!(Cid::from_bytes(&[0x01, 0x71, 0x12, 0x20, 0x00]).is_err());
+        assert!(Cid::from_bytes(&[0x12, 0x20]).is_err());
+        let mut bytes = zero_cid_bytes();
+        bytes.push(0);
+        assert!(Cid::from_bytes(&bytes).is_err());
+    }
+}
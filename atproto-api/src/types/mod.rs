@@ -1,9 +1,15 @@
 mod at_uri;
 mod blob;
+mod cid;
 mod did;
+mod nsid;
+mod record_key;
 mod tid;
 
 pub use at_uri::AtUri;
 pub use blob::{BlobRef, CidLink};
+pub use cid::Cid;
 pub use did::{Did, Handle};
+pub use nsid::Nsid;
+pub use record_key::RecordKey;
 pub use tid::Tid;
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Longest NSID the spec allows: a 253-character domain authority, a dot
+/// and a 63-character name.
+const MAX_LENGTH: usize = 317;
+const MAX_AUTHORITY_LENGTH: usize = 253;
+const MAX_SEGMENT_LENGTH: usize = 63;
+
+/// A Namespaced Identifier naming a lexicon, e.g. a record collection.
+/// Format: app.bsky.feed.post
+///
+/// The domain authority (`app.bsky.feed`) is a reversed domain name; the
+/// final segment is the name, which starts with a letter and contains only
+/// letters and digits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Nsid(String);
+
+impl Nsid {
+    pub fn new(nsid: impl Into<String>) -> Result<Self, Error> {
+        let nsid = nsid.into();
+        if is_valid(&nsid) {
+            Ok(Self(nsid))
+        } else {
+            Err(Error::InvalidNsid(nsid))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The reversed domain name, e.g. `app.bsky.feed`.
+    pub fn authority(&self) -> &str {
+        self.0.rsplit_once('.').map_or("", |(authority, _)| authority)
+    }
+
+    /// The final segment, e.g. `post`.
+    pub fn name(&self) -> &str {
+        self.0.rsplit_once('.').map_or("", |(_, name)| name)
+    }
+}
+
+fn is_valid(nsid: &str) -> bool {
+    if nsid.len() > MAX_LENGTH || !nsid.is_ascii() {
+        return false;
+    }
+    let Some((authority, name)) = nsid.rsplit_once('.') else {
+        return false;
+    };
+    if authority.len() > MAX_AUTHORITY_LENGTH {
+        return false;
+    }
+
+    let segments: Vec<&str> = authority.split('.').collect();
+    let domain_ok = segments.len() >= 2
+        && segments.iter().all(|segment| is_domain_segment(segment))
+        // The first segment is a TLD, which can't start with a digit
+        && !segments[0].starts_with(|c: char| c.is_ascii_digit());
+    let name_ok = (1..=MAX_SEGMENT_LENGTH).contains(&name.len())
+        && name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric());
+    domain_ok && name_ok
+}
+
+fn is_domain_segment(segment: &str) -> bool {
+    (1..=MAX_SEGMENT_LENGTH).contains(&segment.len())
+        && !segment.starts_with('-')
+        && !segment.ends_with('-')
+        && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+impl fmt::Display for Nsid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for Nsid {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Nsid::new(s)
+    }
+}
+
+impl TryFrom<String> for Nsid {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Nsid::new(s)
+    }
+}
+
+impl From<Nsid> for String {
+    fn from(nsid: Nsid) -> Self {
+        nsid.0
+    }
+}
+
+impl AsRef<str> for Nsid {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_valid_nsid() {
+        let nsid = Nsid::new("app.bsky.feed.post").unwrap();
+        assert_eq!(nsid.authority(), "app.bsky.feed");
+        assert_eq!(nsid.name(), "post");
+        assert!(Nsid::new("eu.atchef.recipe").is_ok());
+        assert!(Nsid::new("com.example.fooBar").is_ok());
+        assert!(Nsid::new("net.users.bob.ping").is_ok());
+        assert!(Nsid::new("a-0.b-1.c").is_ok());
+        assert!(Nsid::new("cn.8.lex.stuff").is_ok());
+    }
+
+    #[test]
+    fn test_invalid_nsid() {
+        assert!(Nsid::new("com.example").is_err());
+        assert!(Nsid::new("com.example.").is_err());
+        assert!(Nsid::new("com..example.foo").is_err());
+        assert!(Nsid::new("com.example.3").is_err());
+        assert!(Nsid::new("com.example.foo-bar").is_err());
+        assert!(Nsid::new("com.-example.foo").is_err());
+        assert!(Nsid::new("1com.example.foo").is_err());
+        assert!(Nsid::new("com.exa💩mple.foo").is_err());
+        assert!(Nsid::new("com.example.foo*").is_err());
+        assert!(Nsid::new(format!("com.{}.foo", "a".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_validates() {
+        let nsid: Nsid = serde_json::from_str("\"eu.atchef.recipe\"").unwrap();
+        assert_eq!(serde_json::to_string(&nsid).unwrap(), "\"eu.atchef.recipe\"");
+        assert!(serde_json::from_str::<Nsid>("\"not an nsid\"").is_err());
+    }
+
+    /// Domain segments and names as the spec's reference regex defines them
+    fn valid_nsid() -> impl Strategy<Value = String> {
+        (
+            "[a-zA-Z]([a-zA-Z0-9-]{0,20}[a-zA-Z0-9])?",
+            prop::collection::vec("[a-zA-Z0-9]([a-zA-Z0-9-]{0,20}[a-zA-Z0-9])?", 1..5),
+            "[a-zA-Z][a-zA-Z0-9]{0,30}",
+        )
+            .prop_map(|(tld, domain, name)| format!("{}.{}.{}", tld, domain.join("."), name))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_valid_nsids_roundtrip(s in valid_nsid()) {
+            let nsid = Nsid::new(s.clone()).unwrap();
+            prop_assert_eq!(nsid.to_string(), s.clone());
+            prop_assert_eq!(format!("{}.{}", nsid.authority(), nsid.name()), s);
+        }
+
+        #[test]
+        fn prop_foreign_characters_rejected(s in valid_nsid(), c in "[^a-zA-Z0-9.-]", at in any::<prop::sample::Index>()) {
+            let mut chars: Vec<char> = s.chars().collect();
+            chars.insert(at.index(chars.len() + 1), c.chars().next().unwrap());
+            prop_assert!(Nsid::new(chars.into_iter().collect::<String>()).is_err());
+        }
+
+        #[test]
+        fn prop_parse_never_panics(s in "\\PC*") {
+            if let Ok(nsid) = Nsid::new(s.clone()) {
+                prop_assert!(nsid.as_str().len() <= MAX_LENGTH);
+                prop_assert!(nsid.as_str().split('.').count() >= 3);
+            }
+        }
+    }
+}
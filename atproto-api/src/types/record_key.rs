@@ -0,0 +1,141 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+const MAX_LENGTH: usize = 512;
+
+/// The key of a record within a collection.
+/// Format: 1-512 characters of `A-Za-z0-9 . - _ : ~`, but not `.` or `..`
+///
+/// Usually a TID (`3k2f5v...`) or a fixed key like `self`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RecordKey(String);
+
+impl RecordKey {
+    pub fn new(rkey: impl Into<String>) -> Result<Self, Error> {
+        let rkey = rkey.into();
+        let valid = (1..=MAX_LENGTH).contains(&rkey.len())
+            && rkey != "."
+            && rkey != ".."
+            && rkey
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | ':' | '~'));
+        if valid {
+            Ok(Self(rkey))
+        } else {
+            Err(Error::InvalidRecordKey(rkey))
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RecordKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for RecordKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecordKey::new(s)
+    }
+}
+
+impl TryFrom<String> for RecordKey {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        RecordKey::new(s)
+    }
+}
+
+impl From<RecordKey> for String {
+    fn from(rkey: RecordKey) -> Self {
+        rkey.0
+    }
+}
+
+impl From<super::Tid> for RecordKey {
+    fn from(tid: super::Tid) -> Self {
+        Self(tid.to_string())
+    }
+}
+
+impl AsRef<str> for RecordKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::types::Tid;
+
+    #[test]
+    fn test_valid_record_key() {
+        assert!(RecordKey::new("self").is_ok());
+        assert!(RecordKey::new("3jui7kd54zh2y").is_ok());
+        assert!(RecordKey::new("example.com").is_ok());
+        assert!(RecordKey::new("~1.2-3_").is_ok());
+        assert!(RecordKey::new("dHJ1ZQ").is_ok());
+        assert!(RecordKey::new("pre:fix").is_ok());
+        assert!(RecordKey::new("...").is_ok());
+        assert!(RecordKey::new("a".repeat(512)).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_record_key() {
+        assert!(RecordKey::new("").is_err());
+        assert!(RecordKey::new(".").is_err());
+        assert!(RecordKey::new("..").is_err());
+        assert!(RecordKey::new("alpha/beta").is_err());
+        assert!(RecordKey::new("%2F").is_err());
+        assert!(RecordKey::new("a b").is_err());
+        assert!(RecordKey::new("a?b").is_err());
+        assert!(RecordKey::new("a#b").is_err());
+        assert!(RecordKey::new("a".repeat(513)).is_err());
+    }
+
+    #[test]
+    fn test_tid_is_record_key() {
+        let tid = Tid::now();
+        assert_eq!(RecordKey::from(tid).as_str(), tid.to_string());
+    }
+
+    proptest! {
+        #[test]
+        fn prop_valid_record_keys_roundtrip(s in "[a-zA-Z0-9._:~-]{1,512}") {
+            prop_assume!(s != "." && s != "..");
+            let rkey = RecordKey::new(s.clone()).unwrap();
+            prop_assert_eq!(rkey.to_string(), s.clone());
+            let json = serde_json::to_string(&rkey).unwrap();
+            prop_assert_eq!(serde_json::from_str::<RecordKey>(&json).unwrap(), rkey);
+        }
+
+        #[test]
+        fn prop_foreign_characters_rejected(prefix in "[a-z0-9]{0,8}", c in "[^a-zA-Z0-9._:~-]", suffix in "[a-z0-9]{0,8}") {
+            let rkey = format!("{}{}{}", prefix, c, suffix);
+            prop_assert!(RecordKey::new(rkey).is_err());
+        }
+
+        #[test]
+        fn prop_accepted_keys_are_url_safe(s in "\\PC{0,600}") {
+            if let Ok(rkey) = RecordKey::new(s) {
+                prop_assert!(rkey.as_str().len() <= MAX_LENGTH);
+                prop_assert!(!rkey.as_str().contains(['/', '?', '#', '%']));
+            }
+        }
+    }
+}
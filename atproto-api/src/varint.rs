@@ -0,0 +1,54 @@
+use crate::Error;
+
+/// Read an unsigned LEB128 varint from the front of `data`, advancing past it.
+///
+/// Used for CID fields and CAR section lengths.
+pub(crate) fn read(data: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data
+            .split_first()
+            .ok_or_else(|| Error::Internal("truncated varint".to_string()))?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::Internal("varint too long".to_string()))
+}
+
+/// Append `value` as an unsigned LEB128 varint.
+pub(crate) fn write(mut value: u64, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0, 1, 127, 128, 300, 16_384, u64::from(u32::MAX)] {
+            let mut buf = Vec::new();
+            write(value, &mut buf);
+            let mut data = buf.as_slice();
+            assert_eq!(read(&mut data).unwrap(), value);
+            assert!(data.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_truncated_varint() {
+        let mut data: &[u8] = &[0x80, 0x80];
+        assert!(read(&mut data).is_err());
+    }
+}
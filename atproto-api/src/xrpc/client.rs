@@ -1,92 +1,274 @@
-use reqwest::Client;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use reqwest::{Client, StatusCode};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use url::Url;
 
-use crate::error::XrpcErrorResponse;
+use super::retry::{requested_delay, RetryPolicy};
+use crate::error::{XrpcErrorCode, XrpcErrorResponse};
 use crate::session::Session;
 use crate::Error;
 
+/// The HTTP client plus the request policy an `Agent` was built with.
+///
+/// Shared by every API group of one agent, so a rate limit seen on one call
+/// delays the next call too.
+pub struct Transport {
+    client: Client,
+    retry: RetryPolicy,
+    timeout: Option<Duration>,
+    /// Set when the server reports its rate-limit window is used up
+    blocked_until: Mutex<Option<Instant>>,
+}
+
+impl Transport {
+    pub fn new(client: Client, retry: RetryPolicy, timeout: Option<Duration>) -> Self {
+        Self {
+            client,
+            retry,
+            timeout,
+            blocked_until: Mutex::new(None),
+        }
+    }
+
+    /// Wait out a rate-limit window reported by an earlier response, unless
+    /// it is longer than the policy is willing to wait.
+    async fn wait_for_rate_limit(&self) {
+        let blocked_until = *self.blocked_until.lock().unwrap();
+        if let Some(until) = blocked_until {
+            let wait = until.saturating_duration_since(Instant::now());
+            if !wait.is_zero() && wait <= self.retry.max_backoff {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+
+    fn note_rate_limit(&self, resp: &reqwest::Response) {
+        let mut blocked_until = self.blocked_until.lock().unwrap();
+        *blocked_until = requested_delay(resp.headers(), SystemTime::now())
+            .map(|delay| Instant::now() + delay);
+    }
+
+    /// GET a URL that isn't an XRPC endpoint of a session's PDS, e.g. a
+    /// DID document, with the same timeout, rate-limit and retry handling
+    /// as XRPC calls.
+    ///
+    /// Returns the last response whatever its status; only network errors
+    /// are returned as `Err`.
+    pub(crate) async fn get(&self, url: Url) -> Result<reqwest::Response, Error> {
+        self.get_with_policy(url, &self.retry).await
+    }
+
+    /// Like `get`, but a single attempt, for probes where a failure just
+    /// means trying something else.
+    pub(crate) async fn get_once(&self, url: Url) -> Result<reqwest::Response, Error> {
+        self.get_with_policy(url, &RetryPolicy::none()).await
+    }
+
+    async fn get_with_policy(&self, url: Url, policy: &RetryPolicy) -> Result<reqwest::Response, Error> {
+        let mut retries = 0;
+        loop {
+            self.wait_for_rate_limit().await;
+            let mut request = self.client.get(url.clone());
+            if let Some(timeout) = self.timeout {
+                request = request.timeout(timeout);
+            }
+
+            let delay = match request.send().await {
+                Ok(resp) => {
+                    self.note_rate_limit(&resp);
+                    let status = resp.status();
+                    let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+                    if !retryable || retries >= policy.max_retries {
+                        return Ok(resp);
+                    }
+                    let delay = requested_delay(resp.headers(), SystemTime::now())
+                        .unwrap_or_else(|| policy.backoff(retries));
+                    if delay > policy.max_backoff {
+                        return Ok(resp);
+                    }
+                    delay
+                }
+                Err(e) => {
+                    if !(e.is_connect() || e.is_timeout()) || retries >= policy.max_retries {
+                        return Err(e.into());
+                    }
+                    policy.backoff(retries)
+                }
+            };
+            retries += 1;
+            tracing::debug!("GET {} failed, retrying in {:?}", url, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
 /// HTTP client for XRPC requests with session-based authentication.
 pub struct XrpcClient<'a, S: Session> {
     session: &'a S,
-    http: &'a Client,
+    http: &'a Transport,
 }
 
 impl<'a, S: Session> XrpcClient<'a, S> {
-    pub fn new(session: &'a S, http: &'a Client) -> Self {
+    pub fn new(session: &'a S, http: &'a Transport) -> Self {
         Self { session, http }
     }
 
     pub async fn get<T: DeserializeOwned>(&self, nsid: &str, params: &[(&str, &str)]) -> Result<T, Error> {
         let url = self.build_url(nsid, params)?;
-        let resp = self.with_dpop_retry("GET", &url, |headers| {
-            apply_headers(self.http.get(url.clone()), headers)
+        let resp = self.send("GET", &url, true, |headers| {
+            apply_headers(self.http.client.get(url.clone()), headers)
         }).await?;
-        parse_json(resp).await
+        Ok(resp.json().await?)
+    }
+
+    /// GET an endpoint that responds with binary data, e.g. a CAR file or blob.
+    pub async fn get_bytes(&self, nsid: &str, params: &[(&str, &str)]) -> Result<Vec<u8>, Error> {
+        let url = self.build_url(nsid, params)?;
+        let resp = self.send("GET", &url, true, |headers| {
+            apply_headers(self.http.client.get(url.clone()), headers)
+        }).await?;
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    /// Like `get_bytes`, but fails with `Error::ResponseTooLarge` as soon
+    /// as the body turns out to be longer than `max_bytes`.
+    pub async fn get_bytes_limited(
+        &self,
+        nsid: &str,
+        params: &[(&str, &str)],
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let url = self.build_url(nsid, params)?;
+        let mut resp = self.send("GET", &url, true, |headers| {
+            apply_headers(self.http.client.get(url.clone()), headers)
+        }).await?;
+        if resp.content_length().is_some_and(|len| len > max_bytes as u64) {
+            return Err(Error::ResponseTooLarge(max_bytes));
+        }
+        // Content-Length may be missing or wrong, so enforce the limit while reading
+        let mut data = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            if data.len() + chunk.len() > max_bytes {
+                return Err(Error::ResponseTooLarge(max_bytes));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data)
     }
 
     pub async fn post<I: Serialize, O: DeserializeOwned>(&self, nsid: &str, body: &I) -> Result<O, Error> {
         let url = self.build_url(nsid, &[])?;
         let body_json = serde_json::to_value(body)
             .map_err(|e| Error::Internal(format!("Failed to serialize body: {}", e)))?;
-        let resp = self.with_dpop_retry("POST", &url, |headers| {
-            apply_headers(self.http.post(url.clone()).json(&body_json), headers)
+        let resp = self.send("POST", &url, false, |headers| {
+            apply_headers(self.http.client.post(url.clone()).json(&body_json), headers)
         }).await?;
-        parse_json(resp).await
+        Ok(resp.json().await?)
     }
 
     pub async fn post_bytes<O: DeserializeOwned>(&self, nsid: &str, data: Vec<u8>, content_type: &str) -> Result<O, Error> {
         let url = self.build_url(nsid, &[])?;
-        let resp = self.with_dpop_retry("POST", &url, |headers| {
+        let resp = self.send("POST", &url, false, |headers| {
             apply_headers(
-                self.http.post(url.clone()).header("Content-Type", content_type).body(data.clone()),
+                self.http.client.post(url.clone()).header("Content-Type", content_type).body(data.clone()),
                 headers,
             )
         }).await?;
-        parse_json(resp).await
+        Ok(resp.json().await?)
     }
 
     pub async fn post_no_response<I: Serialize>(&self, nsid: &str, body: &I) -> Result<(), Error> {
         let url = self.build_url(nsid, &[])?;
         let body_json = serde_json::to_value(body)
             .map_err(|e| Error::Internal(format!("Failed to serialize body: {}", e)))?;
-        let resp = self.with_dpop_retry("POST", &url, |headers| {
-            apply_headers(self.http.post(url.clone()).json(&body_json), headers)
+        self.send("POST", &url, false, |headers| {
+            apply_headers(self.http.client.post(url.clone()).json(&body_json), headers)
         }).await?;
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(parse_error_response(resp).await)
-        }
+        Ok(())
     }
 
-    /// Sends a request, retrying once with a DPoP nonce if the server returns 401.
+    /// Sends a request, answering a DPoP nonce challenge, retrying transient
+    /// failures according to the agent's `RetryPolicy`, and refreshing the
+    /// session once if the server rejects its credentials.
     ///
     /// `build` is called with auth headers and returns a ready-to-send `RequestBuilder`.
-    /// It may be called twice (initial attempt + nonce retry), so captured data must be cloneable.
-    async fn with_dpop_retry<F>(&self, method: &str, url: &Url, build: F) -> Result<reqwest::Response, Error>
+    /// It is called once per attempt, so captured data must be cloneable. Only
+    /// `idempotent` requests are retried after a 5xx or a timeout.
+    ///
+    /// Returns the response only if it succeeded; error responses are parsed
+    /// into `Error::Xrpc`.
+    async fn send<F>(&self, method: &str, url: &Url, idempotent: bool, build: F) -> Result<reqwest::Response, Error>
     where
         F: Fn(Vec<(String, String)>) -> reqwest::RequestBuilder,
     {
-        let headers = self.session.get_auth_headers(method, url.as_str(), None).await?;
-        let resp = build(headers).send().await?;
+        let policy = &self.http.retry;
+        let mut nonce: Option<String> = None;
+        let mut retries = 0;
+        let mut refreshed = false;
 
-        if resp.status() != 401 {
-            return Ok(resp);
-        }
+        loop {
+            self.http.wait_for_rate_limit().await;
+
+            let headers = self.session.get_auth_headers(method, url.as_str(), nonce.as_deref()).await?;
+            let mut request = build(headers);
+            if let Some(timeout) = self.http.timeout {
+                request = request.timeout(timeout);
+            }
+
+            let resp = match request.send().await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    let retryable = e.is_connect() || (idempotent && e.is_timeout());
+                    if !retryable || retries >= policy.max_retries {
+                        return Err(e.into());
+                    }
+                    let delay = policy.backoff(retries);
+                    retries += 1;
+                    tracing::debug!("{} {} failed ({}), retrying in {:?}", method, url.path(), e, delay);
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            };
+            self.http.note_rate_limit(&resp);
+
+            let status = resp.status();
+            if status == StatusCode::UNAUTHORIZED && nonce.is_none() {
+                // The nonce challenge doesn't count against the retry budget
+                let challenge = resp.headers()
+                    .get("DPoP-Nonce")
+                    .and_then(|v| v.to_str().ok())
+                    .map(|s| s.to_owned());
+                if challenge.is_some() {
+                    nonce = challenge;
+                    continue;
+                }
+            }
 
-        let nonce = resp.headers()
-            .get("DPoP-Nonce")
-            .and_then(|v| v.to_str().ok())
-            .map(|s| s.to_owned());
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || (idempotent && status.is_server_error());
+            if retryable && retries < policy.max_retries {
+                let delay = requested_delay(resp.headers(), SystemTime::now())
+                    .unwrap_or_else(|| policy.backoff(retries));
+                if delay <= policy.max_backoff {
+                    retries += 1;
+                    tracing::debug!("{} {} returned {}, retrying in {:?}", method, url.path(), status, delay);
+                    tokio::time::sleep(delay).await;
+                    continue;
+                }
+            }
 
-        match nonce {
-            Some(nonce) => {
-                let headers = self.session.get_auth_headers(method, url.as_str(), Some(&nonce)).await?;
-                Ok(build(headers).send().await?)
+            if status.is_success() {
+                return Ok(resp);
+            }
+            let err = parse_error_response(resp).await;
+            if err.is_auth_error() && !refreshed && self.session.refresh().await? {
+                // Send once more with the renewed credentials
+                refreshed = true;
+                continue;
             }
-            None => Ok(resp),
+            return Err(err);
         }
     }
 
@@ -107,20 +289,227 @@ fn apply_headers(mut req: reqwest::RequestBuilder, headers: Vec<(String, String)
     req
 }
 
-async fn parse_json<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T, Error> {
-    if resp.status().is_success() {
-        Ok(resp.json().await?)
-    } else {
-        Err(parse_error_response(resp).await)
-    }
-}
-
-async fn parse_error_response(resp: reqwest::Response) -> Error {
+pub(crate) async fn parse_error_response(resp: reqwest::Response) -> Error {
     let status = resp.status().as_u16();
     let body = resp.text().await.unwrap_or_default();
     if let Ok(err) = serde_json::from_str::<XrpcErrorResponse>(&body) {
-        Error::Xrpc { status, error: err.error, message: err.message }
+        Error::Xrpc { status, error: err.error.as_str().into(), message: err.message }
     } else {
-        Error::Xrpc { status, error: "Unknown".to_string(), message: Some(body) }
+        Error::Xrpc { status, error: XrpcErrorCode::Other("Unknown".to_string()), message: Some(body) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::session::AnonymousSession;
+    use crate::test_server::{self, Response};
+
+    /// Answers successive requests with the given `(status, Retry-After)`
+    /// responses, repeating the last one, and counts the requests served.
+    /// Error responses carry `error` as their XRPC error code.
+    fn spawn_scripted(responses: Vec<(u16, &'static str)>, error: &'static str) -> (String, Arc<AtomicUsize>) {
+        let served = Arc::new(AtomicUsize::new(0));
+        let count = served.clone();
+        let url = test_server::spawn(move |_| {
+            let n = count.fetch_add(1, Ordering::SeqCst);
+            let (status, retry_after) = responses[n.min(responses.len() - 1)];
+            let response = if status == 200 {
+                Response::json(200, serde_json::json!({ "ok": true }))
+            } else {
+                Response::error(status, error)
+            };
+            match retry_after {
+                "" => response,
+                seconds => response.header("Retry-After", seconds),
+            }
+        });
+        (url, served)
+    }
+
+    fn transport(max_retries: u32) -> Transport {
+        let retry = RetryPolicy {
+            max_retries,
+            min_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_secs(2),
+        };
+        Transport::new(Client::new(), retry, None)
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_server_errors() {
+        let (pds_url, served) = spawn_scripted(vec![(503, ""), (502, ""), (200, "")], "UpstreamFailure");
+        let session = AnonymousSession::new("did:plc:abc123", pds_url);
+        let http = transport(3);
+
+        let value: serde_json::Value = XrpcClient::new(&session, &http)
+            .get("com.example.ping", &[])
+            .await
+            .unwrap();
+        assert_eq!(value["ok"], true);
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_plain_get_retries_server_errors() {
+        let (url, served) = spawn_scripted(vec![(503, ""), (429, "0"), (200, "")], "UpstreamFailure");
+        let http = transport(3);
+
+        let resp = http.get(Url::parse(&url).unwrap()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+
+        let (url, served) = spawn_scripted(vec![(503, "")], "UpstreamFailure");
+        let resp = http.get_once(Url::parse(&url).unwrap()).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_bytes_limited() {
+        let url = test_server::spawn(|_| Response::json(200, serde_json::json!({ "data": "0123456789" })));
+        let session = AnonymousSession::new("did:plc:abc123", url);
+        let http = transport(0);
+        let client = XrpcClient::new(&session, &http);
+
+        let body = client.get_bytes_limited("com.example.blob", &[], 1000).await.unwrap();
+        assert_eq!(body, br#"{"data":"0123456789"}"#);
+        let err = client.get_bytes_limited("com.example.blob", &[], 10).await.unwrap_err();
+        assert!(matches!(err, Error::ResponseTooLarge(10)));
+    }
+
+    #[tokio::test]
+    async fn test_retries_are_bounded() {
+        let (pds_url, served) = spawn_scripted(vec![(503, "")], "UpstreamFailure");
+        let session = AnonymousSession::new("did:plc:abc123", pds_url);
+        let http = transport(2);
+
+        let err = XrpcClient::new(&session, &http)
+            .get::<serde_json::Value>("com.example.ping", &[])
+            .await
+            .unwrap_err();
+        assert!(err.is_retryable());
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_post_not_retried_on_server_error() {
+        let (pds_url, served) = spawn_scripted(vec![(500, ""), (200, "")], "UpstreamFailure");
+        let session = AnonymousSession::new("did:plc:abc123", pds_url);
+        let http = transport(3);
+
+        let result = XrpcClient::new(&session, &http)
+            .post::<_, serde_json::Value>("com.example.write", &serde_json::json!({}))
+            .await;
+        assert!(result.is_err());
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_post_retried_after_rate_limit() {
+        let (pds_url, served) = spawn_scripted(vec![(429, "0"), (200, "")], "UpstreamFailure");
+        let session = AnonymousSession::new("did:plc:abc123", pds_url);
+        let http = transport(3);
+
+        let value: serde_json::Value = XrpcClient::new(&session, &http)
+            .post("com.example.write", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(value["ok"], true);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_long_rate_limit_fails_fast() {
+        let (pds_url, served) = spawn_scripted(vec![(429, "3600"), (200, "")], "UpstreamFailure");
+        let session = AnonymousSession::new("did:plc:abc123", pds_url);
+        let http = transport(3);
+
+        let err = XrpcClient::new(&session, &http)
+            .get::<serde_json::Value>("com.example.ping", &[])
+            .await
+            .unwrap_err();
+        assert_eq!(err.xrpc_code().map(|c| c.as_str()), Some("UpstreamFailure"));
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    /// Counts refreshes and reports whether it managed to refresh.
+    struct RefreshingSession {
+        pds_url: String,
+        can_refresh: bool,
+        refreshes: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl Session for RefreshingSession {
+        fn did(&self) -> &str {
+            "did:plc:abc123"
+        }
+
+        fn pds_url(&self) -> &str {
+            &self.pds_url
+        }
+
+        async fn get_auth_headers(
+            &self,
+            _method: &str,
+            _url: &str,
+            _nonce: Option<&str>,
+        ) -> Result<Vec<(String, String)>, Error> {
+            Ok(Vec::new())
+        }
+
+        async fn refresh(&self) -> Result<bool, Error> {
+            self.refreshes.fetch_add(1, Ordering::SeqCst);
+            Ok(self.can_refresh)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_refreshes_once() {
+        let (pds_url, served) = spawn_scripted(vec![(400, ""), (200, "")], "ExpiredToken");
+        let session = RefreshingSession { pds_url, can_refresh: true, refreshes: AtomicUsize::new(0) };
+        let http = transport(0);
+
+        let value: serde_json::Value = XrpcClient::new(&session, &http)
+            .post("com.example.write", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(value["ok"], true);
+        assert_eq!(session.refreshes.load(Ordering::SeqCst), 1);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_refresh_returns_auth_error() {
+        let (pds_url, served) = spawn_scripted(vec![(401, "")], "InvalidToken");
+        let session = RefreshingSession { pds_url, can_refresh: false, refreshes: AtomicUsize::new(0) };
+        let http = transport(0);
+
+        let err = XrpcClient::new(&session, &http)
+            .get::<serde_json::Value>("com.example.ping", &[])
+            .await
+            .unwrap_err();
+        assert!(err.is_auth_error());
+        assert_eq!(session.refreshes.load(Ordering::SeqCst), 1);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_is_not_repeated() {
+        let (pds_url, served) = spawn_scripted(vec![(400, "")], "ExpiredToken");
+        let session = RefreshingSession { pds_url, can_refresh: true, refreshes: AtomicUsize::new(0) };
+        let http = transport(0);
+
+        let err = XrpcClient::new(&session, &http)
+            .get::<serde_json::Value>("com.example.ping", &[])
+            .await
+            .unwrap_err();
+        assert_eq!(err.xrpc_code(), Some(&XrpcErrorCode::ExpiredToken));
+        assert_eq!(session.refreshes.load(Ordering::SeqCst), 1);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
     }
 }
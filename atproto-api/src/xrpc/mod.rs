@@ -1,3 +1,6 @@
 mod client;
+mod retry;
 
-pub use client::XrpcClient;
+pub(crate) use client::parse_error_response;
+pub use client::{Transport, XrpcClient};
+pub use retry::RetryPolicy;
@@ -0,0 +1,124 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+/// When and how often `XrpcClient` retries a failed request.
+///
+/// Network failures and 5xx responses are retried only for idempotent
+/// calls (GET); a 429 or a connection that never opened is retried for
+/// every call, since the server didn't act on the request.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further retry
+    pub min_backoff: Duration,
+    /// Longest single wait, including waits the server asks for through
+    /// `Retry-After` or `ratelimit-reset`. Longer requested waits fail fast.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            min_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry; only the DPoP nonce challenge is answered.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (0-based).
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        self.min_backoff
+            .saturating_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+            .min(self.max_backoff)
+    }
+}
+
+/// How long the server asked us to wait before the next request.
+///
+/// `Retry-After` takes precedence (delta-seconds or an HTTP date). Otherwise
+/// the `ratelimit-reset` epoch is used, but only once `ratelimit-remaining`
+/// says the window is used up.
+pub(crate) fn requested_delay(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+
+    if let Some(value) = header(RETRY_AFTER.as_str()) {
+        if let Ok(seconds) = value.parse::<u64>() {
+            return Some(Duration::from_secs(seconds));
+        }
+        if let Ok(date) = chrono::DateTime::parse_from_rfc2822(value) {
+            return Some(until(date.timestamp(), now));
+        }
+    }
+
+    let remaining: u64 = header("ratelimit-remaining")?.parse().ok()?;
+    if remaining > 0 {
+        return None;
+    }
+    let reset: i64 = header("ratelimit-reset")?.parse().ok()?;
+    Some(until(reset, now))
+}
+
+fn until(epoch_secs: i64, now: SystemTime) -> Duration {
+    let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    u64::try_from(epoch_secs)
+        .map(|secs| Duration::from_secs(secs).saturating_sub(now))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in pairs {
+            map.insert(*name, value.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_secs(1));
+        assert_eq!(policy.backoff(40), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_requested_delay() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let retry_after = headers(&[("retry-after", "7")]);
+        assert_eq!(requested_delay(&retry_after, now), Some(Duration::from_secs(7)));
+
+        let retry_date = headers(&[("retry-after", "Tue, 14 Nov 2023 22:13:30 GMT")]);
+        assert_eq!(requested_delay(&retry_date, now), Some(Duration::from_secs(10)));
+
+        let exhausted = headers(&[("ratelimit-remaining", "0"), ("ratelimit-reset", "1700000030")]);
+        assert_eq!(requested_delay(&exhausted, now), Some(Duration::from_secs(30)));
+
+        let available = headers(&[("ratelimit-remaining", "12"), ("ratelimit-reset", "1700000030")]);
+        assert_eq!(requested_delay(&available, now), None);
+
+        let past_reset = headers(&[("ratelimit-remaining", "0"), ("ratelimit-reset", "1600000000")]);
+        assert_eq!(requested_delay(&past_reset, now), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,504 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use std::time::Duration;
+
+use anyhow::anyhow;
+use atproto_api::{Agent, AnonymousSession, Collection, ListRecordsOptions};
+use futures_util::{StreamExt, TryStreamExt};
+use sqlx::SqlitePool;
+
+use crate::identity::IdentityResolver;
+use crate::pds::PdsClient;
+use crate::records::{Recipe, RecipeRecord};
+
+/// Public relay that implements `com.atproto.sync.listReposByCollection`.
+pub const DEFAULT_RELAY_URL: &str = "https://relay1.us-east.bsky.network";
+
+#[derive(Clone, Debug)]
+pub struct BackfillConfig {
+    /// Relay used to discover repos that contain recipes.
+    pub relay_url: String,
+    /// How many repos are fetched at the same time.
+    pub concurrency: usize,
+    /// Page size for `listReposByCollection` and `listRecords`.
+    pub page_size: u32,
+    /// How many more passes are made over repos that failed, resuming each
+    /// from its last stored page.
+    pub retry_passes: u32,
+    /// Pause before each retry pass.
+    pub retry_delay: Duration,
+}
+
+impl Default for BackfillConfig {
+    fn default() -> Self {
+        Self {
+            relay_url: DEFAULT_RELAY_URL.to_string(),
+            concurrency: 4,
+            page_size: 100,
+            retry_passes: 2,
+            retry_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Where the list of repos to backfill comes from.
+pub enum BackfillSource {
+    /// Ask the relay for every repo with recipe records.
+    Relay,
+    /// Only these DIDs (re-fetched even if they were backfilled before).
+    Dids(Vec<String>),
+}
+
+/// Imports existing recipe records from the network into the index.
+///
+/// Progress lives in the `backfill_repos` and `backfill_state` tables, so an
+/// interrupted run picks up where it stopped: relay discovery resumes from its
+/// saved cursor and each repo resumes from its last `listRecords` page.
+pub struct Backfill {
+    pds: PdsClient,
+    identity: Arc<IdentityResolver>,
+    pool: SqlitePool,
+    config: BackfillConfig,
+    running: AtomicBool,
+}
+
+#[derive(Default)]
+pub struct BackfillStatus {
+    pub running: bool,
+    pub pending: i64,
+    pub done: i64,
+    pub failed: i64,
+    pub records: i64,
+}
+
+impl Backfill {
+    pub fn new(
+        pds: PdsClient,
+        identity: Arc<IdentityResolver>,
+        pool: SqlitePool,
+        config: BackfillConfig,
+    ) -> Self {
+        Self {
+            pds,
+            identity,
+            pool,
+            config,
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Start a backfill in the background. Returns `false` if one is already running.
+    pub fn start(self: &Arc<Self>, source: BackfillSource) -> bool {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            match this.run(source).await {
+                Ok(()) => tracing::info!("backfill finished"),
+                Err(e) => tracing::error!("backfill failed: {e}"),
+            }
+            this.running.store(false, Ordering::SeqCst);
+        });
+        true
+    }
+
+    pub async fn status(&self) -> anyhow::Result<BackfillStatus> {
+        let rows: Vec<(String, i64, i64)> = sqlx::query_as(
+            "SELECT status, COUNT(*), COALESCE(SUM(records), 0) FROM backfill_repos GROUP BY status",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut status = BackfillStatus {
+            running: self.running.load(Ordering::SeqCst),
+            ..Default::default()
+        };
+        for (state, count, records) in rows {
+            match state.as_str() {
+                "pending" => status.pending = count,
+                "done" => status.done = count,
+                "failed" => status.failed = count,
+                _ => {}
+            }
+            status.records += records;
+        }
+        Ok(status)
+    }
+
+    async fn run(&self, source: BackfillSource) -> anyhow::Result<()> {
+        match source {
+            BackfillSource::Relay => self.discover_from_relay().await?,
+            BackfillSource::Dids(dids) => {
+                for did in dids {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO backfill_repos (did, status, updated_at) VALUES (?, 'pending', ?)
+                        ON CONFLICT(did) DO UPDATE SET status = 'pending', cursor = NULL, records = 0, error = NULL, updated_at = excluded.updated_at
+                        "#,
+                    )
+                    .bind(&did)
+                    .bind(chrono::Utc::now().to_rfc3339())
+                    .execute(&self.pool)
+                    .await?;
+                }
+            }
+        }
+
+        // Repos that failed in an earlier run are picked up again too
+        let pending: Vec<(String, Option<String>)> =
+            sqlx::query_as("SELECT did, cursor FROM backfill_repos WHERE status IN ('pending', 'failed')")
+                .fetch_all(&self.pool)
+                .await?;
+        tracing::info!("backfilling {} repos", pending.len());
+        let mut failed = self.backfill_repos(pending).await;
+
+        for pass in 1..=self.config.retry_passes {
+            if failed.is_empty() {
+                break;
+            }
+            tracing::info!("retrying {} failed repos (pass {pass})", failed.len());
+            tokio::time::sleep(self.config.retry_delay).await;
+            let retry: Vec<(String, Option<String>)> = sqlx::query_as(
+                "SELECT did, cursor FROM backfill_repos WHERE status = 'failed' AND did IN (SELECT value FROM json_each(?))",
+            )
+            .bind(serde_json::to_string(&failed)?)
+            .fetch_all(&self.pool)
+            .await?;
+            failed = self.backfill_repos(retry).await;
+        }
+        Ok(())
+    }
+
+    /// Backfill repos concurrently, each from its saved cursor. Returns the
+    /// DIDs that failed.
+    async fn backfill_repos(&self, repos: Vec<(String, Option<String>)>) -> Vec<String> {
+        futures_util::stream::iter(repos)
+            .map(|(did, cursor)| async move {
+                let result = self.backfill_repo(&did, cursor).await;
+                let failed = result.is_err();
+                if let Err(e) = self.finish_repo(&did, result).await {
+                    tracing::error!("failed to record backfill result for {did}: {e}");
+                }
+                failed.then_some(did)
+            })
+            .buffer_unordered(self.config.concurrency.max(1))
+            .filter_map(|failed| async move { failed })
+            .collect()
+            .await
+    }
+
+    /// Page through the relay's repo list, queueing every DID that has recipes.
+    async fn discover_from_relay(&self) -> anyhow::Result<()> {
+        let mut cursor: Option<String> =
+            sqlx::query_scalar("SELECT relay_cursor FROM backfill_state WHERE id = 1")
+                .fetch_optional(&self.pool)
+                .await?
+                .flatten();
+
+        // The relay isn't any one repo's PDS, so the session has no DID
+        let relay = self.pds.agent(AnonymousSession::new("", self.config.relay_url.as_str()));
+        loop {
+            let page = relay
+                .sync()
+                .list_repos_by_collection(Recipe::NSID, Some(self.config.page_size), cursor.as_deref())
+                .await
+                .map_err(|e| anyhow!("listReposByCollection failed: {e}"))?;
+
+            let mut tx = self.pool.begin().await?;
+            for repo in &page.repos {
+                sqlx::query("INSERT OR IGNORE INTO backfill_repos (did, status, updated_at) VALUES (?, 'pending', ?)")
+                    .bind(&repo.did)
+                    .bind(chrono::Utc::now().to_rfc3339())
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            // Forget the cursor once discovery completes, so the next run finds new repos
+            let next = if page.repos.is_empty() { None } else { page.cursor };
+            sqlx::query("INSERT OR REPLACE INTO backfill_state (id, relay_cursor) VALUES (1, ?)")
+                .bind(&next)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+
+            match next {
+                Some(c) => cursor = Some(c),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    async fn backfill_repo(&self, did: &str, cursor: Option<String>) -> anyhow::Result<()> {
+        let pds_url = self.identity.get_pds_url(did).await?;
+        let handle = self.identity.resolve_did_to_handle(did).await?;
+        let agent = self.pds.agent(AnonymousSession::new(did, pds_url));
+        import_repo(&agent, &self.pool, &handle, cursor, self.config.page_size).await
+    }
+
+    async fn finish_repo(&self, did: &str, result: anyhow::Result<()>) -> anyhow::Result<()> {
+        let (status, error) = match result {
+            Ok(()) => ("done", None),
+            Err(e) => {
+                tracing::warn!("backfill of {did} failed: {e}");
+                ("failed", Some(e.to_string()))
+            }
+        };
+        sqlx::query("UPDATE backfill_repos SET status = ?, error = ?, updated_at = ? WHERE did = ?")
+            .bind(status)
+            .bind(error)
+            .bind(chrono::Utc::now().to_rfc3339())
+            .bind(did)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Copy every recipe in the agent's repo from its PDS into the index.
+///
+/// Each page is upserted in the same transaction that saves the page cursor,
+/// so a restarted backfill continues from the first page not yet stored.
+pub async fn import_repo(
+    agent: &Agent<AnonymousSession>,
+    pool: &SqlitePool,
+    handle: &str,
+    cursor: Option<String>,
+    page_size: u32,
+) -> anyhow::Result<()> {
+    let did = agent.did();
+    let options = ListRecordsOptions {
+        limit: Some(page_size),
+        ..Default::default()
+    };
+    // Records are parsed one by one, so a malformed one doesn't fail its page
+    let mut pages = Box::pin(agent.repo().list_records_pages::<serde_json::Value>(
+        did,
+        Recipe::NSID,
+        options,
+        cursor,
+    ));
+    while let Some(page) = pages.try_next().await? {
+        let mut tx = pool.begin().await?;
+        let mut imported = 0i64;
+        for listed in page.records {
+            let Some(rkey) = listed.uri.rsplit('/').next() else {
+                continue;
+            };
+            let record: RecipeRecord = match serde_json::from_value(listed.value) {
+                Ok(r) => r,
+                Err(e) => {
+                    tracing::warn!("skipping malformed recipe {}: {e}", listed.uri);
+                    continue;
+                }
+            };
+            record.save(&mut *tx, did, handle, rkey).await?;
+            imported += 1;
+        }
+        save_progress(&mut tx, did, page.cursor.as_deref(), imported).await?;
+        tx.commit().await?;
+    }
+    // The last page may still have carried a cursor
+    save_progress(&mut *pool.acquire().await?, did, None, 0).await
+}
+
+async fn save_progress(
+    conn: &mut sqlx::SqliteConnection,
+    did: &str,
+    cursor: Option<&str>,
+    imported: i64,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO backfill_repos (did, status, cursor, records, updated_at) VALUES (?, 'pending', ?, ?, ?)
+        ON CONFLICT(did) DO UPDATE SET cursor = excluded.cursor, records = records + excluded.records, updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(did)
+    .bind(cursor)
+    .bind(imported)
+    .bind(chrono::Utc::now().to_rfc3339())
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atproto_api::RetryPolicy;
+    use axum::{extract::Query, routing::get, Json, Router};
+    use std::collections::HashMap;
+
+    /// A PDS that serves three recipes over two `listRecords` pages, plus one
+    /// malformed record.
+    async fn mock_pds() -> String {
+        async fn list_records(Query(params): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            let recipe = |rkey: &str, name: &str| {
+                serde_json::json!({
+                    "uri": format!("at://did:plc:alice/eu.atchef.recipe/{rkey}"),
+                    "cid": "bafyreib2rxk3rybk3aobmv5cjuql3bm2twh4jo5uxgf5kpqcsgz7soqxwm",
+                    "value": {
+                        "$type": "eu.atchef.recipe",
+                        "name": name,
+                        "content": "Mix @flour{500%g}.",
+                        "portions": 2,
+                        "time": 30,
+                        "createdAt": "2025-01-01T00:00:00Z",
+                    },
+                })
+            };
+            Json(match params.get("cursor").map(String::as_str) {
+                None => serde_json::json!({
+                    "records": [recipe("3k2a", "Bread"), recipe("3k2b", "Soup")],
+                    "cursor": "page2",
+                }),
+                Some("page2") => serde_json::json!({
+                    "records": [
+                        recipe("3k2c", "Pie"),
+                        { "uri": "at://did:plc:alice/eu.atchef.recipe/3k2d", "cid": "x", "value": { "name": 1 } },
+                    ],
+                    "cursor": "page3",
+                }),
+                Some(_) => serde_json::json!({ "records": [] }),
+            })
+        }
+
+        let app = Router::new().route("/xrpc/com.atproto.repo.listRecords", get(list_records));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn test_pool() -> SqlitePool {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        pool
+    }
+
+    #[tokio::test]
+    async fn imports_all_pages_from_mock_pds() {
+        let pds = mock_pds().await;
+        let pool = test_pool().await;
+        let agent = Agent::new(AnonymousSession::new("did:plc:alice", pds));
+
+        import_repo(&agent, &pool, "alice.test", None, 2).await.unwrap();
+
+        let names: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM recipes WHERE author_did = 'did:plc:alice' ORDER BY rkey",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(names, ["Bread", "Soup", "Pie"]);
+
+        let (cursor, records): (Option<String>, i64) =
+            sqlx::query_as("SELECT cursor, records FROM backfill_repos WHERE did = 'did:plc:alice'")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(cursor, None);
+        assert_eq!(records, 3);
+    }
+
+    #[tokio::test]
+    async fn resumes_from_saved_cursor() {
+        let pds = mock_pds().await;
+        let pool = test_pool().await;
+        let agent = Agent::new(AnonymousSession::new("did:plc:alice", pds));
+
+        import_repo(&agent, &pool, "alice.test", Some("page2".into()), 2).await.unwrap();
+
+        let names: Vec<String> = sqlx::query_scalar("SELECT name FROM recipes")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(names, ["Pie"]);
+    }
+
+    #[tokio::test]
+    async fn recipes_missing_their_image_cid_are_requeued() {
+        let pool = test_pool().await;
+        sqlx::query(
+            "INSERT INTO recipes (author_did, rkey, uri, author_handle, name, content, portions, time, created_at, image_mime_type) \
+             VALUES ('did:plc:alice', '3k2a', 'at://did:plc:alice/eu.atchef.recipe/3k2a', 'alice.test', 'Bread', '', 1, 1, '2025-01-01T00:00:00Z', 'image/png')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let status = || async {
+            sqlx::query_as::<_, (String, Option<String>)>("SELECT status, cursor FROM backfill_repos WHERE did = 'did:plc:alice'")
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+
+        // A database from before the repair
+        sqlx::query("PRAGMA user_version = 0").execute(&pool).await.unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        assert_eq!(status().await, ("pending".into(), None));
+
+        // Later restarts leave the backfill's progress alone
+        sqlx::query("UPDATE backfill_repos SET status = 'done', cursor = '3k2a'").execute(&pool).await.unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        assert_eq!(status().await, ("done".into(), Some("3k2a".into())));
+    }
+
+    #[tokio::test]
+    async fn failed_repos_are_retried() {
+        use std::sync::atomic::AtomicUsize;
+
+        use atproto_api::identity::StaticTxtResolver;
+        use axum::extract::{Path, State};
+        use axum::http::StatusCode;
+
+        // The PDS refuses the first request, and the PLC directory points at it
+        async fn list_records(State(calls): State<Arc<AtomicUsize>>) -> Result<Json<serde_json::Value>, StatusCode> {
+            if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            Ok(Json(serde_json::json!({ "records": [] })))
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let pds = url.clone();
+        let did_document = move |Path(did): Path<String>| async move {
+            Json(serde_json::json!({
+                "id": did,
+                "alsoKnownAs": ["at://alice.test"],
+                "service": [{
+                    "id": "#atproto_pds",
+                    "type": "AtprotoPersonalDataServer",
+                    "serviceEndpoint": pds,
+                }],
+            }))
+        };
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route("/xrpc/com.atproto.repo.listRecords", get(list_records))
+            .with_state(calls.clone())
+            .route("/{did}", get(did_document));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let pool = test_pool().await;
+        let pds = PdsClient::new(reqwest::Client::new(), RetryPolicy::none(), Duration::from_secs(5));
+        let resolver = pds
+            .identity_resolver()
+            .dns(Arc::new(StaticTxtResolver::new(&[("_atproto.alice.test.", &["did=did:plc:alice"])])))
+            .plc_directory(url)
+            .build();
+        let identity = Arc::new(IdentityResolver::new(resolver, pool.clone(), Default::default()));
+        let config = BackfillConfig {
+            retry_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let backfill = Backfill::new(pds, identity, pool, config);
+
+        backfill.run(BackfillSource::Dids(vec!["did:plc:alice".into()])).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let status = backfill.status().await.unwrap();
+        assert_eq!((status.done, status.failed), (1, 0));
+    }
+}
@@ -52,6 +52,15 @@ impl BlobCacheService {
         }))
     }
 
+    /// Whether a blob is cached, without counting it as an access
+    pub async fn contains(&self, cid: &str) -> anyhow::Result<bool> {
+        let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM blob_cache WHERE cid = ?)")
+            .bind(cid)
+            .fetch_one(&*self.db)
+            .await?;
+        Ok(exists)
+    }
+
     /// Store a blob in cache, evicting old entries if necessary
     pub async fn store(&self, cid: &str, data: Vec<u8>, mime_type: &str) -> anyhow::Result<()> {
         let size = data.len() as u64;
@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use atproto_api::AnonymousSession;
+use tokio::sync::{mpsc, Semaphore};
+
+use crate::blob_cache::BlobCacheService;
+use crate::identity::{IdentityResolver, Unavailable};
+use crate::pds::PdsClient;
+
+/// `maxSize` of the image blob in the eu.atchef.recipe lexicon.
+pub const MAX_IMAGE_SIZE: usize = 1_000_000;
+
+#[derive(Clone, Debug)]
+pub struct BlobWarmerConfig {
+    /// Number of blobs fetched at the same time.
+    pub concurrency: usize,
+    /// Requests waiting beyond this many are dropped; the image is still
+    /// fetched on first view.
+    pub queue_capacity: usize,
+    /// Attempts per blob, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each further one.
+    pub retry_backoff: Duration,
+}
+
+impl Default for BlobWarmerConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            queue_capacity: 1000,
+            max_attempts: 3,
+            retry_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+struct WarmRequest {
+    did: String,
+    cid: String,
+    mime_type: String,
+}
+
+/// Pre-fetches recipe images into the blob cache as recipes are indexed, so
+/// the first visitor doesn't wait on the author's PDS.
+#[derive(Clone)]
+pub struct BlobWarmer {
+    requests: mpsc::Sender<WarmRequest>,
+    /// CIDs queued or being fetched, to skip duplicate requests
+    pending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl BlobWarmer {
+    pub fn start(
+        config: BlobWarmerConfig,
+        pds: PdsClient,
+        identity: Arc<IdentityResolver>,
+        blob_cache: Arc<BlobCacheService>,
+    ) -> Self {
+        let (requests, mut queue) = mpsc::channel::<WarmRequest>(config.queue_capacity.max(1));
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        let permits = Arc::new(Semaphore::new(config.concurrency.max(1)));
+
+        let worker_pending = pending.clone();
+        tokio::spawn(async move {
+            while let Some(request) = queue.recv().await {
+                let Ok(permit) = permits.clone().acquire_owned().await else {
+                    break;
+                };
+                let config = config.clone();
+                let pds = pds.clone();
+                let identity = identity.clone();
+                let blob_cache = blob_cache.clone();
+                let pending = worker_pending.clone();
+                tokio::spawn(async move {
+                    warm(&config, &pds, &identity, &blob_cache, &request).await;
+                    pending.lock().unwrap().remove(&request.cid);
+                    drop(permit);
+                });
+            }
+        });
+
+        Self { requests, pending }
+    }
+
+    /// Queue an image for warming. Does nothing if the CID is already queued
+    /// or the queue is full.
+    pub fn enqueue(&self, did: &str, cid: &str, mime_type: &str) {
+        if !self.pending.lock().unwrap().insert(cid.to_string()) {
+            return;
+        }
+        let request = WarmRequest {
+            did: did.to_string(),
+            cid: cid.to_string(),
+            mime_type: mime_type.to_string(),
+        };
+        if let Err(e) = self.requests.try_send(request) {
+            tracing::debug!("not warming blob {}: {}", cid, e);
+            self.pending.lock().unwrap().remove(cid);
+        }
+    }
+
+    /// Number of images queued or being fetched.
+    pub fn queue_depth(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+}
+
+/// Outcome of a failed fetch attempt
+#[derive(Debug)]
+enum FetchError {
+    /// Worth trying again later (network error, 5xx, rate limit, identity
+    /// lookup outage)
+    Transient(anyhow::Error),
+    /// Will fail the same way again (4xx, blob too large, CID mismatch, DID
+    /// without a PDS)
+    Permanent(anyhow::Error),
+}
+
+async fn warm(
+    config: &BlobWarmerConfig,
+    pds: &PdsClient,
+    identity: &Arc<IdentityResolver>,
+    blob_cache: &BlobCacheService,
+    request: &WarmRequest,
+) {
+    match blob_cache.contains(&request.cid).await {
+        Ok(true) => return,
+        Ok(false) => {}
+        Err(e) => tracing::warn!("failed to check blob cache for {}: {}", request.cid, e),
+    }
+
+    let mut delay = config.retry_backoff;
+    for attempt in 1..=config.max_attempts {
+        match fetch_blob(pds, identity, &request.did, &request.cid).await {
+            Ok(data) => {
+                match blob_cache.store(&request.cid, data, &request.mime_type).await {
+                    Ok(()) => tracing::debug!("cached recipe image blob: {}", request.cid),
+                    Err(e) => tracing::warn!("failed to cache blob {}: {}", request.cid, e),
+                }
+                return;
+            }
+            Err(FetchError::Permanent(e)) => {
+                tracing::debug!("not warming blob {}: {}", request.cid, e);
+                return;
+            }
+            Err(FetchError::Transient(e)) if attempt < config.max_attempts => {
+                tracing::debug!("fetching blob {} failed (attempt {}), retrying in {:?}: {}", request.cid, attempt, delay, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(FetchError::Transient(e)) => {
+                tracing::warn!("giving up on blob {} after {} attempts: {}", request.cid, attempt, e);
+            }
+        }
+    }
+}
+
+async fn fetch_blob(
+    pds: &PdsClient,
+    identity: &Arc<IdentityResolver>,
+    did: &str,
+    cid: &str,
+) -> Result<Vec<u8>, FetchError> {
+    let expected: atproto_api::Cid = cid.parse().map_err(|e| FetchError::Permanent(anyhow!("{}", e)))?;
+    let pds_url = identity.get_pds_url(did).await.map_err(|e| {
+        if e.is::<Unavailable>() { FetchError::Transient(e) } else { FetchError::Permanent(e) }
+    })?;
+
+    // The agent already retried network errors, 5xx and rate limits; what
+    // still fails that way is worth another attempt later
+    let agent = pds.agent(AnonymousSession::new(did, pds_url.as_str()));
+    let data = agent
+        .sync()
+        .get_blob_limited(did, cid, MAX_IMAGE_SIZE)
+        .await
+        .map_err(|e| {
+            // A connection dropped while reading the body is transient too
+            let transient = e.is_retryable() || matches!(e, atproto_api::Error::Http(_));
+            let e = anyhow!("{} failed: {}", pds_url, e);
+            if transient { FetchError::Transient(e) } else { FetchError::Permanent(e) }
+        })?;
+    if !expected.verify(&data) {
+        return Err(FetchError::Permanent(anyhow!("{} returned content that does not match the CID", pds_url)));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use atproto_api::identity::StaticTxtResolver;
+    use axum::extract::{Path, Query};
+    use axum::http::StatusCode;
+    use axum::response::{IntoResponse, Response};
+    use axum::{Json, Router, routing::get};
+
+    use atproto_api::RetryPolicy;
+
+    use super::*;
+    use crate::identity::IdentityCacheConfig;
+
+    const IMAGE: &[u8] = b"not really a png";
+
+    fn cid_of(data: &[u8]) -> String {
+        atproto_api::Cid::compute(atproto_api::Cid::RAW, data).to_string()
+    }
+
+    fn pds_client(max_retries: u32) -> PdsClient {
+        let retry = RetryPolicy {
+            max_retries,
+            min_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        };
+        PdsClient::new(reqwest::Client::new(), retry, Duration::from_secs(5))
+    }
+
+    /// A PLC directory and a PDS in one server. `did:plc:gone` doesn't exist
+    /// and `did:plc:flaky` is behind a directory outage. The PDS serves
+    /// `IMAGE` and answers for other CIDs as the CID of their name says;
+    /// `once` fails the first time it is asked for.
+    async fn mock_network() -> Arc<IdentityResolver> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let pds = url.clone();
+        let did_document = move |Path(did): Path<String>| async move {
+            match did.as_str() {
+                "did:plc:gone" => return Err(StatusCode::NOT_FOUND),
+                "did:plc:flaky" => return Err(StatusCode::SERVICE_UNAVAILABLE),
+                _ => {}
+            }
+            Ok(Json(serde_json::json!({
+                "id": did,
+                "service": [{
+                    "id": "#atproto_pds",
+                    "type": "AtprotoPersonalDataServer",
+                    "serviceEndpoint": pds,
+                }],
+            })))
+        };
+        async fn get_blob(Query(params): Query<HashMap<String, String>>) -> Response {
+            let cid = &params["cid"];
+            if *cid == cid_of(IMAGE) {
+                IMAGE.into_response()
+            } else if *cid == cid_of(b"busy") {
+                StatusCode::TOO_MANY_REQUESTS.into_response()
+            } else if *cid == cid_of(b"down") {
+                StatusCode::BAD_GATEWAY.into_response()
+            } else if *cid == cid_of(b"huge") {
+                vec![0u8; MAX_IMAGE_SIZE + 1].into_response()
+            } else if *cid == cid_of(b"once") {
+                static SERVED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+                if SERVED.swap(true, std::sync::atomic::Ordering::SeqCst) {
+                    b"once".as_slice().into_response()
+                } else {
+                    StatusCode::SERVICE_UNAVAILABLE.into_response()
+                }
+            } else if *cid == cid_of(b"wrong") {
+                b"something else".as_slice().into_response()
+            } else {
+                StatusCode::NOT_FOUND.into_response()
+            }
+        }
+        let app = Router::new()
+            .route("/xrpc/com.atproto.sync.getBlob", get(get_blob))
+            .route("/{did}", get(did_document));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        let resolver = atproto_api::IdentityResolver::builder()
+            .dns(Arc::new(StaticTxtResolver::new(&[])))
+            .plc_directory(url)
+            .retry_policy(RetryPolicy::none())
+            .build();
+        Arc::new(IdentityResolver::new(resolver, pool, IdentityCacheConfig::default()))
+    }
+
+    #[tokio::test]
+    async fn fetch_failures_are_classified() {
+        let identity = mock_network().await;
+        let pds = pds_client(0);
+        let fetch = |did: &'static str, cid: String| {
+            let (pds, identity) = (pds.clone(), identity.clone());
+            async move { fetch_blob(&pds, &identity, did, &cid).await }
+        };
+        let transient = |result| matches!(result, Err(FetchError::Transient(_)));
+        let permanent = |result| matches!(result, Err(FetchError::Permanent(_)));
+
+        assert_eq!(fetch("did:plc:alice", cid_of(IMAGE)).await.unwrap(), IMAGE);
+        assert!(transient(fetch("did:plc:alice", cid_of(b"busy")).await));
+        assert!(transient(fetch("did:plc:alice", cid_of(b"down")).await));
+        assert!(permanent(fetch("did:plc:alice", cid_of(b"missing")).await));
+        assert!(permanent(fetch("did:plc:alice", cid_of(b"huge")).await));
+        assert!(permanent(fetch("did:plc:alice", "not a cid".into()).await));
+        // An identity outage may pass; a DID that doesn't exist won't
+        assert!(transient(fetch("did:plc:flaky", cid_of(IMAGE)).await));
+        assert!(permanent(fetch("did:plc:gone", cid_of(IMAGE)).await));
+    }
+
+    #[tokio::test]
+    async fn content_must_match_the_cid() {
+        let identity = mock_network().await;
+        let result = fetch_blob(&pds_client(0), &identity, "did:plc:alice", &cid_of(b"wrong")).await;
+        assert!(matches!(result, Err(FetchError::Permanent(_))));
+    }
+
+    #[tokio::test]
+    async fn fetches_follow_the_pds_retry_policy() {
+        let identity = mock_network().await;
+        let data = fetch_blob(&pds_client(1), &identity, "did:plc:alice", &cid_of(b"once")).await.unwrap();
+        assert_eq!(data, b"once");
+    }
+
+    #[tokio::test]
+    async fn duplicate_and_overflowing_requests_are_dropped() {
+        // No worker drains the queue, so it holds whatever was accepted
+        let (requests, mut queue) = mpsc::channel(2);
+        let warmer = BlobWarmer {
+            requests,
+            pending: Default::default(),
+        };
+
+        warmer.enqueue("did:plc:alice", "bafkreia", "image/png");
+        warmer.enqueue("did:plc:alice", "bafkreia", "image/png");
+        warmer.enqueue("did:plc:alice", "bafkreib", "image/png");
+        warmer.enqueue("did:plc:alice", "bafkreic", "image/png");
+        assert_eq!(warmer.queue_depth(), 2);
+
+        let queued: Vec<String> = [queue.recv().await.unwrap(), queue.recv().await.unwrap()]
+            .into_iter()
+            .map(|r| r.cid)
+            .collect();
+        assert_eq!(queued, ["bafkreia", "bafkreib"]);
+        // A dropped request can be queued again later
+        warmer.enqueue("did:plc:alice", "bafkreic", "image/png");
+        assert_eq!(warmer.queue_depth(), 3);
+    }
+}
@@ -1,5 +1,5 @@
 use chrono::{DateTime, Utc};
-use sqlx::SqlitePool;
+use sqlx::{SqliteConnection, SqliteExecutor, SqlitePool};
 
 pub async fn init_db(pool: &SqlitePool) -> anyhow::Result<()> {
     // Migrate old schema (had synthetic 'id' PK) → drop and recreate with composite PK
@@ -56,6 +56,44 @@ pub async fn init_db(pool: &SqlitePool) -> anyhow::Result<()> {
     .execute(pool)
     .await?;
 
+    // Backfill progress: one row per repo, plus the relay discovery cursor
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS backfill_repos (
+            did TEXT PRIMARY KEY,
+            status TEXT NOT NULL,
+            cursor TEXT,
+            records INTEGER NOT NULL DEFAULT 0,
+            error TEXT,
+            updated_at TEXT NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS backfill_state (id INTEGER PRIMARY KEY, relay_cursor TEXT)
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    // Resolved handles and DID documents, see identity::IdentityResolver
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS identity_cache (
+            key TEXT PRIMARY KEY,
+            value TEXT,
+            error TEXT,
+            fetched_at INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
     // Create blob cache table
     sqlx::query(
         r#"
@@ -101,6 +139,49 @@ pub async fn init_db(pool: &SqlitePool) -> anyhow::Result<()> {
         }
     }
 
+    // Recipes of deactivated or taken-down accounts are kept but hidden
+    let has_hidden_column = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM pragma_table_info('recipes') WHERE name = 'hidden'"
+    )
+    .fetch_one(pool)
+    .await? == 1;
+    if !has_hidden_column {
+        sqlx::query("ALTER TABLE recipes ADD COLUMN hidden INTEGER NOT NULL DEFAULT 0")
+            .execute(pool)
+            .await?;
+    }
+
+    // Jetstream used to read image CIDs only from the legacy `image.cid`, so
+    // recipes with current blob refs (`image.ref.$link`) were indexed without
+    // one. Queue their repos once so the next backfill re-reads them; repos
+    // already queued or done keep their progress.
+    let user_version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(pool).await?;
+    if user_version < 1 {
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO backfill_repos (did, status, updated_at)
+            SELECT DISTINCT author_did, 'pending', datetime('now') FROM recipes
+            WHERE image_cid IS NULL AND image_mime_type IS NOT NULL
+            ON CONFLICT(did) DO NOTHING
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("PRAGMA user_version = 1").execute(&mut *tx).await?;
+        tx.commit().await?;
+    }
+
+    // Accounts that are deactivated, taken down or deleted. Kept apart from
+    // the recipes so the state outlives a purge and covers recipes that
+    // arrive later.
+    sqlx::query("CREATE TABLE IF NOT EXISTS hidden_authors (did TEXT PRIMARY KEY)")
+        .execute(pool)
+        .await?;
+    sqlx::query("INSERT OR IGNORE INTO hidden_authors (did) SELECT DISTINCT author_did FROM recipes WHERE hidden = 1")
+        .execute(pool)
+        .await?;
+
     // Create index for recipe images (only after columns exist)
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_recipes_image_cid ON recipes(image_cid)")
         .execute(pool)
@@ -109,32 +190,148 @@ pub async fn init_db(pool: &SqlitePool) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub async fn get_cursor(pool: &SqlitePool) -> anyhow::Result<Option<i64>> {
-    let row: Option<(i64,)> = sqlx::query_as("SELECT cursor FROM sync_cursor WHERE id = 1")
+/// Which event stream a sync cursor belongs to. Jetstream cursors are
+/// timestamps in microseconds and firehose cursors are sequence numbers, so
+/// each stream keeps its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorStream {
+    Jetstream = 1,
+    Firehose = 2,
+}
+
+pub async fn get_cursor(pool: &SqlitePool, stream: CursorStream) -> anyhow::Result<Option<i64>> {
+    let row: Option<(i64,)> = sqlx::query_as("SELECT cursor FROM sync_cursor WHERE id = ?")
+        .bind(stream as i64)
         .fetch_optional(pool)
         .await?;
     Ok(row.map(|(c,)| c))
 }
 
-pub async fn save_cursor(pool: &SqlitePool, cursor: i64) -> anyhow::Result<()> {
-    sqlx::query("INSERT OR REPLACE INTO sync_cursor (id, cursor) VALUES (1, ?)")
+/// Accepts a pool or a transaction, so the cursor can be advanced atomically
+/// with the write it belongs to.
+pub async fn save_cursor<'e>(
+    executor: impl SqliteExecutor<'e>,
+    stream: CursorStream,
+    cursor: i64,
+) -> anyhow::Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO sync_cursor (id, cursor) VALUES (?, ?)")
+        .bind(stream as i64)
         .bind(cursor)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+pub async fn clear_cursor(pool: &SqlitePool, stream: CursorStream) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM sync_cursor WHERE id = ?")
+        .bind(stream as i64)
         .execute(pool)
         .await?;
     Ok(())
 }
 
-pub async fn delete_recipe(pool: &SqlitePool, rkey: &str, author_did: &str) -> anyhow::Result<()> {
+pub async fn delete_recipe<'e>(executor: impl SqliteExecutor<'e>, rkey: &str, author_did: &str) -> anyhow::Result<()> {
     sqlx::query("DELETE FROM recipes WHERE rkey = ? AND author_did = ?")
         .bind(rkey)
         .bind(author_did)
-        .execute(pool)
+        .execute(executor)
         .await?;
     Ok(())
 }
 
-pub async fn save_recipe(
-    pool: &SqlitePool,
+/// Whether we index anything for this DID (recipes, a login or its hidden
+/// state).
+pub async fn is_known_author(pool: &SqlitePool, did: &str) -> anyhow::Result<bool> {
+    let known: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM recipes WHERE author_did = ?) OR EXISTS(SELECT 1 FROM users WHERE did = ?) \
+         OR EXISTS(SELECT 1 FROM hidden_authors WHERE did = ?)",
+    )
+    .bind(did)
+    .bind(did)
+    .bind(did)
+    .fetch_one(pool)
+    .await?;
+    Ok(known)
+}
+
+pub async fn update_author_handle(conn: &mut SqliteConnection, did: &str, handle: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE recipes SET author_handle = ? WHERE author_did = ?")
+        .bind(handle)
+        .bind(did)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("UPDATE users SET handle = ? WHERE did = ?")
+        .bind(handle)
+        .bind(did)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn set_author_hidden(conn: &mut SqliteConnection, did: &str, hidden: bool) -> anyhow::Result<()> {
+    let query = if hidden {
+        "INSERT OR IGNORE INTO hidden_authors (did) VALUES (?)"
+    } else {
+        "DELETE FROM hidden_authors WHERE did = ?"
+    };
+    sqlx::query(query).bind(did).execute(&mut *conn).await?;
+    sqlx::query("UPDATE recipes SET hidden = ? WHERE author_did = ?")
+        .bind(hidden)
+        .bind(did)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Whether the account is deactivated, taken down or deleted, in which case
+/// none of its content may be shown or fetched from its PDS.
+pub async fn is_author_hidden(pool: &SqlitePool, did: &str) -> anyhow::Result<bool> {
+    let hidden: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM hidden_authors WHERE did = ?)")
+        .bind(did)
+        .fetch_one(pool)
+        .await?;
+    Ok(hidden)
+}
+
+/// Whether an image only belongs to recipes of hidden accounts.
+pub async fn is_blob_hidden(pool: &SqlitePool, cid: &str) -> anyhow::Result<bool> {
+    let hidden: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM recipes WHERE image_cid = ?) \
+         AND NOT EXISTS(SELECT 1 FROM recipes WHERE image_cid = ? AND hidden = 0)",
+    )
+    .bind(cid)
+    .bind(cid)
+    .fetch_one(pool)
+    .await?;
+    Ok(hidden)
+}
+
+/// Remove every recipe of a deleted account along with its cached images. The
+/// account stays hidden so its recipes aren't fetched back from its PDS.
+/// Images that a visible recipe of another author also uses stay cached.
+pub async fn purge_author(conn: &mut SqliteConnection, did: &str) -> anyhow::Result<()> {
+    sqlx::query("INSERT OR IGNORE INTO hidden_authors (did) VALUES (?)")
+        .bind(did)
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        "DELETE FROM blob_cache WHERE cid IN (\
+             SELECT image_cid FROM recipes r WHERE author_did = ? AND image_cid IS NOT NULL \
+             AND NOT EXISTS(SELECT 1 FROM recipes WHERE image_cid = r.image_cid AND hidden = 0 AND author_did != ?))",
+    )
+    .bind(did)
+    .bind(did)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("DELETE FROM recipes WHERE author_did = ?")
+        .bind(did)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn save_recipe<'e>(
+    executor: impl SqliteExecutor<'e>,
     uri: &str,
     author_did: &str,
     author_handle: &str,
@@ -152,8 +349,8 @@ pub async fn save_recipe(
 ) -> anyhow::Result<()> {
     sqlx::query(
         r#"
-        INSERT INTO recipes (author_did, rkey, uri, author_handle, name, content, portions, time, created_at, description, prep_time, cook_time, image_cid, image_mime_type)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO recipes (author_did, rkey, uri, author_handle, name, content, portions, time, created_at, description, prep_time, cook_time, image_cid, image_mime_type, hidden)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, EXISTS(SELECT 1 FROM hidden_authors WHERE did = ?))
         ON CONFLICT(author_did, rkey) DO UPDATE SET
             uri = excluded.uri,
             author_handle = excluded.author_handle,
@@ -183,7 +380,8 @@ pub async fn save_recipe(
     .bind(cook_time)
     .bind(image_cid)
     .bind(image_mime_type)
-    .execute(pool)
+    .bind(author_did)
+    .execute(executor)
     .await?;
 
     Ok(())
@@ -192,6 +390,7 @@ pub async fn save_recipe(
 #[derive(sqlx::FromRow)]
 struct SqliteRecipeDetailRow {
     rkey: String,
+    author_did: String,
     author_handle: String,
     name: String,
     content: String,
@@ -207,6 +406,7 @@ struct SqliteRecipeDetailRow {
 
 pub struct RecipeDetailRow {
     pub rkey: String,
+    pub author_did: String,
     pub author_handle: String,
     pub name: String,
     pub content: String,
@@ -220,21 +420,22 @@ pub struct RecipeDetailRow {
     pub image_mime_type: Option<String>,
 }
 
-pub async fn get_recipe(pool: &SqlitePool, author_handle: &str, rkey: &str) -> anyhow::Result<Option<RecipeDetailRow>> {
+pub async fn get_recipe(pool: &SqlitePool, author_did: &str, rkey: &str) -> anyhow::Result<Option<RecipeDetailRow>> {
     let row = sqlx::query_as::<_, SqliteRecipeDetailRow>(
         r#"
-        SELECT rkey, author_handle, name, content, portions, time, created_at, description, prep_time, cook_time, image_cid, image_mime_type
+        SELECT rkey, author_did, author_handle, name, content, portions, time, created_at, description, prep_time, cook_time, image_cid, image_mime_type
         FROM recipes
-        WHERE author_handle = ? AND rkey = ? AND content IS NOT NULL
+        WHERE author_did = ? AND rkey = ? AND content IS NOT NULL AND hidden = 0
         "#,
     )
-    .bind(author_handle)
+    .bind(author_did)
     .bind(rkey)
     .fetch_optional(pool)
     .await?;
 
     Ok(row.map(|r| RecipeDetailRow {
         rkey: r.rkey,
+        author_did: r.author_did,
         author_handle: r.author_handle,
         name: r.name,
         content: r.content,
@@ -254,8 +455,9 @@ pub async fn get_recipe(pool: &SqlitePool, author_handle: &str, rkey: &str) -> a
 pub async fn get_all_recipes(pool: &SqlitePool) -> anyhow::Result<Vec<RecipeRow>> {
     let rows = sqlx::query_as::<_, SqliteRecipeRow>(
         r#"
-        SELECT rkey, author_handle, name, created_at
+        SELECT rkey, author_did, author_handle, name, created_at
         FROM recipes
+        WHERE hidden = 0
         ORDER BY created_at DESC
         "#,
     )
@@ -268,6 +470,7 @@ pub async fn get_all_recipes(pool: &SqlitePool) -> anyhow::Result<Vec<RecipeRow>
 #[derive(sqlx::FromRow)]
 struct SqliteRecipeRow {
     rkey: String,
+    author_did: String,
     author_handle: String,
     name: String,
     created_at: String,
@@ -275,6 +478,7 @@ struct SqliteRecipeRow {
 
 pub struct RecipeRow {
     pub rkey: String,
+    pub author_did: String,
     pub author_handle: String,
     pub name: String,
     pub created_at: DateTime<Utc>,
@@ -284,6 +488,7 @@ impl From<SqliteRecipeRow> for RecipeRow {
     fn from(row: SqliteRecipeRow) -> Self {
         RecipeRow {
             rkey: row.rkey,
+            author_did: row.author_did,
             author_handle: row.author_handle,
             name: row.name,
             created_at: DateTime::parse_from_rfc3339(&row.created_at)
@@ -299,7 +504,7 @@ pub async fn upsert_user(pool: &SqlitePool, did: &str, handle: &str) -> anyhow::
         r#"
         INSERT INTO users (did, handle, first_login_at, last_login_at)
         VALUES (?, ?, ?, ?)
-        ON CONFLICT(did) DO UPDATE SET last_login_at = excluded.last_login_at
+        ON CONFLICT(did) DO UPDATE SET handle = excluded.handle, last_login_at = excluded.last_login_at
         "#,
     )
     .bind(did)
@@ -328,13 +533,13 @@ pub async fn get_all_users(pool: &SqlitePool) -> anyhow::Result<Vec<UserRow>> {
 
 #[derive(sqlx::FromRow)]
 struct SqliteUserRow {
-    #[allow(dead_code)]
     did: String,
     handle: String,
     first_login_at: String,
 }
 
 pub struct UserRow {
+    pub did: String,
     pub handle: String,
     pub joined_at: DateTime<Utc>,
 }
@@ -345,6 +550,7 @@ impl From<SqliteUserRow> for UserRow {
             .map(|dt| dt.with_timezone(&Utc))
             .unwrap_or_else(|_| Utc::now());
         UserRow {
+            did: row.did,
             handle: row.handle,
             joined_at: joined,
         }
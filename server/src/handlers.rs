@@ -1,203 +1,36 @@
-use atproto_api::Agent;
+use atproto_api::{Agent, AnonymousSession, Collection as _, ListRecordsOptions, PasswordSession, PasswordSessionData, RecordKey, Session as _};
+use futures_util::TryStreamExt;
 use axum::{
-    extract::{Multipart, Path, Query, State},
-    http::{header, HeaderMap, StatusCode},
+    extract::{FromRequestParts, Multipart, Path, Query, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
     response::{IntoResponse, Redirect, Response},
     Form, Json,
 };
 use maud::Markup;
 use serde::{Deserialize, Serialize};
 use tower_sessions::Session;
-use jsonwebtoken::jwk::Jwk;
 
-use crate::lexicons::eu::atchef::recipe::RecordData;
-use crate::models::{Recipe, RecipeDetail, ProfileRecord};
-use crate::oauth::{discovery, dpop, pkce, AuthenticatedUser, DpopSession, PendingAuth};
+use crate::login_throttle::Throttled;
+use crate::models::{Recipe, RecipeDetail, RecipeDraft, ProfileRecord};
+use crate::oauth::{discovery, dpop, pkce, token, AuthenticatedUser, Credentials, DpopSession, PendingAuth, UserSession};
+use crate::records::{self, RecipeRecord};
 use crate::views::{base_layout, base_layout_with_user, login_page, recipe_form_page, recipe_list, recipe_page};
 use crate::{AppState, db};
 
 const PENDING_AUTH_KEY: &str = "pending_auth";
 const USER_KEY: &str = "user";
+const DRAFT_KEY: &str = "recipe_draft";
 
 // Image upload configuration
 const MAX_IMAGE_SIZE_BYTES: usize = 1024 * 1024; // 1MB
 const ALLOWED_IMAGE_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp"];
 
-/// Convert from our atproto_api::BlobRef to atrium_api::types::BlobRef
-fn convert_blob_ref(blob_ref: &atproto_api::BlobRef) -> anyhow::Result<atrium_api::types::BlobRef> {
-    use atrium_api::types::{BlobRef, TypedBlobRef, Blob};
-    use std::str::FromStr;
-    
-    let cid = ipld_core::cid::Cid::from_str(blob_ref.cid())
-        .map_err(|e| anyhow::anyhow!("Invalid CID: {}", e))?;
-    
-    Ok(BlobRef::Typed(TypedBlobRef::Blob(Blob {
-        r#ref: atrium_api::types::CidLink(cid),
-        mime_type: blob_ref.mime_type.clone(),
-        size: blob_ref.size as usize,
-    })))
-}
-
-async fn refresh_access_token(
-    client: &reqwest::Client,
-    token_endpoint: &str,
-    dpop_private_key_pem: &str,
-    dpop_public_jwk: &Jwk,
-    client_id: &str,
-    refresh_token: &str,
-) -> anyhow::Result<TokenResponse> {
-    // First attempt without nonce
-    let dpop_proof = dpop::create_proof(
-        dpop_private_key_pem,
-        dpop_public_jwk,
-        "POST",
-        token_endpoint,
-        None,
-        None,
-    )?;
-
-    let response = client
-        .post(token_endpoint)
-        .header("DPoP", &dpop_proof)
-        .form(&[
-            ("grant_type", "refresh_token"),
-            ("refresh_token", refresh_token),
-            ("client_id", client_id),
-        ])
-        .send()
-        .await?;
-
-    // Check if server requires a nonce
-    if response.status() == 400 {
-        if let Some(nonce) = response.headers().get("dpop-nonce") {
-            let nonce_str = nonce.to_str().unwrap_or_default();
-            tracing::debug!("Retrying token refresh with DPoP nonce: {}", nonce_str);
-
-            // Retry with nonce
-            let dpop_proof_with_nonce = dpop::create_proof(
-                dpop_private_key_pem,
-                dpop_public_jwk,
-                "POST",
-                token_endpoint,
-                Some(nonce_str),
-                None,
-            )?;
-
-            let retry_response = client
-                .post(token_endpoint)
-                .header("DPoP", &dpop_proof_with_nonce)
-                .form(&[
-                    ("grant_type", "refresh_token"),
-                    ("refresh_token", refresh_token),
-                    ("client_id", client_id),
-                ])
-                .send()
-                .await?;
-
-            if !retry_response.status().is_success() {
-                let status = retry_response.status();
-                let body = retry_response.text().await.unwrap_or_default();
-                return Err(anyhow::anyhow!("token refresh failed: {} - {}", status, body));
-            }
-
-            return retry_response.json().await.map_err(Into::into);
-        }
-
-        let body = response.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!("token refresh failed: 400 - {}", body));
-    }
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!("token refresh failed: {} - {}", status, body));
-    }
-
-    response.json().await.map_err(Into::into)
-}
-
-async fn exchange_token(
-    client: &reqwest::Client,
-    token_endpoint: &str,
-    dpop_private_key_pem: &str,
-    dpop_public_jwk: &Jwk,
-    client_id: &str,
-    code: &str,
-    redirect_uri: &str,
-    code_verifier: &str,
-) -> anyhow::Result<TokenResponse> {
-    // First attempt without nonce
-    let dpop_proof = dpop::create_proof(
-        dpop_private_key_pem,
-        dpop_public_jwk,
-        "POST",
-        token_endpoint,
-        None,
-        None,
-    )?;
-
-    let response = client
-        .post(token_endpoint)
-        .header("DPoP", &dpop_proof)
-        .form(&[
-            ("grant_type", "authorization_code"),
-            ("code", code),
-            ("redirect_uri", redirect_uri),
-            ("client_id", client_id),
-            ("code_verifier", code_verifier),
-        ])
-        .send()
-        .await?;
-
-    // Check if server requires a nonce
-    if response.status() == 400 {
-        if let Some(nonce) = response.headers().get("dpop-nonce") {
-            let nonce_str = nonce.to_str().unwrap_or_default();
-            tracing::debug!("Retrying token exchange with DPoP nonce: {}", nonce_str);
-
-            // Retry with nonce
-            let dpop_proof_with_nonce = dpop::create_proof(
-                dpop_private_key_pem,
-                dpop_public_jwk,
-                "POST",
-                token_endpoint,
-                Some(nonce_str),
-                None,
-            )?;
-
-            let retry_response = client
-                .post(token_endpoint)
-                .header("DPoP", &dpop_proof_with_nonce)
-                .form(&[
-                    ("grant_type", "authorization_code"),
-                    ("code", code),
-                    ("redirect_uri", redirect_uri),
-                    ("client_id", client_id),
-                    ("code_verifier", code_verifier),
-                ])
-                .send()
-                .await?;
-
-            if !retry_response.status().is_success() {
-                let status = retry_response.status();
-                let body = retry_response.text().await.unwrap_or_default();
-                return Err(anyhow::anyhow!("token exchange failed: {} - {}", status, body));
-            }
-
-            return retry_response.json().await.map_err(Into::into);
-        }
+// Most recipes listed on a public profile page
+const MAX_PROFILE_RECIPES: usize = 500;
 
-        let body = response.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!("token exchange failed: 400 - {}", body));
-    }
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(anyhow::anyhow!("token exchange failed: {} - {}", status, body));
-    }
-
-    response.json().await.map_err(Into::into)
+/// Build an agent for a user's PDS with the configured retry policy and timeout.
+fn pds_agent<S: atproto_api::Session>(state: &AppState, session: S) -> Agent<S> {
+    state.pds.agent(session)
 }
 
 pub async fn home(State(state): State<AppState>, session: Session) -> Markup {
@@ -207,7 +40,7 @@ pub async fn home(State(state): State<AppState>, session: Session) -> Markup {
     
     let mut recipes = Vec::new();
     for row in &db_recipes {
-        let author_info = crate::models::AuthorInfo::basic(row.author_handle.clone());
+        let author_info = crate::models::AuthorInfo::basic(row.author_did.clone(), row.author_handle.clone());
         recipes.push(Recipe::from_db_row(row, author_info));
     }
 
@@ -224,55 +57,16 @@ pub async fn home(State(state): State<AppState>, session: Session) -> Markup {
 
 pub async fn profile(session: Session) -> Response {
     match session.get::<AuthenticatedUser>(USER_KEY).await {
-        Ok(Some(user)) => Redirect::to(&format!("/profile/{}", user.handle)).into_response(),
+        Ok(Some(user)) => Redirect::to(&format!("/profile/{}", user.actor())).into_response(),
         _ => Redirect::to("/login").into_response(),
     }
 }
 
-#[derive(Deserialize)]
-struct ListRecordsValue {
-    name: String,
-    #[serde(rename = "createdAt")]
-    created_at: String,
-}
-
-#[derive(Deserialize)]
-struct ListRecordsRecord {
-    uri: String,
-    value: ListRecordsValue,
-}
-
-#[derive(Deserialize)]
-struct ListRecordsResponse {
-    records: Vec<ListRecordsRecord>,
-}
-
 #[derive(Deserialize)]
 struct ProfileRecordResponse {
     value: crate::models::ProfileRecord,
 }
 
-#[derive(Deserialize)]
-struct GetRecordValue {
-    name: String,
-    content: String,
-    portions: u64,
-    time: u64,
-    #[serde(rename = "createdAt")]
-    created_at: String,
-    description: Option<String>,
-    #[serde(rename = "prepTime")]
-    prep_time: Option<u64>,
-    #[serde(rename = "cookTime")]
-    cook_time: Option<u64>,
-    image: Option<serde_json::Value>,
-}
-
-#[derive(Deserialize)]
-struct GetRecordResponse {
-    value: GetRecordValue,
-}
-
 fn time_ago(created_at: &str) -> String {
     let Ok(dt) = chrono::DateTime::parse_from_rfc3339(created_at) else {
         return "recently".to_string();
@@ -289,15 +83,24 @@ fn time_ago(created_at: &str) -> String {
 pub async fn recipe(
     State(state): State<AppState>,
     session: Session,
-    Path((handle, rkey)): Path<(String, String)>,
-) -> Markup {
+    RecipePath(handle, rkey): RecipePath,
+) -> Response {
     let user = session.get::<AuthenticatedUser>(USER_KEY).await.ok().flatten();
     let result = async {
+        // The URL may name the author by handle or DID; recipes are keyed by DID
+        let did = state.identity.resolve_actor(&handle).await.map_err(unknown_actor)?;
+
+        // Deactivated, taken-down and deleted accounts are neither shown nor
+        // fetched back from their PDS
+        if db::is_author_hidden(&state.sqlite_pool, &did).await? {
+            return Ok(None);
+        }
+
         // Cache-first: try DB before hitting PDS
-        if let Ok(Some(row)) = db::get_recipe(&state.sqlite_pool, &handle, &rkey).await {
-            let author_info = crate::models::AuthorInfo::basic(row.author_handle.clone());
+        if let Ok(Some(row)) = db::get_recipe(&state.sqlite_pool, &did, rkey.as_str()).await {
+            let author_info = crate::models::AuthorInfo::basic(row.author_did.clone(), row.author_handle.clone());
             
-            return Ok(RecipeDetail {
+            return Ok(Some(RecipeDetail {
                 id: row.rkey.clone(),
                 name: row.name,
                 content: row.content,
@@ -311,92 +114,131 @@ pub async fn recipe(
                 cook_time: row.cook_time,
                 image_cid: row.image_cid,
                 image_mime_type: row.image_mime_type,
-            });
+            }));
         }
 
-        let did = discovery::resolve_handle(&state.http_client, &handle).await?;
-        let pds_url = discovery::get_pds_url(&state.http_client, &did).await?;
-        let url = format!(
-            "{}/xrpc/com.atproto.repo.getRecord?repo={}&collection=eu.atchef.recipe&rkey={}",
-            pds_url.trim_end_matches('/'),
-            urlencoding::encode(&did),
-            urlencoding::encode(&rkey),
-        );
-        let response = state.http_client.get(&url).send().await?;
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("record not found: {}", response.status()));
+        let pds_url = state.identity.get_pds_url(&did).await.map_err(unknown_actor)?;
+        let agent = pds_agent(&state, AnonymousSession::new(did.clone(), pds_url));
+        let record = agent.repo().get::<records::Recipe>(&rkey).await?.value;
+
+        let author_handle = state.identity.resolve_did_to_handle(&did).await?;
+        if let Err(e) = record.save(&state.sqlite_pool, &did, &author_handle, rkey.as_str()).await {
+            tracing::warn!("Failed to cache recipe {}/{}: {}", did, rkey, e);
         }
-        let record: GetRecordResponse = response.json().await?;
 
-        let author_info = crate::models::AuthorInfo::basic(handle.clone());
-        
+        let author_info = crate::models::AuthorInfo::basic(did.clone(), author_handle);
         let recipe_detail = RecipeDetail {
-            id: rkey.clone(),
-            name: record.value.name.clone(),
-            content: record.value.content.clone(),
-            portions: record.value.portions as u32,
-            time: record.value.time as u32,
+            id: rkey.to_string(),
+            time_ago: time_ago(&record.created_at),
+            portions: record.portions.unwrap_or(0) as u32,
+            time: record.time.unwrap_or(0) as u32,
+            prep_time: record.prep_time.and_then(|v| u32::try_from(v).ok()),
+            cook_time: record.cook_time.and_then(|v| u32::try_from(v).ok()),
+            image_cid: record.image_cid(),
+            image_mime_type: record.image_mime_type(),
+            name: record.name,
+            content: record.content,
             author: author_info,
-            time_ago: time_ago(&record.value.created_at),
             comments: vec![],
-            description: record.value.description.clone(),
-            prep_time: record.value.prep_time.map(|v| v as u32),
-            cook_time: record.value.cook_time.map(|v| v as u32),
-            image_cid: record.value.image.as_ref()
-                .and_then(|img| img.get("ref"))
-                .and_then(|r| r.get("$link"))
-                .and_then(|cid| cid.as_str())
-                .map(String::from),
-            image_mime_type: record.value.image.as_ref()
-                .and_then(|img| img.get("mimeType"))
-                .and_then(|mime| mime.as_str())
-                .map(String::from),
+            description: record.description,
         };
 
-        let uri = format!("at://{}/eu.atchef.recipe/{}", did, rkey);
-        let _ = db::save_recipe(
-            &state.sqlite_pool,
-            &uri,
-            &did,
-            &handle,
-            &rkey,
-            &recipe_detail.name,
-            &recipe_detail.content,
-            recipe_detail.portions,
-            recipe_detail.time,
-            &record.value.created_at,
-            recipe_detail.description.as_deref(),
-            recipe_detail.prep_time,
-            recipe_detail.cook_time,
-            record.value.image.as_ref()
-                .and_then(|img| img.get("ref"))
-                .and_then(|r| r.get("$link"))
-                .and_then(|cid| cid.as_str()),
-            record.value.image.as_ref()
-                .and_then(|img| img.get("mimeType"))
-                .and_then(|mime| mime.as_str()),
-        )
-        .await;
-
-        Ok(recipe_detail)
+        Ok::<_, anyhow::Error>(Some(recipe_detail))
     }
     .await;
 
     match result {
-        Ok(detail) => {
+        Ok(Some(detail)) => {
             let content = recipe_page(&detail);
             base_layout_with_user(&format!("{} | AtChef", detail.name), content, user.as_ref().map(|u| u.handle.as_str()))
+                .into_response()
         }
+        Ok(None) => author_unavailable("Recipe unavailable"),
         Err(e) => {
-            tracing::error!("Failed to load recipe {}/{}: {}", handle, rkey, e);
-            base_layout(
-                "Not Found | AtChef",
-                maud::html! {
-                    h1 { "Recipe not found" }
-                    p { "The recipe you're looking for doesn't exist." }
-                    p { a href="/" { "Back to home" } }
-                },
+            tracing::error!("Failed to load recipe {}/{}: {:#}", handle, rkey, e);
+            let failure = pds_failure(&e);
+            if e.is::<UnknownActor>() || failure.status == StatusCode::NOT_FOUND {
+                return recipe_not_found();
+            }
+            // A PDS or directory that is down says nothing about whether the
+            // recipe exists, so don't answer 404 for it
+            (
+                failure.status,
+                base_layout(
+                    "Unavailable | AtChef",
+                    maud::html! {
+                        h1 { "Recipe unavailable" }
+                        p { "The recipe can't be loaded right now, please try again later." }
+                        p { a href="/" { "Back to home" } }
+                    },
+                ),
             )
+                .into_response()
+        }
+    }
+}
+
+/// A handle or DID that doesn't resolve to an account.
+#[derive(Debug)]
+struct UnknownActor;
+
+impl std::fmt::Display for UnknownActor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("unknown account")
+    }
+}
+
+/// Mark an identity lookup failure as an unknown account, unless the
+/// resolver was only temporarily unavailable.
+fn unknown_actor(e: anyhow::Error) -> anyhow::Error {
+    if e.is::<crate::identity::Unavailable>() { e } else { e.context(UnknownActor) }
+}
+
+/// The 410 page for content of a deactivated, taken-down or deleted account.
+fn author_unavailable(heading: &str) -> Response {
+    (
+        StatusCode::GONE,
+        base_layout(
+            "Unavailable | AtChef",
+            maud::html! {
+                h1 { (heading) }
+                p { "The author's account is no longer active." }
+                p { a href="/" { "Back to home" } }
+            },
+        ),
+    )
+        .into_response()
+}
+
+fn recipe_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        base_layout(
+            "Not Found | AtChef",
+            maud::html! {
+                h1 { "Recipe not found" }
+                p { "The recipe you're looking for doesn't exist." }
+                p { a href="/" { "Back to home" } }
+            },
+        ),
+    )
+        .into_response()
+}
+
+/// The `{handle}` and `{rkey}` of a recipe URL. A record key that isn't
+/// valid gets the recipe-not-found page instead of axum's plain-text 400.
+pub struct RecipePath(String, RecordKey);
+
+impl<S: Send + Sync> FromRequestParts<S> for RecipePath {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match Path::<(String, RecordKey)>::from_request_parts(parts, state).await {
+            Ok(Path((handle, rkey))) => Ok(Self(handle, rkey)),
+            Err(rejection) => {
+                tracing::debug!("Bad recipe path {}: {}", parts.uri, rejection);
+                Err(recipe_not_found())
+            }
         }
     }
 }
@@ -405,7 +247,7 @@ pub async fn public_profile(
     State(state): State<AppState>,
     Path(handle): Path<String>,
     session: Session,
-) -> Markup {
+) -> Response {
     let viewer = session
         .get::<AuthenticatedUser>(USER_KEY)
         .await
@@ -413,12 +255,17 @@ pub async fn public_profile(
         .flatten();
     let is_owner = viewer
         .as_ref()
-        .map(|u| u.handle == handle)
+        .map(|u| u.handle == handle || u.did == handle)
         .unwrap_or(false);
 
     let result = async {
-        let did = discovery::resolve_handle(&state.http_client, &handle).await?;
-        let pds_url = discovery::get_pds_url(&state.http_client, &did).await?;
+        let did = state.identity.resolve_actor(&handle).await?;
+        // Hidden accounts get no profile, so nothing is fetched from their PDS
+        if db::is_author_hidden(&state.sqlite_pool, &did).await? {
+            return Ok(None);
+        }
+        let author_handle = state.identity.resolve_did_to_handle(&did).await?;
+        let pds_url = state.identity.get_pds_url(&did).await?;
 
         // Fetch profile record (best-effort)
         let profile_url = format!(
@@ -449,19 +296,20 @@ pub async fn public_profile(
         let display_name = profile.as_ref().and_then(|p| p.display_name.clone());
         let description = profile.as_ref().and_then(|p| p.description.clone());
 
-        let url = format!(
-            "{}/xrpc/com.atproto.repo.listRecords?repo={}&collection=eu.atchef.recipe",
-            pds_url.trim_end_matches('/'),
-            urlencoding::encode(&did),
-        );
-        let response = state.http_client.get(&url).send().await?;
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("failed to list records: {}", response.status()));
-        }
-        let list: ListRecordsResponse = response.json().await?;
-        let recipes = list.records.into_iter().map(|r| {
+        let agent = pds_agent(&state, AnonymousSession::new(did.clone(), pds_url.clone()));
+        let options = ListRecordsOptions {
+            limit: Some(100),
+            max_items: Some(MAX_PROFILE_RECIPES),
+            ..Default::default()
+        };
+        let records: Vec<atproto_api::ListRecordsRecord<RecipeRecord>> = agent
+            .repo()
+            .list::<records::Recipe>(options)
+            .try_collect()
+            .await?;
+        let recipes = records.into_iter().map(|r| {
             let rkey = r.uri.split('/').last().unwrap_or("").to_string();
-            let author_info = crate::models::AuthorInfo::basic(handle.clone());
+            let author_info = crate::models::AuthorInfo::basic(did.clone(), author_handle.clone());
             crate::models::Recipe {
                 id: rkey,
                 name: r.value.name,
@@ -471,14 +319,14 @@ pub async fn public_profile(
             }
         }).collect::<Vec<_>>();
         let is_member = db::is_atchef_member(&state.sqlite_pool, &did).await.unwrap_or(false);
-        Ok((recipes, display_name, description, avatar_url, is_member))
+        Ok::<_, anyhow::Error>(Some((author_handle, recipes, display_name, description, avatar_url, is_member)))
     }
     .await;
 
     match result {
-        Ok((recipes, display_name, description, avatar_url, is_member)) => {
+        Ok(Some((author_handle, recipes, display_name, description, avatar_url, is_member))) => {
             let content = crate::views::public_profile_page(
-                &handle,
+                &author_handle,
                 &recipes,
                 is_owner,
                 display_name.as_deref(),
@@ -487,11 +335,13 @@ pub async fn public_profile(
                 is_member,
             );
             base_layout_with_user(
-                &format!("{} | AtChef", handle),
+                &format!("{} | AtChef", author_handle),
                 content,
                 viewer.as_ref().map(|u| u.handle.as_str()),
             )
+            .into_response()
         }
+        Ok(None) => author_unavailable("Profile unavailable"),
         Err(e) => {
             tracing::error!("Failed to load profile {}: {}", handle, e);
             base_layout(
@@ -501,11 +351,12 @@ pub async fn public_profile(
                     p { a href="/" { "Back to home" } }
                 },
             )
+            .into_response()
         }
     }
 }
 
-pub async fn login_page_handler(session: Session) -> Markup {
+pub async fn login_page_handler(State(state): State<AppState>, session: Session) -> Markup {
     if let Ok(Some(_)) = session.get::<AuthenticatedUser>(USER_KEY).await {
         return base_layout(
             "Already signed in | AtChef",
@@ -519,10 +370,22 @@ pub async fn login_page_handler(session: Session) -> Markup {
         );
     }
 
-    let content = login_page(None);
+    // A draft waiting means the user was sent here because their session expired
+    let expired = matches!(session.get::<RecipeDraft>(DRAFT_KEY).await, Ok(Some(_)));
+    let message = expired.then_some("Your session has expired. Sign in again to get back to your recipe.");
+    let content = login_page(message, state.app_password_login);
     base_layout("Sign in | AtChef", content)
 }
 
+/// Where to go after signing in: back to a form whose submission needed a
+/// fresh sign-in, or home.
+async fn after_login(session: &Session) -> String {
+    match session.get::<RecipeDraft>(DRAFT_KEY).await {
+        Ok(Some(draft)) => draft.return_to,
+        _ => "/".to_string(),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct LoginForm {
     handle: String,
@@ -536,8 +399,8 @@ pub async fn login_start(
     let handle = form.handle.trim().to_lowercase();
 
     let result = async {
-        let did = discovery::resolve_handle(&state.http_client, &handle).await?;
-        let pds_url = discovery::get_pds_url(&state.http_client, &did).await?;
+        let did = state.identity.resolve_handle(&handle).await?;
+        let pds_url = state.identity.get_pds_url(&did).await?;
         let as_metadata = discovery::get_auth_server_metadata(&state.http_client, &pds_url).await?;
 
         let pkce = pkce::generate();
@@ -606,7 +469,7 @@ pub async fn login_start(
         Ok(auth_url) => Redirect::to(&auth_url).into_response(),
         Err(e) => {
             tracing::error!("OAuth error: {}", e);
-            let content = login_page(Some(&format!("Login failed: {}", e)));
+            let content = login_page(Some(&format!("Login failed: {}", e)), state.app_password_login);
             base_layout("Sign in | AtChef", content).into_response()
         }
     }
@@ -627,14 +490,14 @@ pub async fn oauth_callback(
 ) -> Response {
     if let Some(error) = params.error {
         let desc = params.error_description.unwrap_or_default();
-        let content = login_page(Some(&format!("Authorization failed: {} - {}", error, desc)));
+        let content = login_page(Some(&format!("Authorization failed: {} - {}", error, desc)), state.app_password_login);
         return base_layout("Sign in | AtChef", content).into_response();
     }
 
     let code = match params.code {
         Some(c) => c,
         None => {
-            let content = login_page(Some("Missing authorization code"));
+            let content = login_page(Some("Missing authorization code"), state.app_password_login);
             return base_layout("Sign in | AtChef", content).into_response();
         }
     };
@@ -653,15 +516,17 @@ pub async fn oauth_callback(
         let redirect_uri = format!("{}/oauth/callback", state.base_url);
 
         // Token exchange with DPoP nonce handling
-        let tokens = exchange_token(
+        let tokens = token::exchange_token(
             &state.http_client,
             &pending.token_endpoint,
             &pending.dpop_private_key_pem,
             &pending.dpop_public_jwk,
             &state.client_id,
-            &code,
-            &redirect_uri,
-            &pending.code_verifier,
+            token::AuthorizationCode {
+                code: &code,
+                redirect_uri: &redirect_uri,
+                code_verifier: &pending.code_verifier,
+            },
         )
         .await?;
 
@@ -673,7 +538,7 @@ pub async fn oauth_callback(
             &pending.dpop_private_key_pem,
             pending.dpop_public_jwk.clone(),
         );
-        let agent = Agent::with_http_client(dpop_session, state.http_client.clone());
+        let agent = pds_agent(&state, dpop_session);
         let profile = agent
             .repo()
             .get_record::<ProfileRecord>(&tokens.sub, "app.bsky.actor.profile", "self")
@@ -681,14 +546,29 @@ pub async fn oauth_callback(
             .ok()
             .map(|r| r.value); // Gracefully handle errors - profile is optional
 
+        // Only show the handle if it resolves back to the DID that logged in.
+        // The login itself succeeded, so a failed lookup doesn't undo it.
+        let handle = match state.identity.resolve_did_to_handle(&tokens.sub).await {
+            Ok(handle) => handle,
+            Err(e) => {
+                tracing::warn!("failed to verify the handle of {}: {}", tokens.sub, e);
+                crate::identity::INVALID_HANDLE.to_string()
+            }
+        };
+        if handle != pending.handle {
+            tracing::warn!("{} logged in as {} but its verified handle is {}", tokens.sub, pending.handle, handle);
+        }
+
         let user = AuthenticatedUser {
             did: tokens.sub,
-            handle: pending.handle,
-            access_token: tokens.access_token,
-            refresh_token: tokens.refresh_token,
-            expires_at: chrono::Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64),
-            dpop_private_key_pem: pending.dpop_private_key_pem,
-            dpop_public_jwk: pending.dpop_public_jwk,
+            handle,
+            credentials: Credentials::OAuth {
+                access_token: tokens.access_token,
+                refresh_token: tokens.refresh_token,
+                expires_at: chrono::Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64),
+                dpop_private_key_pem: pending.dpop_private_key_pem,
+                dpop_public_jwk: Box::new(pending.dpop_public_jwk),
+            },
             pds_url: pending.pds_url,
             profile,
         };
@@ -709,21 +589,101 @@ pub async fn oauth_callback(
     .await;
 
     match result {
-        Ok(()) => Redirect::to("/").into_response(),
+        Ok(()) => Redirect::to(&after_login(&session).await).into_response(),
         Err(e) => {
             tracing::error!("OAuth callback error: {}", e);
-            let content = login_page(Some(&format!("Login failed: {}", e)));
+            let content = login_page(Some(&format!("Login failed: {}", e)), state.app_password_login);
             base_layout("Sign in | AtChef", content).into_response()
         }
     }
 }
 
 #[derive(Deserialize)]
-struct TokenResponse {
-    access_token: String,
-    refresh_token: Option<String>,
-    expires_in: u64,
-    sub: String,
+pub struct AppPasswordLoginForm {
+    handle: String,
+    password: String,
+}
+
+/// Sign in with an app password through com.atproto.server.createSession,
+/// for accounts whose PDS has no working OAuth setup.
+pub async fn app_password_login(
+    State(state): State<AppState>,
+    session: Session,
+    Form(form): Form<AppPasswordLoginForm>,
+) -> Response {
+    if !state.app_password_login {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let handle = form.handle.trim().to_lowercase();
+
+    let result = async {
+        let did = state.identity.resolve_actor(&handle).await?;
+        state.login_throttle.check(&did)?;
+        let pds_url = state.identity.get_pds_url(&did).await?;
+        let login = PasswordSession::login_with_http_client(
+            state.http_client.clone(),
+            &pds_url,
+            &did,
+            &form.password,
+        )
+        .await;
+        let password_session = match login {
+            Ok(password_session) => password_session,
+            Err(e) if e.xrpc_code().map(|c| c.as_str()) == Some("AuthFactorTokenRequired") => {
+                return Err(anyhow::anyhow!("this account requires a sign-in code; use an app password instead"));
+            }
+            Err(e) if e.is_auth_error() => {
+                state.login_throttle.record_failure(&did);
+                return Err(anyhow::anyhow!("invalid handle or app password"));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        state.login_throttle.clear(&did);
+        if password_session.did() != did {
+            return Err(anyhow::anyhow!("PDS signed in {} instead of {}", password_session.did(), did));
+        }
+
+        let profile = pds_agent(&state, AnonymousSession::new(did.clone(), pds_url.clone()))
+            .repo()
+            .get_record::<ProfileRecord>(&did, "app.bsky.actor.profile", "self")
+            .await
+            .ok()
+            .map(|r| r.value);
+        let handle = state.identity.resolve_did_to_handle(&did).await?;
+
+        let data = password_session.data();
+        let user = AuthenticatedUser {
+            did,
+            handle,
+            credentials: Credentials::AppPassword {
+                access_jwt: data.access_jwt,
+                refresh_jwt: data.refresh_jwt,
+                service: data.service,
+            },
+            pds_url: data.pds_url,
+            profile,
+        };
+
+        if let Err(e) = db::upsert_user(&state.sqlite_pool, &user.did, &user.handle).await {
+            tracing::error!("Failed to track user: {}", e);
+        }
+        session
+            .insert(USER_KEY, user)
+            .await
+            .map_err(|e| anyhow::anyhow!("session error: {}", e))?;
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match result {
+        Ok(()) => Redirect::to(&after_login(&session).await).into_response(),
+        Err(e) => {
+            tracing::error!("App password login error: {}", e);
+            let status = if e.is::<Throttled>() { StatusCode::TOO_MANY_REQUESTS } else { StatusCode::OK };
+            let content = login_page(Some(&format!("Login failed: {}", e)), state.app_password_login);
+            (status, base_layout("Sign in | AtChef", content)).into_response()
+        }
+    }
 }
 
 pub async fn logout(session: Session) -> Redirect {
@@ -762,6 +722,31 @@ pub async fn client_metadata(State(state): State<AppState>) -> Json<ClientMetada
 
 // Removed RecipeForm - replaced with multipart parsing
 
+/// Shown above a form filled back in from a draft after signing in again.
+const RESTORED_DRAFT: &str = "Your recipe was kept while you signed in again. Select the image again if you had added one.";
+
+/// Take the draft saved for the form at `path`, if there is one.
+async fn take_draft(session: &Session, path: &str) -> Option<RecipeDraft> {
+    let draft = session.get::<RecipeDraft>(DRAFT_KEY).await.ok().flatten()?;
+    if draft.return_to != path {
+        return None;
+    }
+    session.remove::<RecipeDraft>(DRAFT_KEY).await.ok();
+    Some(draft)
+}
+
+/// The PDS no longer accepts the user's session: sign them out and send them
+/// to sign in again, keeping what they submitted so the form can be restored.
+async fn reauthenticate(session: &Session, draft: Option<RecipeDraft>) -> Response {
+    session.remove::<AuthenticatedUser>(USER_KEY).await.ok();
+    if let Some(draft) = draft
+        && let Err(e) = session.insert(DRAFT_KEY, draft).await
+    {
+        tracing::warn!("failed to keep recipe draft: {}", e);
+    }
+    Redirect::to("/login").into_response()
+}
+
 #[derive(Debug)]
 pub struct RecipeFormData {
     name: String,
@@ -774,16 +759,46 @@ pub struct RecipeFormData {
     post_to_bluesky: bool,
 }
 
+impl RecipeFormData {
+    /// The text fields, to fill the form at `return_to` back in.
+    fn draft(&self, return_to: String) -> RecipeDraft {
+        RecipeDraft {
+            return_to,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            portions: self.portions,
+            prep_time: self.prep_time,
+            cook_time: self.cook_time,
+            content: self.content.clone(),
+            post_to_bluesky: self.post_to_bluesky,
+        }
+    }
+}
+
 pub async fn new_recipe_form(session: Session) -> Response {
     match session.get::<AuthenticatedUser>(USER_KEY).await {
         Ok(Some(user)) => {
-            let content = recipe_form_page(None);
+            let draft = take_draft(&session, "/recipe/new").await;
+            let content = recipe_form_page(draft.as_ref(), draft.as_ref().map(|_| RESTORED_DRAFT));
             base_layout_with_user("New Recipe | AtChef", content, Some(&user.handle)).into_response()
         }
         _ => Redirect::to("/login").into_response(),
     }
 }
 
+/// A number of minutes from the form; blank keeps the default, negative is refused.
+fn parse_minutes(field: &str, value: &str) -> anyhow::Result<Option<u64>> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse::<i64>() {
+        Ok(minutes) if minutes < 0 => Err(anyhow::anyhow!("{} can't be negative", field)),
+        Ok(minutes) => Ok(Some(minutes as u64)),
+        Err(_) => Err(anyhow::anyhow!("{} must be a whole number of minutes", field)),
+    }
+}
+
 async fn parse_recipe_multipart(mut multipart: Multipart) -> anyhow::Result<RecipeFormData> {
     let mut name = String::new();
     let mut description = String::new();
@@ -810,14 +825,10 @@ async fn parse_recipe_multipart(mut multipart: Multipart) -> anyhow::Result<Reci
                 }
             }
             "prep_time" => {
-                if let Ok(val) = field.text().await?.parse::<u64>() {
-                    prep_time = val;
-                }
+                prep_time = parse_minutes("prep time", &field.text().await?)?.unwrap_or(prep_time);
             }
             "cook_time" => {
-                if let Ok(val) = field.text().await?.parse::<u64>() {
-                    cook_time = val;
-                }
+                cook_time = parse_minutes("cook time", &field.text().await?)?.unwrap_or(cook_time);
             }
             "content" => {
                 content = field.text().await?;
@@ -871,12 +882,52 @@ async fn parse_recipe_multipart(mut multipart: Multipart) -> anyhow::Result<Reci
     })
 }
 
+/// How a failed PDS call should be reported back to the user.
+struct PdsFailure {
+    status: StatusCode,
+    message: String,
+    /// The session can't be used any more; send the user to sign in again
+    reauthenticate: bool,
+}
+
+/// Map a PDS error to an HTTP status and a message fit for the form page.
+///
+/// A recipe breaking the lexicon's limits is a 400 and an identity lookup
+/// that may work again later is a 503. Anything else gets a 500 with a
+/// generic message; callers log the full error chain.
+fn pds_failure(e: &anyhow::Error) -> PdsFailure {
+    use atproto_api::XrpcErrorCode;
+
+    const GENERIC: &str = "Something went wrong, please try again later";
+    let failure = |status, message: &str| PdsFailure { status, message: message.to_string(), reauthenticate: false };
+    if let Some(invalid) = e.downcast_ref::<records::InvalidRecipe>() {
+        return failure(StatusCode::BAD_REQUEST, &invalid.to_string());
+    }
+    if e.is::<crate::identity::Unavailable>() {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Looking up your account failed, please try again later");
+    }
+    let Some(err) = e.downcast_ref::<atproto_api::Error>() else {
+        return failure(StatusCode::INTERNAL_SERVER_ERROR, GENERIC);
+    };
+    if err.is_auth_error() {
+        return PdsFailure { reauthenticate: true, ..failure(StatusCode::UNAUTHORIZED, "Your session has expired, please sign in again") };
+    }
+    match err.xrpc_code() {
+        Some(XrpcErrorCode::BlobTooLarge) => failure(StatusCode::PAYLOAD_TOO_LARGE, "The image is larger than your PDS accepts"),
+        Some(XrpcErrorCode::RateLimitExceeded) => failure(StatusCode::TOO_MANY_REQUESTS, "Your PDS is rate limiting requests, please try again in a moment"),
+        Some(XrpcErrorCode::InvalidSwap) => failure(StatusCode::CONFLICT, "The recipe was changed elsewhere, please reload and try again"),
+        Some(XrpcErrorCode::RecordNotFound) => failure(StatusCode::NOT_FOUND, "Recipe not found"),
+        _ if err.is_retryable() => failure(StatusCode::BAD_GATEWAY, "Your PDS is unavailable right now, please try again later"),
+        _ => failure(StatusCode::INTERNAL_SERVER_ERROR, GENERIC),
+    }
+}
+
 pub async fn create_recipe(
     State(state): State<AppState>,
     session: Session,
     multipart: Multipart,
 ) -> Response {
-    let mut user = match session.get::<AuthenticatedUser>(USER_KEY).await {
+    let user = match session.get::<AuthenticatedUser>(USER_KEY).await {
         Ok(Some(user)) => user,
         _ => return Redirect::to("/login").into_response(),
     };
@@ -886,63 +937,34 @@ pub async fn create_recipe(
         Ok(form) => form,
         Err(e) => {
             tracing::error!("Failed to parse form data: {}", e);
-            let content = recipe_form_page(Some(&format!("Invalid form data: {}", e)));
+            let content = recipe_form_page(None, Some(&format!("Invalid form data: {}", e)));
             return base_layout_with_user("New Recipe | AtChef", content, Some(&user.handle)).into_response();
         }
     };
 
     let post_to_bluesky = form.post_to_bluesky;
+    let draft = form.draft("/recipe/new".to_string());
+    let agent = user_agent(&user, &state, &session);
 
     let result = async {
-        // Check if token is expired and refresh if needed
-        let now = chrono::Utc::now();
-        if now >= user.expires_at {
-            tracing::info!("Access token expired, attempting refresh");
-            if let Some(refresh_token) = &user.refresh_token {
-                let metadata = discovery::get_auth_server_metadata(&state.http_client, &user.pds_url).await
-                    .map_err(|e| anyhow::anyhow!("Failed to fetch OAuth metadata: {}", e))?;
-                
-                let tokens = refresh_access_token(
-                    &state.http_client,
-                    &metadata.token_endpoint,
-                    &user.dpop_private_key_pem,
-                    &user.dpop_public_jwk,
-                    &state.client_id,
-                    refresh_token,
-                ).await?;
-
-                // Update user with new tokens
-                user.access_token = tokens.access_token;
-                user.refresh_token = tokens.refresh_token.or(user.refresh_token.clone());
-                user.expires_at = chrono::Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64);
-                
-                // Update session with refreshed tokens
-                session.insert(USER_KEY, &user).await
-                    .map_err(|e| anyhow::anyhow!("Failed to update session: {}", e))?;
-                
-                tracing::info!("Token refreshed successfully");
-            } else {
-                return Err(anyhow::anyhow!("Token expired and no refresh token available"));
-            }
-        }
-
-        let dpop_session = DpopSession::new(
-            &user.did,
-            &user.pds_url,
-            &user.access_token,
-            &user.dpop_private_key_pem,
-            user.dpop_public_jwk.clone(),
-        );
-        let agent = Agent::with_http_client(dpop_session, state.http_client.clone());
-
-        let created_at = atrium_api::types::string::Datetime::now();
-        // Already have form.name available, no need for separate variable
-
         let portions = form.portions.max(1);
         let time = (form.prep_time + form.cook_time).max(1);
         let description = if form.description.trim().is_empty() { None } else { Some(form.description.trim().to_string()) };
-        let prep_time = if form.prep_time > 0 { Some(form.prep_time) } else { None };
-        let cook_time = if form.cook_time > 0 { Some(form.cook_time) } else { None };
+        let prep_time = if form.prep_time > 0 { Some(form.prep_time as i64) } else { None };
+        let cook_time = if form.cook_time > 0 { Some(form.cook_time as i64) } else { None };
+
+        let mut record = RecipeRecord {
+            name: form.name,
+            description,
+            portions: Some(portions),
+            time: Some(time),
+            prep_time,
+            cook_time,
+            content: form.content,
+            image: None,
+            created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        };
+        record.validate()?;
 
         // Handle image upload if present
         let image_blob = if let Some((image_data, mime_type)) = form.image {
@@ -954,63 +976,29 @@ pub async fn create_recipe(
                 }
                 Err(e) => {
                     tracing::error!("Failed to upload image: {}", e);
-                    return Err(anyhow::anyhow!("Failed to upload image: {}", e));
+                    return Err(anyhow::Error::new(e).context("Failed to upload image"));
                 }
             }
         } else {
             None
         };
 
-        let converted_image = if let Some(ref blob) = image_blob {
-            Some(convert_blob_ref(blob)?)
-        } else {
-            None
-        };
+        if let Some(blob) = &image_blob {
+            record.set_image(blob)?;
+        }
 
-        let record = RecordData {
-            name: form.name.clone(),
-            description,
-            portions: std::num::NonZeroU64::new(portions).unwrap(),
-            time: std::num::NonZeroU64::new(time).unwrap(),
-            prep_time,
-            cook_time,
-            content: form.content.clone(),
-            image: converted_image,
-            created_at: created_at.clone(),
-        };
+        let output = agent.repo().create::<records::Recipe>(&record).await?;
 
-        let output = agent
-            .repo()
-            .create_record(&user.did, "eu.atchef.recipe", &record)
-            .await?;
-
-        Ok::<_, anyhow::Error>((output, created_at, form.name, record.content, portions as u32, time as u32, record.description, record.prep_time, record.cook_time, image_blob))
+        Ok::<_, anyhow::Error>((output, record))
     }
     .await;
 
     match result {
-        Ok((output, created_at, recipe_name, content, portions, time, description, prep_time, cook_time, original_blob)) => {
+        Ok((output, record)) => {
             let rkey = output.uri.split('/').last().unwrap_or("").to_string();
-            let uri = output.uri.clone();
 
             // Save recipe to local database for caching
-            if let Err(e) = db::save_recipe(
-                &state.sqlite_pool,
-                &uri,
-                &user.did,
-                &user.handle,
-                &rkey,
-                &recipe_name,
-                &content,
-                portions,
-                time,
-                created_at.as_str(),
-                description.as_deref(),
-                prep_time.map(|v| v as u32),
-                cook_time.map(|v| v as u32),
-                original_blob.as_ref().map(|img| img.cid()),
-                original_blob.as_ref().map(|img| img.mime_type.as_str()),
-            ).await {
+            if let Err(e) = record.save(&state.sqlite_pool, &user.did, &user.handle, &rkey).await {
                 tracing::error!("Failed to save recipe to local database cache: {}", e);
                 // Recipe was successfully created in AT Protocol, but local caching failed
                 // This is non-critical - the recipe will still be accessible via AT Protocol
@@ -1018,37 +1006,31 @@ pub async fn create_recipe(
             }
 
             if post_to_bluesky {
-                let recipe_url = format!("{}/profile/{}/recipe/{}", state.base_url, user.handle, rkey);
-                if let Err(e) = post_recipe_to_bluesky(&user, &state, &recipe_name, &recipe_url).await {
+                let recipe_url = format!("{}/profile/{}/recipe/{}", state.base_url, user.actor(), rkey);
+                if let Err(e) = post_recipe_to_bluesky(&agent, &record.name, &recipe_url).await {
                     tracing::error!("Failed to post to Bluesky: {}", e);
                 }
             }
 
-            Redirect::to(&format!("/profile/{}/recipe/{}", user.handle, rkey)).into_response()
+            Redirect::to(&format!("/profile/{}/recipe/{}", user.actor(), rkey)).into_response()
         }
         Err(e) => {
-            tracing::error!("Failed to create recipe: {}", e);
-            let content = recipe_form_page(Some(&format!("Failed to create recipe: {}", e)));
-            base_layout_with_user("New Recipe | AtChef", content, Some(&user.handle)).into_response()
+            tracing::error!("Failed to create recipe: {:#}", e);
+            let failure = pds_failure(&e);
+            if failure.reauthenticate {
+                return reauthenticate(&session, Some(draft)).await;
+            }
+            let content = recipe_form_page(Some(&draft), Some(&format!("Failed to create recipe: {}", failure.message)));
+            (failure.status, base_layout_with_user("New Recipe | AtChef", content, Some(&user.handle))).into_response()
         }
     }
 }
 
 async fn post_recipe_to_bluesky(
-    user: &AuthenticatedUser,
-    state: &AppState,
+    agent: &Agent<UserSession>,
     recipe_name: &str,
     recipe_url: &str,
 ) -> anyhow::Result<()> {
-    let dpop_session = DpopSession::new(
-        &user.did,
-        &user.pds_url,
-        &user.access_token,
-        &user.dpop_private_key_pem,
-        user.dpop_public_jwk.clone(),
-    );
-    let agent = Agent::with_http_client(dpop_session, state.http_client.clone());
-
     let text = format!("New recipe: {}\n\n{}", recipe_name, recipe_url);
     let url_start = text.len() - recipe_url.len();
     let url_end = text.len();
@@ -1077,69 +1059,117 @@ async fn post_recipe_to_bluesky(
         created_at: chrono::Utc::now().to_rfc3339(),
     };
 
-    agent.repo().create_record(&user.did, "app.bsky.feed.post", &post).await?;
+    agent.repo().create_record(agent.did(), "app.bsky.feed.post", &post).await?;
     Ok(())
 }
 
-async fn refresh_and_build_agent(
-    user: &mut AuthenticatedUser,
-    state: &AppState,
-    session: &Session,
-) -> anyhow::Result<Agent<DpopSession>> {
-    let now = chrono::Utc::now();
-    if now >= user.expires_at {
-        if let Some(refresh_token) = user.refresh_token.clone() {
-            let metadata = discovery::get_auth_server_metadata(&state.http_client, &user.pds_url).await?;
-            let tokens = refresh_access_token(
-                &state.http_client,
-                &metadata.token_endpoint,
-                &user.dpop_private_key_pem,
-                &user.dpop_public_jwk,
-                &state.client_id,
-                &refresh_token,
-            ).await?;
-            user.access_token = tokens.access_token;
-            user.refresh_token = tokens.refresh_token.or(Some(refresh_token));
-            user.expires_at = chrono::Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64);
-            session.insert(USER_KEY, &*user).await
-                .map_err(|e| anyhow::anyhow!("Failed to update session: {}", e))?;
-        } else {
-            return Err(anyhow::anyhow!("Token expired and no refresh token available"));
+/// Agent acting as the signed-in user.
+///
+/// An access token the PDS rejects is refreshed on demand, and the new
+/// tokens are written back to the user's session.
+fn user_agent(user: &AuthenticatedUser, state: &AppState, session: &Session) -> Agent<UserSession> {
+    let user_session = match &user.credentials {
+        Credentials::OAuth { access_token, refresh_token, expires_at, dpop_private_key_pem, dpop_public_jwk } => {
+            let mut dpop_session = DpopSession::new(
+                &user.did,
+                &user.pds_url,
+                access_token,
+                dpop_private_key_pem,
+                (**dpop_public_jwk).clone(),
+            );
+            if let Some(refresh_token) = refresh_token {
+                let session = session.clone();
+                let user = user.clone();
+                dpop_session = dpop_session.with_refresh(
+                    state.http_client.clone(),
+                    &state.client_id,
+                    refresh_token,
+                    *expires_at,
+                    Box::new(move |tokens| {
+                        let session = session.clone();
+                        let mut user = user.clone();
+                        Box::pin(async move {
+                            if let Credentials::OAuth { access_token, refresh_token, expires_at, .. } = &mut user.credentials {
+                                *access_token = tokens.access_token;
+                                if tokens.refresh_token.is_some() {
+                                    *refresh_token = tokens.refresh_token;
+                                }
+                                *expires_at = chrono::Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64);
+                            }
+                            session.insert(USER_KEY, &user).await?;
+                            Ok(())
+                        })
+                    }),
+                );
+            }
+            UserSession::OAuth(Box::new(dpop_session))
         }
-    }
-    let dpop_session = DpopSession::new(
-        &user.did,
-        &user.pds_url,
-        &user.access_token,
-        &user.dpop_private_key_pem,
-        user.dpop_public_jwk.clone(),
-    );
-    Ok(Agent::with_http_client(dpop_session, state.http_client.clone()))
+        Credentials::AppPassword { access_jwt, refresh_jwt, service } => {
+            let password_session = PasswordSession::resume_with_http_client(
+                state.http_client.clone(),
+                PasswordSessionData {
+                    did: user.did.clone(),
+                    handle: user.handle.clone(),
+                    pds_url: user.pds_url.clone(),
+                    access_jwt: access_jwt.clone(),
+                    refresh_jwt: refresh_jwt.clone(),
+                    service: service.clone(),
+                },
+            );
+            let session = session.clone();
+            let user = user.clone();
+            UserSession::AppPassword {
+                session: Box::new(password_session),
+                persist: Box::new(move |data| {
+                    let session = session.clone();
+                    let mut user = user.clone();
+                    Box::pin(async move {
+                        user.credentials = Credentials::AppPassword {
+                            access_jwt: data.access_jwt,
+                            refresh_jwt: data.refresh_jwt,
+                            service: data.service,
+                        };
+                        session.insert(USER_KEY, &user).await?;
+                        Ok(())
+                    })
+                }),
+            }
+        }
+    };
+    pds_agent(state, user_session)
 }
 
 pub async fn delete_recipe(
     State(state): State<AppState>,
     session: Session,
-    Path((handle, rkey)): Path<(String, String)>,
+    RecipePath(handle, rkey): RecipePath,
 ) -> Response {
-    let mut user = match session.get::<AuthenticatedUser>(USER_KEY).await {
+    let user = match session.get::<AuthenticatedUser>(USER_KEY).await {
         Ok(Some(u)) => u,
         _ => return Redirect::to("/login").into_response(),
     };
-    if user.handle != handle {
+    if user.handle != handle && user.did != handle {
         return StatusCode::FORBIDDEN.into_response();
     }
     let result = async {
-        let agent = refresh_and_build_agent(&mut user, &state, &session).await?;
-        agent.repo().delete_record(&user.did, "eu.atchef.recipe", &rkey).await?;
-        db::delete_recipe(&state.sqlite_pool, &rkey, &user.did).await?;
+        let agent = user_agent(&user, &state, &session);
+        match agent.repo().delete::<records::Recipe>(&rkey).await {
+            // Already gone from the PDS; still drop our cached copy
+            Err(e) if e.xrpc_code() == Some(&atproto_api::XrpcErrorCode::RecordNotFound) => {}
+            other => other?,
+        }
+        db::delete_recipe(&state.sqlite_pool, rkey.as_str(), &user.did).await?;
         Ok::<_, anyhow::Error>(())
     }.await;
     match result {
         Ok(_) => Redirect::to(&format!("/profile/{}", handle)).into_response(),
         Err(e) => {
-            tracing::error!("Failed to delete recipe: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            tracing::error!("Failed to delete recipe: {:#}", e);
+            let failure = pds_failure(&e);
+            if failure.reauthenticate {
+                return reauthenticate(&session, None).await;
+            }
+            failure.status.into_response()
         }
     }
 }
@@ -1147,18 +1177,33 @@ pub async fn delete_recipe(
 pub async fn edit_recipe_form(
     State(state): State<AppState>,
     session: Session,
-    Path((handle, rkey)): Path<(String, String)>,
+    RecipePath(handle, rkey): RecipePath,
 ) -> Response {
     let user = match session.get::<AuthenticatedUser>(USER_KEY).await {
         Ok(Some(u)) => u,
         _ => return Redirect::to("/login").into_response(),
     };
-    if user.handle != handle {
+    if user.handle != handle && user.did != handle {
         return StatusCode::FORBIDDEN.into_response();
     }
-    match db::get_recipe(&state.sqlite_pool, &handle, &rkey).await {
+    let path = format!("/profile/{}/recipe/{}/edit", handle, rkey);
+    if let Some(draft) = take_draft(&session, &path).await {
+        let content = crate::views::edit_recipe_form_page(&handle, rkey.as_str(), &draft, Some(RESTORED_DRAFT));
+        return base_layout_with_user("Edit Recipe | AtChef", content, Some(&user.handle)).into_response();
+    }
+    match db::get_recipe(&state.sqlite_pool, &user.did, rkey.as_str()).await {
         Ok(Some(row)) => {
-            let content = crate::views::edit_recipe_form_page(&handle, &rkey, &row.name, &row.description.unwrap_or_default(), row.portions.into(), row.prep_time.unwrap_or(0) as u64, row.cook_time.unwrap_or(0) as u64, &row.content, None);
+            let draft = RecipeDraft {
+                return_to: path,
+                name: row.name,
+                description: row.description.unwrap_or_default(),
+                portions: row.portions.into(),
+                prep_time: row.prep_time.unwrap_or(0) as u64,
+                cook_time: row.cook_time.unwrap_or(0) as u64,
+                content: row.content,
+                post_to_bluesky: false,
+            };
+            let content = crate::views::edit_recipe_form_page(&handle, rkey.as_str(), &draft, None);
             base_layout_with_user("Edit Recipe | AtChef", content, Some(&user.handle)).into_response()
         }
         _ => StatusCode::NOT_FOUND.into_response(),
@@ -1168,108 +1213,89 @@ pub async fn edit_recipe_form(
 pub async fn update_recipe(
     State(state): State<AppState>,
     session: Session,
-    Path((handle, rkey)): Path<(String, String)>,
+    RecipePath(handle, rkey): RecipePath,
     multipart: Multipart,
 ) -> Response {
-    let mut user = match session.get::<AuthenticatedUser>(USER_KEY).await {
+    let user = match session.get::<AuthenticatedUser>(USER_KEY).await {
         Ok(Some(u)) => u,
         _ => return Redirect::to("/login").into_response(),
     };
-    if user.handle != handle {
+    if user.handle != handle && user.did != handle {
         return StatusCode::FORBIDDEN.into_response();
     }
     let form = match parse_recipe_multipart(multipart).await {
         Ok(f) => f,
         Err(e) => {
-            let content = crate::views::edit_recipe_form_page(&handle, &rkey, "", "", 4, 15, 30, "", Some(&format!("Invalid form data: {}", e)));
+            let draft = RecipeDraft {
+                return_to: String::new(),
+                name: String::new(),
+                description: String::new(),
+                portions: 4,
+                prep_time: 15,
+                cook_time: 30,
+                content: String::new(),
+                post_to_bluesky: false,
+            };
+            let content = crate::views::edit_recipe_form_page(&handle, rkey.as_str(), &draft, Some(&format!("Invalid form data: {}", e)));
             return base_layout_with_user("Edit Recipe | AtChef", content, Some(&user.handle)).into_response();
         }
     };
+    let draft = form.draft(format!("/profile/{}/recipe/{}/edit", handle, rkey));
     let result = async {
-        let agent = refresh_and_build_agent(&mut user, &state, &session).await?;
+        let agent = user_agent(&user, &state, &session);
 
         let portions = form.portions.max(1);
         let time = (form.prep_time + form.cook_time).max(1);
         let description = if form.description.trim().is_empty() { None } else { Some(form.description.trim().to_string()) };
-        let prep_time = if form.prep_time > 0 { Some(form.prep_time) } else { None };
-        let cook_time = if form.cook_time > 0 { Some(form.cook_time) } else { None };
+        let prep_time = if form.prep_time > 0 { Some(form.prep_time as i64) } else { None };
+        let cook_time = if form.cook_time > 0 { Some(form.cook_time as i64) } else { None };
 
         // Fetch existing record to preserve created_at and image
-        let existing = db::get_recipe(&state.sqlite_pool, &handle, &rkey).await?
+        let existing = db::get_recipe(&state.sqlite_pool, &user.did, rkey.as_str()).await?
             .ok_or_else(|| anyhow::anyhow!("Recipe not found"))?;
 
+        let mut record = RecipeRecord {
+            name: form.name.clone(),
+            description,
+            portions: Some(portions),
+            time: Some(time),
+            prep_time,
+            cook_time,
+            content: form.content.clone(),
+            image: None,
+            created_at: existing.created_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        };
+        record.validate()?;
+
         let image_blob = if let Some((image_data, mime_type)) = form.image {
             let blob_ref = agent.repo().upload_blob(image_data, &mime_type).await?;
             Some(blob_ref)
         } else {
             None
         };
-        let converted_image = if let Some(ref blob) = image_blob {
-            Some(convert_blob_ref(blob)?)
-        } else {
+        if let Some(blob) = &image_blob {
+            record.set_image(blob)?;
+        } else if let Some(cid) = existing.image_cid.as_deref().and_then(|cid| cid.parse().ok()) {
             // Preserve existing image if no new one uploaded
-            existing.image_cid.as_ref().and_then(|cid| {
-                let mime = existing.image_mime_type.as_deref().unwrap_or("image/jpeg");
-                use atrium_api::types::{BlobRef, TypedBlobRef, Blob};
-                use std::str::FromStr;
-                let cid_val = ipld_core::cid::Cid::from_str(cid).ok()?;
-                Some(BlobRef::Typed(TypedBlobRef::Blob(Blob {
-                    r#ref: atrium_api::types::CidLink(cid_val),
-                    mime_type: mime.to_string(),
-                    size: 0,
-                })))
-            })
-        };
-
-        let created_at = atrium_api::types::string::Datetime::new(
-            existing.created_at.with_timezone(&chrono::FixedOffset::east_opt(0).unwrap())
-        );
-
-        let record = RecordData {
-            name: form.name.clone(),
-            description,
-            portions: std::num::NonZeroU64::new(portions).unwrap(),
-            time: std::num::NonZeroU64::new(time).unwrap(),
-            prep_time,
-            cook_time,
-            content: form.content.clone(),
-            image: converted_image,
-            created_at,
-        };
+            let mime = existing.image_mime_type.as_deref().unwrap_or("image/jpeg");
+            record.set_image(&atproto_api::BlobRef::new(cid, mime, 0))?;
+        }
 
-        agent.repo().put_record(&user.did, "eu.atchef.recipe", &rkey, &record).await?;
-
-        let uri = format!("at://{}/eu.atchef.recipe/{}", user.did, rkey);
-        let image_cid_ref = image_blob.as_ref().map(|b| b.cid());
-        let image_mime_ref = image_blob.as_ref().map(|b| b.mime_type.as_str());
-        let final_cid = image_cid_ref.as_deref().or(existing.image_cid.as_deref());
-        let final_mime = image_mime_ref.or(existing.image_mime_type.as_deref());
-        db::save_recipe(
-            &state.sqlite_pool,
-            &uri,
-            &user.did,
-            &user.handle,
-            &rkey,
-            &form.name,
-            &form.content,
-            portions as u32,
-            time as u32,
-            &existing.created_at.to_rfc3339(),
-            record.description.as_deref(),
-            record.prep_time.map(|v| v as u32),
-            record.cook_time.map(|v| v as u32),
-            final_cid,
-            final_mime,
-        ).await?;
+        agent.repo().put::<records::Recipe>(&rkey, &record).await?;
+        record.save(&state.sqlite_pool, &user.did, &user.handle, rkey.as_str()).await?;
 
         Ok::<_, anyhow::Error>(())
     }.await;
     match result {
         Ok(_) => Redirect::to(&format!("/profile/{}/recipe/{}", handle, rkey)).into_response(),
         Err(e) => {
-            tracing::error!("Failed to update recipe: {}", e);
-            let content = crate::views::edit_recipe_form_page(&handle, &rkey, &form.name, &form.description, form.portions, form.prep_time, form.cook_time, &form.content, Some(&format!("Failed to update recipe: {}", e)));
-            base_layout_with_user("Edit Recipe | AtChef", content, Some(&user.handle)).into_response()
+            tracing::error!("Failed to update recipe: {:#}", e);
+            let failure = pds_failure(&e);
+            if failure.reauthenticate {
+                return reauthenticate(&session, Some(draft)).await;
+            }
+            let content = crate::views::edit_recipe_form_page(&handle, rkey.as_str(), &draft, Some(&format!("Failed to update recipe: {}", failure.message)));
+            (failure.status, base_layout_with_user("Edit Recipe | AtChef", content, Some(&user.handle))).into_response()
         }
     }
 }
@@ -1294,6 +1320,19 @@ pub async fn serve_blob(
     Path(cid): Path<String>,
     State(state): State<AppState>,
 ) -> Result<impl IntoResponse, StatusCode> {
+    let Ok(parsed_cid) = cid.parse::<atproto_api::Cid>() else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    // Images of hidden accounts stay cached in case they come back, but
+    // aren't served
+    match db::is_blob_hidden(&state.sqlite_pool, &cid).await {
+        Ok(false) => {}
+        Ok(true) => return Err(StatusCode::GONE),
+        Err(e) => {
+            tracing::error!("Failed to check visibility of blob {}: {}", cid, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
     // Try to get blob from cache first
     match state.blob_cache.get(&cid).await {
         Ok(Some(cached_blob)) => {
@@ -1318,7 +1357,7 @@ pub async fn serve_blob(
         }
         Ok(None) => {
             // Cache miss - need to fetch from PDS and cache it
-            match fetch_and_cache_blob(&cid, &state).await {
+            match fetch_and_cache_blob(&parsed_cid, &state).await {
                 Ok(Some(blob_data)) => {
                     let mut headers = HeaderMap::new();
                     headers.insert(
@@ -1354,105 +1393,50 @@ pub async fn serve_blob(
 }
 
 async fn fetch_and_cache_blob(
-    cid: &str,
+    parsed_cid: &atproto_api::Cid,
     state: &AppState,
 ) -> anyhow::Result<Option<(Vec<u8>, String)>> {
+    let cid = &parsed_cid.to_string();
     // First, try to find which author_did has a recipe with this image_cid
-    let author_did: Option<String> = sqlx::query_scalar(
-        "SELECT author_did FROM recipes WHERE image_cid = ? LIMIT 1"
+    let author: Option<(String, Option<String>)> = sqlx::query_as(
+        "SELECT author_did, image_mime_type FROM recipes WHERE image_cid = ? AND hidden = 0 LIMIT 1"
     )
     .bind(cid)
     .fetch_optional(&state.sqlite_pool)
     .await?;
 
-    let Some(did) = author_did else {
+    let Some((did, mime_type)) = author else {
         tracing::debug!("No recipe found with image CID: {}", cid);
         return Ok(None);
     };
-
-    // Extract PDS host from DID - AT Protocol DIDs typically follow format: did:plc:xxxxx
-    // We need to resolve the DID to get the PDS URL
-    let pds_url = resolve_pds_for_did(&did, &state.http_client).await?;
-    
-    // Fetch blob from the PDS
-    let blob_url = format!("{}/xrpc/com.atproto.sync.getBlob?did={}&cid={}", 
-        pds_url, 
-        urlencoding::encode(&did), 
-        urlencoding::encode(cid)
-    );
-
-    tracing::debug!("Fetching blob from: {}", blob_url);
-    
-    match state.http_client.get(&blob_url).send().await {
-        Ok(response) if response.status().is_success() => {
-            let content_type = response
-                .headers()
-                .get("content-type")
-                .and_then(|ct| ct.to_str().ok())
-                .unwrap_or("application/octet-stream")
-                .to_string();
-                
-            let data = response.bytes().await?;
-            
-            // Cache the blob for future requests
-            if let Err(e) = state.blob_cache.store(cid, data.to_vec(), &content_type).await {
-                tracing::warn!("Failed to cache blob {}: {}", cid, e);
-            } else {
-                tracing::debug!("Successfully cached blob: {}", cid);
-            }
-            
-            Ok(Some((data.to_vec(), content_type)))
-        }
-        Ok(response) => {
-            tracing::warn!("Failed to fetch blob {} from {}: HTTP {}", cid, pds_url, response.status());
-            Ok(None)
-        }
+    let content_type = mime_type.unwrap_or_else(|| "application/octet-stream".to_string());
+
+    // Fetch the blob from the author's PDS, with the same retries and size
+    // cap as the blob warmer
+    let pds_url = state.identity.get_pds_url(&did).await?;
+    let agent = pds_agent(state, AnonymousSession::new(did.as_str(), pds_url.as_str()));
+    let data = match agent.sync().get_blob_limited(&did, cid, crate::blob_warmer::MAX_IMAGE_SIZE).await {
+        Ok(data) => data,
+        // Still failing after the agent's retries: an error, not a missing image
+        Err(e) if e.is_retryable() => return Err(anyhow::Error::new(e).context(format!("fetching blob from {}", pds_url))),
         Err(e) => {
-            tracing::warn!("Error fetching blob {} from {}: {}", cid, pds_url, e);
-            Ok(None)
+            tracing::warn!("Failed to fetch blob {} from {}: {}", cid, pds_url, e);
+            return Ok(None);
         }
+    };
+    if !parsed_cid.verify(&data) {
+        tracing::warn!("Blob from {} does not match its CID {}", pds_url, cid);
+        return Ok(None);
     }
-}
 
-/// Resolve a DID to find its PDS (Personal Data Server) URL
-async fn resolve_pds_for_did(did: &str, client: &reqwest::Client) -> anyhow::Result<String> {
-    // For did:plc: DIDs, resolve via the PLC directory
-    if did.starts_with("did:plc:") {
-        let plc_url = format!("https://plc.directory/{}", did);
-        
-        match client.get(&plc_url).send().await {
-            Ok(response) if response.status().is_success() => {
-                if let Ok(doc) = response.json::<serde_json::Value>().await {
-                    // Look for PDS service endpoint in the DID document
-                    if let Some(services) = doc.get("service").and_then(|s| s.as_array()) {
-                        for service in services {
-                            if let (Some(service_type), Some(endpoint)) = (
-                                service.get("type").and_then(|t| t.as_str()),
-                                service.get("serviceEndpoint").and_then(|e| e.as_str())
-                            ) {
-                                if service_type == "AtprotoPersonalDataServer" {
-                                    return Ok(endpoint.to_string());
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            _ => {}
-        }
-    }
-    
-    // For did:web: DIDs, extract hostname directly
-    if did.starts_with("did:web:") {
-        let hostname = did.strip_prefix("did:web:").unwrap_or("");
-        return Ok(format!("https://{}", hostname));
+    // Cache the blob for future requests
+    if let Err(e) = state.blob_cache.store(cid, data.clone(), &content_type).await {
+        tracing::warn!("Failed to cache blob {}: {}", cid, e);
+    } else {
+        tracing::debug!("Successfully cached blob: {}", cid);
     }
-    
-    // Fallback to common PDS instances
-    tracing::debug!("Could not resolve PDS for DID {}, trying common instances", did);
-    
-    // Try bsky.social first as it's the most common
-    Ok("https://bsky.social".to_string())
+
+    Ok(Some((data, content_type)))
 }
 
 // ── Admin ────────────────────────────────────────────────────────────────────
@@ -1479,7 +1463,11 @@ pub async fn admin_page(State(state): State<AppState>, session: Session) -> Resp
         .fetch_one(&state.sqlite_pool)
         .await
         .unwrap_or(0);
-    let content = crate::views::admin_dashboard_page(recipe_count, blob_count);
+    let stream = state.sync_control.stream();
+    let cursor = db::get_cursor(&state.sqlite_pool, stream).await.ok().flatten();
+    let backfill = state.backfill.status().await.unwrap_or_default();
+    let warm_queue = state.blob_warmer.queue_depth();
+    let content = crate::views::admin_dashboard_page(recipe_count, blob_count, warm_queue, stream, cursor, &backfill);
     base_layout("Admin | AtChef", content).into_response()
 }
 
@@ -1518,11 +1506,6 @@ pub async fn admin_cleanup(State(state): State<AppState>, session: Session) -> R
     #[derive(sqlx::FromRow)]
     struct AuthorRow { author_did: String, author_handle: String }
 
-    #[derive(serde::Deserialize)]
-    struct ListRecord { uri: String }
-    #[derive(serde::Deserialize)]
-    struct ListResponse { records: Vec<ListRecord> }
-
     let authors = sqlx::query_as::<_, AuthorRow>(
         "SELECT DISTINCT author_did, author_handle FROM recipes"
     )
@@ -1534,7 +1517,7 @@ pub async fn admin_cleanup(State(state): State<AppState>, session: Session) -> R
     let mut errors: Vec<String> = Vec::new();
 
     for author in &authors {
-        let pds_url = match discovery::get_pds_url(&state.http_client, &author.author_did).await {
+        let pds_url = match state.identity.get_pds_url(&author.author_did).await {
             Ok(u) => u,
             Err(e) => {
                 errors.push(format!("{}: failed to resolve PDS — {}", author.author_handle, e));
@@ -1543,46 +1526,24 @@ pub async fn admin_cleanup(State(state): State<AppState>, session: Session) -> R
         };
 
         // Paginate through all recipe records on the PDS
-        let mut valid_rkeys = std::collections::HashSet::new();
-        let mut cursor: Option<String> = None;
-        loop {
-            let mut url = format!(
-                "{}/xrpc/com.atproto.repo.listRecords?repo={}&collection=eu.atchef.recipe&limit=100",
-                pds_url.trim_end_matches('/'),
-                urlencoding::encode(&author.author_did),
-            );
-            if let Some(ref c) = cursor {
-                url.push_str(&format!("&cursor={}", urlencoding::encode(c)));
-            }
-            match state.http_client.get(&url).send().await {
-                Ok(resp) if resp.status().is_success() => {
-                    match resp.json::<ListResponse>().await {
-                        Ok(list) => {
-                            let done = list.records.len() < 100;
-                            cursor = list.records.last().map(|r| r.uri.clone());
-                            for r in list.records {
-                                if let Some(rkey) = r.uri.split('/').last() {
-                                    valid_rkeys.insert(rkey.to_string());
-                                }
-                            }
-                            if done { break; }
-                        }
-                        Err(e) => {
-                            errors.push(format!("{}: failed to parse response — {}", author.author_handle, e));
-                            break;
-                        }
-                    }
-                }
-                Ok(resp) => {
-                    errors.push(format!("{}: PDS returned {}", author.author_handle, resp.status()));
-                    break;
-                }
-                Err(e) => {
-                    errors.push(format!("{}: request failed — {}", author.author_handle, e));
-                    break;
-                }
+        let agent = pds_agent(&state, AnonymousSession::new(author.author_did.clone(), pds_url));
+        let options = ListRecordsOptions { limit: Some(100), ..Default::default() };
+        let listed: Result<Vec<atproto_api::ListRecordsRecord<serde::de::IgnoredAny>>, _> = agent
+            .repo()
+            .list_records_stream(&author.author_did, records::Recipe::NSID, options)
+            .try_collect()
+            .await;
+        let valid_rkeys: std::collections::HashSet<String> = match listed {
+            Ok(records) => records
+                .iter()
+                .filter_map(|r| r.uri.rsplit('/').next().map(str::to_string))
+                .collect(),
+            Err(e) => {
+                // Deleting against a partial listing would drop live recipes
+                errors.push(format!("{}: failed to list records — {}", author.author_handle, e));
+                continue;
             }
-        }
+        };
 
         // Find local rkeys that no longer exist on PDS
         let local: Vec<String> = sqlx::query_scalar(
@@ -1630,3 +1591,329 @@ pub async fn admin_fix_image_cache(State(state): State<AppState>, session: Sessi
     };
     base_layout("Admin | AtChef", content).into_response()
 }
+
+#[derive(Deserialize)]
+pub struct AdminCursorForm {
+    action: String,
+    /// `datetime-local` input value, interpreted as UTC.
+    timestamp: Option<String>,
+}
+
+pub async fn admin_cursor(
+    State(state): State<AppState>,
+    session: Session,
+    Form(form): Form<AdminCursorForm>,
+) -> Response {
+    if state.admin_token.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if !is_admin_authed(&session).await {
+        return Redirect::to("/admin").into_response();
+    }
+
+    let command = match form.action.as_str() {
+        "reset" => Ok(crate::sync::CursorCommand::Reset),
+        "rewind" => form
+            .timestamp
+            .as_deref()
+            .and_then(|ts| chrono::NaiveDateTime::parse_from_str(ts, "%Y-%m-%dT%H:%M").ok())
+            .map(|dt| crate::sync::CursorCommand::RewindTo(dt.and_utc().timestamp_micros()))
+            .ok_or_else(|| anyhow::anyhow!("invalid timestamp")),
+        other => Err(anyhow::anyhow!("unknown action: {}", other)),
+    };
+
+    let message = match command.and_then(|c| {
+        let msg = match &c {
+            crate::sync::CursorCommand::Reset => "Cursor reset. The consumer will resume from the live tail.".to_string(),
+            crate::sync::CursorCommand::RewindTo(us) => format!(
+                "Cursor rewound to {}. The consumer is reconnecting and will replay events from there.",
+                chrono::DateTime::from_timestamp_micros(*us).map(|dt| dt.to_rfc3339()).unwrap_or_default(),
+            ),
+        };
+        state.sync_control.send(c).map(|_| msg)
+    }) {
+        Ok(msg) => msg,
+        Err(e) => format!("Error: {}", e),
+    };
+
+    let title = match state.sync_control.stream() {
+        db::CursorStream::Jetstream => "Jetstream cursor",
+        db::CursorStream::Firehose => "Firehose cursor",
+    };
+    let content = crate::views::admin_simple_result_page(title, &message);
+    base_layout("Admin | AtChef", content).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct AdminBackfillForm {
+    /// Whitespace-separated DIDs; empty means discover repos through the relay.
+    dids: Option<String>,
+}
+
+pub async fn admin_backfill(
+    State(state): State<AppState>,
+    session: Session,
+    Form(form): Form<AdminBackfillForm>,
+) -> Response {
+    if state.admin_token.is_none() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if !is_admin_authed(&session).await {
+        return Redirect::to("/admin").into_response();
+    }
+
+    let dids: Vec<String> = form
+        .dids
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if let Some(bad) = dids.iter().find(|d| !d.starts_with("did:")) {
+        let content = crate::views::admin_simple_result_page("Backfill", &format!("Error: not a DID: {}", bad));
+        return base_layout("Admin | AtChef", content).into_response();
+    }
+
+    let source = if dids.is_empty() {
+        crate::backfill::BackfillSource::Relay
+    } else {
+        crate::backfill::BackfillSource::Dids(dids)
+    };
+    let message = if state.backfill.start(source) {
+        "Backfill started. Progress is shown on the admin dashboard."
+    } else {
+        "A backfill is already running."
+    };
+
+    let content = crate::views::admin_simple_result_page("Backfill", message);
+    base_layout("Admin | AtChef", content).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use atproto_api::RetryPolicy;
+    use atproto_api::identity::StaticTxtResolver;
+    use axum::Router;
+    use axum::routing::{get, post};
+    use sqlx::SqlitePool;
+    use tower_sessions::MemoryStore;
+
+    use super::*;
+    use crate::backfill::{Backfill, BackfillConfig};
+    use crate::blob_cache::BlobCacheService;
+    use crate::blob_warmer::{BlobWarmer, BlobWarmerConfig};
+    use crate::db::CursorStream;
+    use crate::identity::{IdentityCacheConfig, IdentityResolver};
+    use crate::login_throttle::LoginThrottle;
+    use crate::pds::PdsClient;
+    use crate::sync::SyncControl;
+
+    /// Alice's PLC entry and PDS, which accepts the app password "app-pass"
+    /// and counts createSession calls.
+    async fn mock_pds(logins: Arc<AtomicUsize>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let pds = url.clone();
+        let did_document = move || async move {
+            Json(serde_json::json!({
+                "id": "did:plc:alice",
+                "alsoKnownAs": ["at://alice.test"],
+                "service": [{
+                    "id": "#atproto_pds",
+                    "type": "AtprotoPersonalDataServer",
+                    "serviceEndpoint": pds,
+                }],
+            }))
+        };
+        let create_session = move |Json(input): Json<serde_json::Value>| async move {
+            logins.fetch_add(1, Ordering::SeqCst);
+            if input["password"] != "app-pass" {
+                let body = serde_json::json!({ "error": "AuthenticationRequired", "message": "Invalid identifier or password" });
+                return (StatusCode::UNAUTHORIZED, Json(body));
+            }
+            (StatusCode::OK, Json(serde_json::json!({
+                "did": "did:plc:alice",
+                "handle": "alice.test",
+                "accessJwt": "access",
+                "refreshJwt": "refresh",
+            })))
+        };
+        // Recipe 3kdown is on a PDS that's having a bad day
+        let get_record = |Query(params): Query<std::collections::HashMap<String, String>>| async move {
+            if params.get("rkey").map(String::as_str) == Some("3kdown") {
+                let body = serde_json::json!({ "error": "InternalServerError", "message": "Internal Server Error" });
+                return (StatusCode::SERVICE_UNAVAILABLE, Json(body));
+            }
+            let body = serde_json::json!({ "error": "RecordNotFound", "message": "Could not locate record" });
+            (StatusCode::BAD_REQUEST, Json(body))
+        };
+        // Blobs are named by their content; "huge" is over the lexicon's size limit
+        let get_blob = |Query(params): Query<std::collections::HashMap<String, String>>| async move {
+            let huge = vec![0u8; crate::blob_warmer::MAX_IMAGE_SIZE + 1];
+            [b"cover".to_vec(), huge]
+                .into_iter()
+                .find(|data| atproto_api::Cid::compute(atproto_api::Cid::RAW, data).to_string() == params["cid"])
+                .ok_or(StatusCode::NOT_FOUND)
+        };
+        let app = Router::new()
+            .route("/did:plc:alice", get(did_document))
+            .route("/xrpc/com.atproto.sync.getBlob", get(get_blob))
+            .route("/xrpc/com.atproto.server.createSession", post(create_session))
+            .route("/xrpc/com.atproto.repo.getRecord", get(get_record));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    async fn app_state(plc: String, login_throttle: LoginThrottle) -> AppState {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        db::init_db(&pool).await.unwrap();
+        let pds = PdsClient::new(reqwest::Client::new(), RetryPolicy::none(), Duration::from_secs(5));
+        let dns = Arc::new(StaticTxtResolver::new(&[("_atproto.alice.test.", &["did=did:plc:alice"])]));
+        let resolver = pds.identity_resolver().dns(dns).plc_directory(plc).build();
+        let identity = Arc::new(IdentityResolver::new(resolver, pool.clone(), IdentityCacheConfig::default()));
+        let blob_cache = Arc::new(BlobCacheService::new(Arc::new(pool.clone()), 10));
+        let blob_warmer = BlobWarmer::start(BlobWarmerConfig::default(), pds.clone(), identity.clone(), blob_cache.clone());
+        let backfill = Arc::new(Backfill::new(pds.clone(), identity.clone(), pool.clone(), BackfillConfig::default()));
+        let (sync_control, _) = SyncControl::new(CursorStream::Jetstream);
+        AppState {
+            http_client: reqwest::Client::new(),
+            base_url: "http://atchef.test".into(),
+            client_id: "http://atchef.test/client-metadata.json".into(),
+            sqlite_pool: pool,
+            blob_cache,
+            blob_warmer,
+            admin_token: None,
+            identity,
+            sync_control,
+            backfill,
+            pds,
+            app_password_login: true,
+            login_throttle: Arc::new(login_throttle),
+        }
+    }
+
+    async fn log_in(state: &AppState, session: &Session, password: &str) -> Response {
+        let form = AppPasswordLoginForm {
+            handle: "Alice.Test".into(),
+            password: password.into(),
+        };
+        app_password_login(State(state.clone()), session.clone(), Form(form)).await
+    }
+
+    #[tokio::test]
+    async fn app_password_login_signs_in() {
+        let logins = Arc::new(AtomicUsize::new(0));
+        let pds = mock_pds(logins.clone()).await;
+        let state = app_state(pds.clone(), LoginThrottle::default()).await;
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+
+        let response = log_in(&state, &session, "app-pass").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/");
+
+        let user = session.get::<AuthenticatedUser>(USER_KEY).await.unwrap().unwrap();
+        assert_eq!(user.did, "did:plc:alice");
+        assert_eq!(user.handle, "alice.test");
+        assert_eq!(user.pds_url, pds);
+        assert!(matches!(user.credentials, Credentials::AppPassword { ref access_jwt, .. } if access_jwt == "access"));
+    }
+
+    #[tokio::test]
+    async fn failed_app_password_logins_are_throttled() {
+        let logins = Arc::new(AtomicUsize::new(0));
+        let pds = mock_pds(logins.clone()).await;
+        let state = app_state(pds, LoginThrottle::new(2, Duration::from_secs(60))).await;
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+
+        for _ in 0..2 {
+            let response = log_in(&state, &session, "guess").await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert_eq!(logins.load(Ordering::SeqCst), 2);
+
+        // Locked out: even the right password isn't sent to the PDS
+        let response = log_in(&state, &session, "app-pass").await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("too many failed sign-in attempts"));
+        assert_eq!(logins.load(Ordering::SeqCst), 2);
+        assert!(session.get::<AuthenticatedUser>(USER_KEY).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn images_are_fetched_within_the_size_limit() {
+        let pds = mock_pds(Arc::new(AtomicUsize::new(0))).await;
+        let state = app_state(pds, LoginThrottle::default()).await;
+        let cover = atproto_api::Cid::compute(atproto_api::Cid::RAW, b"cover");
+        let huge = atproto_api::Cid::compute(atproto_api::Cid::RAW, &vec![0u8; crate::blob_warmer::MAX_IMAGE_SIZE + 1]);
+        for (rkey, cid) in [("3k1", &cover), ("3k2", &huge)] {
+            sqlx::query(
+                "INSERT INTO recipes (author_did, rkey, uri, author_handle, name, content, portions, time, created_at, image_cid, image_mime_type) \
+                 VALUES ('did:plc:alice', ?, '', 'alice.test', 'Soup', '', 1, 1, '2025-01-01T00:00:00Z', ?, 'image/png')",
+            )
+            .bind(rkey)
+            .bind(cid.to_string())
+            .execute(&state.sqlite_pool)
+            .await
+            .unwrap();
+        }
+
+        let (data, mime_type) = fetch_and_cache_blob(&cover, &state).await.unwrap().unwrap();
+        assert_eq!((data.as_slice(), mime_type.as_str()), (b"cover".as_slice(), "image/png"));
+        assert!(state.blob_cache.get(&cover.to_string()).await.unwrap().is_some());
+
+        assert!(fetch_and_cache_blob(&huge, &state).await.unwrap().is_none());
+        assert!(state.blob_cache.get(&huge.to_string()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn only_missing_recipes_are_not_found() {
+        let pds = mock_pds(Arc::new(AtomicUsize::new(0))).await;
+        let state = app_state(pds, LoginThrottle::default()).await;
+        let view = |handle: &str, rkey: &str| {
+            let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+            recipe(State(state.clone()), session, RecipePath(handle.into(), rkey.parse().unwrap()))
+        };
+
+        assert_eq!(view("alice.test", "3kgone").await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(view("nobody.test", "3kgone").await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(view("alice.test", "3kdown").await.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn hidden_accounts_have_no_profile() {
+        let pds = mock_pds(Arc::new(AtomicUsize::new(0))).await;
+        let state = app_state(pds, LoginThrottle::default()).await;
+        sqlx::query("INSERT INTO hidden_authors (did) VALUES ('did:plc:alice')")
+            .execute(&state.sqlite_pool)
+            .await
+            .unwrap();
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+
+        let response = public_profile(State(state), Path("alice.test".into()), session).await;
+        assert_eq!(response.status(), StatusCode::GONE);
+    }
+
+    #[tokio::test]
+    async fn invalid_record_keys_get_the_not_found_page() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new().route(
+            "/profile/{handle}/recipe/{rkey}",
+            get(|RecipePath(handle, rkey): RecipePath| async move { format!("{} {}", handle, rkey) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let response = reqwest::get(format!("{}/profile/alice.test/recipe/3k3", url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "alice.test 3k3");
+
+        let response = reqwest::get(format!("{}/profile/alice.test/recipe/bad%20key", url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.text().await.unwrap().contains("Recipe not found"));
+    }
+}
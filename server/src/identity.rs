@@ -0,0 +1,555 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// Shown in place of a handle that fails bidirectional verification.
+pub const INVALID_HANDLE: &str = "handle.invalid";
+
+/// Identifier for profile URLs: the handle, or the DID when the handle
+/// failed verification
+pub fn profile_actor<'a>(did: &'a str, handle: &'a str) -> &'a str {
+    if handle == INVALID_HANDLE { did } else { handle }
+}
+
+/// A resolution that failed for a reason that may go away by itself: a
+/// network error, a timeout, a failed DNS lookup or a 5xx from the PLC
+/// directory. These are never cached, so callers can retry right away;
+/// check with `anyhow::Error::is::<Unavailable>()`.
+#[derive(Debug)]
+pub struct Unavailable(String);
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "identity resolution temporarily unavailable: {}", self.0)
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+#[derive(Clone, Debug)]
+pub struct IdentityCacheConfig {
+    /// How long a successful resolution is served without revalidation.
+    pub ttl: Duration,
+    /// How long a permanent failure (unknown DID, handle without a record)
+    /// is remembered before trying again.
+    pub negative_ttl: Duration,
+    /// How long past `ttl` a stale entry is still served while it is
+    /// refreshed in the background.
+    pub stale_ttl: Duration,
+    /// Most entries kept in memory; the SQLite table backs the rest.
+    pub max_memory_entries: usize,
+}
+
+impl Default for IdentityCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60 * 60),
+            negative_ttl: Duration::from_secs(5 * 60),
+            stale_ttl: Duration::from_secs(24 * 60 * 60),
+            max_memory_entries: 10_000,
+        }
+    }
+}
+
+/// The parts of a DID document the app cares about. This is what the cache
+/// stores for a DID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DidInfo {
+    /// Handle claimed in the document's alsoKnownAs field. Not verified;
+    /// see `IdentityResolver::resolve_did`.
+    pub handle: Option<String>,
+    /// Endpoint of the AtprotoPersonalDataServer service
+    pub pds_url: Option<String>,
+}
+
+impl DidInfo {
+    pub fn pds_url(&self) -> anyhow::Result<&str> {
+        self.pds_url
+            .as_deref()
+            .ok_or_else(|| anyhow!("no PDS service found in DID document"))
+    }
+}
+
+/// What is being resolved. Handles and DIDs share one cache table: DIDs are
+/// keyed by themselves, handles get a `handle:` prefix.
+#[derive(Clone)]
+enum Lookup {
+    Handle(String),
+    Did(String),
+}
+
+impl Lookup {
+    fn cache_key(&self) -> String {
+        match self {
+            Lookup::Handle(handle) => format!("handle:{}", handle),
+            Lookup::Did(did) => did.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CacheEntry {
+    /// JSON-encoded result, or the error message of a permanent failure
+    value: Result<String, String>,
+    fetched_at: i64,
+}
+
+/// Outcome of asking the network, before it is cached.
+enum Fetched {
+    Found(String),
+    /// Cached for `negative_ttl`
+    NotFound(String),
+    /// Not cached
+    Unavailable(String),
+}
+
+/// Resolves handles and DIDs with an in-memory cache backed by the
+/// `identity_cache` table.
+///
+/// Fresh entries are served directly, permanent failures are cached for a
+/// shorter time, and stale entries are served while a background task
+/// refreshes them. Transient failures are returned as `Unavailable` and
+/// not cached.
+pub struct IdentityResolver {
+    resolver: atproto_api::IdentityResolver,
+    pool: SqlitePool,
+    config: IdentityCacheConfig,
+    memory: Mutex<HashMap<String, CacheEntry>>,
+    refreshing: Mutex<HashSet<String>>,
+}
+
+impl IdentityResolver {
+    pub fn new(resolver: atproto_api::IdentityResolver, pool: SqlitePool, config: IdentityCacheConfig) -> Self {
+        Self {
+            resolver,
+            pool,
+            config,
+            memory: Mutex::new(HashMap::new()),
+            refreshing: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Resolve a handle to a DID
+    pub async fn resolve_handle(self: &Arc<Self>, handle: &str) -> anyhow::Result<String> {
+        self.lookup(Lookup::Handle(handle.to_lowercase())).await
+    }
+
+    /// Resolve a DID to its handle and PDS URL.
+    ///
+    /// The handle is only kept if it resolves back to the same DID, so a DID
+    /// document can't claim somebody else's handle.
+    pub async fn resolve_did(self: &Arc<Self>, did: &str) -> anyhow::Result<DidInfo> {
+        let mut info: DidInfo = self.lookup(Lookup::Did(did.to_string())).await?;
+        if let Some(handle) = info.handle.take() {
+            match self.resolve_handle(&handle).await {
+                Ok(resolved) if resolved == did => info.handle = Some(handle),
+                Ok(resolved) => {
+                    tracing::warn!("{} claims handle {}, which belongs to {}", did, handle, resolved)
+                }
+                // Not knowing yet is different from the handle being invalid
+                Err(e) if e.is::<Unavailable>() => return Err(e),
+                Err(e) => tracing::debug!("handle {} of {} does not resolve: {}", handle, did, e),
+            }
+        }
+        Ok(info)
+    }
+
+    pub async fn get_pds_url(self: &Arc<Self>, did: &str) -> anyhow::Result<String> {
+        Ok(self.resolve_did(did).await?.pds_url()?.to_string())
+    }
+
+    /// The verified handle of a DID, or `handle.invalid`
+    pub async fn resolve_did_to_handle(self: &Arc<Self>, did: &str) -> anyhow::Result<String> {
+        Ok(self
+            .resolve_did(did)
+            .await?
+            .handle
+            .unwrap_or_else(|| INVALID_HANDLE.to_string()))
+    }
+
+    /// Resolve a handle or DID from a URL to a DID
+    pub async fn resolve_actor(self: &Arc<Self>, actor: &str) -> anyhow::Result<String> {
+        if actor.starts_with("did:") {
+            Ok(actor.to_string())
+        } else {
+            self.resolve_handle(actor).await
+        }
+    }
+
+    /// Drop everything cached about a DID, e.g. after an identity event:
+    /// its document, the handle it had, and `handle`, its new handle if
+    /// known.
+    pub async fn invalidate(&self, did: &str, handle: Option<&str>) -> anyhow::Result<()> {
+        let did_key = Lookup::Did(did.to_string()).cache_key();
+        let mut keys = vec![];
+        let old_handle = self
+            .cached(&did_key)
+            .await
+            .and_then(|entry| entry.value.ok())
+            .and_then(|json| serde_json::from_str::<DidInfo>(&json).ok())
+            .and_then(|info| info.handle);
+        for handle in old_handle.as_deref().into_iter().chain(handle) {
+            keys.push(Lookup::Handle(handle.to_lowercase()).cache_key());
+        }
+        keys.push(did_key);
+        {
+            let mut memory = self.memory.lock().unwrap();
+            for key in &keys {
+                memory.remove(key);
+            }
+        }
+        for key in &keys {
+            sqlx::query("DELETE FROM identity_cache WHERE key = ?")
+                .bind(key)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn lookup<T: Serialize + DeserializeOwned>(
+        self: &Arc<Self>,
+        lookup: Lookup,
+    ) -> anyhow::Result<T> {
+        let key = lookup.cache_key();
+        let now = chrono::Utc::now().timestamp();
+
+        if let Some(entry) = self.cached(&key).await {
+            let age = now - entry.fetched_at;
+            let ttl = match entry.value {
+                Ok(_) => self.config.ttl,
+                Err(_) => self.config.negative_ttl,
+            }
+            .as_secs() as i64;
+            let stale_ttl = ttl + self.config.stale_ttl.as_secs() as i64;
+
+            if age < ttl || (entry.value.is_ok() && age < stale_ttl) {
+                if age >= ttl {
+                    self.refresh_in_background(lookup);
+                }
+                return decode(entry.value);
+            }
+        }
+
+        match self.fetch(&lookup).await {
+            Fetched::Found(value) => decode(self.store(&key, Ok(value)).await.value),
+            Fetched::NotFound(error) => decode(self.store(&key, Err(error)).await.value),
+            Fetched::Unavailable(error) => Err(Unavailable(error).into()),
+        }
+    }
+
+    async fn cached(&self, key: &str) -> Option<CacheEntry> {
+        if let Some(entry) = self.memory.lock().unwrap().get(key) {
+            return Some(entry.clone());
+        }
+
+        let row: Option<(Option<String>, Option<String>, i64)> =
+            sqlx::query_as("SELECT value, error, fetched_at FROM identity_cache WHERE key = ?")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await
+                .ok()
+                .flatten();
+        let (value, error, fetched_at) = row?;
+        let entry = CacheEntry {
+            value: value.ok_or_else(|| error.unwrap_or_default()),
+            fetched_at,
+        };
+        self.remember(key, entry.clone());
+        Some(entry)
+    }
+
+    /// Keep an entry in memory, making room by dropping the entry fetched
+    /// longest ago once the map is full.
+    fn remember(&self, key: &str, entry: CacheEntry) {
+        let mut memory = self.memory.lock().unwrap();
+        if !memory.contains_key(key) && memory.len() >= self.config.max_memory_entries {
+            let oldest = memory
+                .iter()
+                .min_by_key(|(_, entry)| entry.fetched_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                memory.remove(&oldest);
+            }
+        }
+        if self.config.max_memory_entries > 0 {
+            memory.insert(key.to_string(), entry);
+        }
+    }
+
+    fn refresh_in_background(self: &Arc<Self>, lookup: Lookup) {
+        let key = lookup.cache_key();
+        if !self.refreshing.lock().unwrap().insert(key.clone()) {
+            return;
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            match this.fetch(&lookup).await {
+                Fetched::Found(value) => {
+                    this.store(&key, Ok(value)).await;
+                }
+                Fetched::NotFound(error) => {
+                    this.store(&key, Err(error)).await;
+                }
+                // Keep serving the stale value until it expires
+                Fetched::Unavailable(e) => tracing::debug!("background refresh of {} failed: {}", key, e),
+            }
+            this.refreshing.lock().unwrap().remove(&key);
+        });
+    }
+
+    async fn fetch(&self, lookup: &Lookup) -> Fetched {
+        let value = match lookup {
+            Lookup::Handle(handle) => self.resolver.resolve_handle(handle)
+                .await
+                .and_then(|did| Ok(serde_json::to_string(&did)?)),
+            Lookup::Did(did) => self.resolver.resolve_did(did)
+                .await
+                .and_then(|document| {
+                    let info = DidInfo {
+                        handle: document.handle().map(|handle| handle.to_string()),
+                        pds_url: document.pds_endpoint().map(str::to_string),
+                    };
+                    Ok(serde_json::to_string(&info)?)
+                }),
+        };
+        match value {
+            Ok(value) => Fetched::Found(value),
+            Err(e) if e.is_retryable() => Fetched::Unavailable(e.to_string()),
+            Err(e) => Fetched::NotFound(e.to_string()),
+        }
+    }
+
+    async fn store(&self, key: &str, value: Result<String, String>) -> CacheEntry {
+        let entry = CacheEntry {
+            value,
+            fetched_at: chrono::Utc::now().timestamp(),
+        };
+        self.remember(key, entry.clone());
+
+        let (value, error) = match &entry.value {
+            Ok(v) => (Some(v.as_str()), None),
+            Err(e) => (None, Some(e.as_str())),
+        };
+        let result = sqlx::query(
+            "INSERT OR REPLACE INTO identity_cache (key, value, error, fetched_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(key)
+        .bind(value)
+        .bind(error)
+        .bind(entry.fetched_at)
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("failed to persist identity cache entry {}: {}", key, e);
+        }
+        entry
+    }
+}
+
+fn decode<T: DeserializeOwned>(value: Result<String, String>) -> anyhow::Result<T> {
+    match value {
+        Ok(json) => Ok(serde_json::from_str(&json)?),
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use atproto_api::identity::StaticTxtResolver;
+    use axum::{Json, Router, extract::{Path, State}, http::StatusCode, routing::get};
+
+    use super::*;
+
+    /// A PLC directory that counts the documents it serves. `did:plc:gone`
+    /// doesn't exist and `did:plc:flaky` is behind an outage.
+    async fn mock_plc() -> (String, Arc<AtomicUsize>) {
+        async fn did_document(
+            State(served): State<Arc<AtomicUsize>>,
+            Path(did): Path<String>,
+        ) -> Result<Json<serde_json::Value>, StatusCode> {
+            served.fetch_add(1, Ordering::SeqCst);
+            match did.as_str() {
+                "did:plc:gone" => return Err(StatusCode::NOT_FOUND),
+                "did:plc:flaky" => return Err(StatusCode::SERVICE_UNAVAILABLE),
+                _ => {}
+            }
+            Ok(Json(serde_json::json!({
+                "id": did,
+                "alsoKnownAs": ["at://alice.test"],
+                "service": [{
+                    "id": "#atproto_pds",
+                    "type": "AtprotoPersonalDataServer",
+                    "serviceEndpoint": "https://pds.example.com",
+                }],
+            })))
+        }
+
+        let served = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route("/{did}", get(did_document)).with_state(served.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), served)
+    }
+
+    async fn resolver(config: IdentityCacheConfig) -> (Arc<IdentityResolver>, Arc<AtomicUsize>) {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        crate::db::init_db(&pool).await.unwrap();
+        let (plc, served) = mock_plc().await;
+        let resolver = atproto_api::IdentityResolver::builder()
+            .dns(Arc::new(StaticTxtResolver::new(&[
+                ("_atproto.alice.test.", &["did=did:plc:alice"]),
+                ("_atproto.new.test.", &["did=did:plc:alice"]),
+            ])))
+            .plc_directory(plc)
+            .retry_policy(atproto_api::RetryPolicy::none())
+            .build();
+        (Arc::new(IdentityResolver::new(resolver, pool, config)), served)
+    }
+
+    /// Pretend every cached entry was fetched `secs` earlier.
+    async fn age(resolver: &IdentityResolver, secs: i64) {
+        for entry in resolver.memory.lock().unwrap().values_mut() {
+            entry.fetched_at -= secs;
+        }
+        sqlx::query("UPDATE identity_cache SET fetched_at = fetched_at - ?")
+            .bind(secs)
+            .execute(&resolver.pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn fresh_entries_are_served_from_cache() {
+        let (resolver, served) = resolver(IdentityCacheConfig::default()).await;
+
+        assert_eq!(resolver.resolve_did_to_handle("did:plc:alice").await.unwrap(), "alice.test");
+        assert_eq!(resolver.get_pds_url("did:plc:alice").await.unwrap(), "https://pds.example.com");
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+
+        // Also after a restart, from the SQLite table
+        resolver.memory.lock().unwrap().clear();
+        resolver.resolve_did("did:plc:alice").await.unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn handles_are_verified_both_ways() {
+        let (resolver, _) = resolver(IdentityCacheConfig::default()).await;
+
+        // Every document claims alice.test, but DNS only vouches for Alice
+        assert_eq!(resolver.resolve_did_to_handle("did:plc:alice").await.unwrap(), "alice.test");
+        assert_eq!(resolver.resolve_did_to_handle("did:plc:mallory").await.unwrap(), INVALID_HANDLE);
+        let info = resolver.resolve_did("did:plc:mallory").await.unwrap();
+        assert_eq!(info.handle, None);
+        assert_eq!(info.pds_url().unwrap(), "https://pds.example.com");
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_refetched() {
+        let config = IdentityCacheConfig::default();
+        let expired = (config.ttl + config.stale_ttl).as_secs() as i64 + 1;
+        let (resolver, served) = resolver(config).await;
+
+        resolver.resolve_did("did:plc:alice").await.unwrap();
+        age(&resolver, expired).await;
+        resolver.resolve_did("did:plc:alice").await.unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stale_entries_are_served_while_refreshing() {
+        let config = IdentityCacheConfig::default();
+        let stale = config.ttl.as_secs() as i64 + 1;
+        let (resolver, served) = resolver(config).await;
+
+        resolver.resolve_did("did:plc:alice").await.unwrap();
+        age(&resolver, stale).await;
+        assert_eq!(resolver.get_pds_url("did:plc:alice").await.unwrap(), "https://pds.example.com");
+
+        for _ in 0..100 {
+            if served.load(Ordering::SeqCst) == 2 && resolver.refreshing.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+        // The refresh reset the entry's age
+        resolver.resolve_did("did:plc:alice").await.unwrap();
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn permanent_failures_are_cached_for_negative_ttl() {
+        let config = IdentityCacheConfig::default();
+        let negative_ttl = config.negative_ttl.as_secs() as i64;
+        let (resolver, served) = resolver(config).await;
+
+        for _ in 0..2 {
+            let err = resolver.resolve_did("did:plc:gone").await.unwrap_err();
+            assert!(!err.is::<Unavailable>());
+        }
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+
+        age(&resolver, negative_ttl).await;
+        resolver.resolve_did("did:plc:gone").await.unwrap_err();
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn transient_failures_are_not_cached() {
+        let (resolver, served) = resolver(IdentityCacheConfig::default()).await;
+
+        for _ in 0..2 {
+            let err = resolver.resolve_did_to_handle("did:plc:flaky").await.unwrap_err();
+            assert!(err.is::<Unavailable>());
+        }
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+        let cached: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM identity_cache")
+            .fetch_one(&resolver.pool)
+            .await
+            .unwrap();
+        assert_eq!(cached, 0);
+    }
+
+    #[tokio::test]
+    async fn memory_is_capped() {
+        let (resolver, _) = resolver(IdentityCacheConfig {
+            max_memory_entries: 2,
+            ..Default::default()
+        })
+        .await;
+
+        for did in ["did:plc:a", "did:plc:b", "did:plc:c"] {
+            resolver.lookup::<DidInfo>(Lookup::Did(did.to_string())).await.unwrap();
+            age(&resolver, 1).await;
+        }
+        let memory = resolver.memory.lock().unwrap();
+        assert_eq!(memory.len(), 2);
+        assert!(!memory.contains_key("did:plc:a"));
+    }
+
+    #[tokio::test]
+    async fn invalidate_drops_old_and_new_handle() {
+        let (resolver, _) = resolver(IdentityCacheConfig::default()).await;
+        resolver.resolve_did("did:plc:alice").await.unwrap();
+        resolver.resolve_handle("new.test").await.unwrap();
+
+        resolver.invalidate("did:plc:alice", Some("New.Test")).await.unwrap();
+
+        assert!(resolver.memory.lock().unwrap().is_empty());
+        let cached: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM identity_cache")
+            .fetch_one(&resolver.pool)
+            .await
+            .unwrap();
+        assert_eq!(cached, 0);
+    }
+}
@@ -1,3 +0,0 @@
-// @generated - This file is generated by esquema-codegen (forked from atrium-codegen). DO NOT EDIT.
-pub mod record;
-pub mod eu;
@@ -1,23 +0,0 @@
-// @generated - This file is generated by esquema-codegen (forked from atrium-codegen). DO NOT EDIT.
-//!A collection of known record types.
-#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(tag = "$type")]
-pub enum KnownRecord {
-    #[serde(rename = "eu.atchef.recipe")]
-    EuAtchefRecipe(Box<super::eu::atchef::recipe::Record>),
-}
-impl From<super::eu::atchef::recipe::Record> for KnownRecord {
-    fn from(record: super::eu::atchef::recipe::Record) -> Self {
-        KnownRecord::EuAtchefRecipe(Box::new(record))
-    }
-}
-impl From<super::eu::atchef::recipe::RecordData> for KnownRecord {
-    fn from(record_data: super::eu::atchef::recipe::RecordData) -> Self {
-        KnownRecord::EuAtchefRecipe(Box::new(record_data.into()))
-    }
-}
-impl Into<atrium_api::types::Unknown> for KnownRecord {
-    fn into(self) -> atrium_api::types::Unknown {
-        atrium_api::types::TryIntoUnknown::try_into_unknown(&self).unwrap()
-    }
-}
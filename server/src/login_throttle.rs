@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Locks an account out of app-password sign-in after too many wrong
+/// passwords, so the form can't be used to guess them.
+///
+/// Counts are kept in memory per DID and reset on a successful sign-in or
+/// once `window` has passed since the first failure.
+pub struct LoginThrottle {
+    max_failures: u32,
+    window: Duration,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+struct Failures {
+    count: u32,
+    since: Instant,
+}
+
+/// Returned instead of trying the password while an account is locked out.
+#[derive(Debug)]
+pub struct Throttled(pub Duration);
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = self.0.as_secs().div_ceil(60).max(1);
+        write!(f, "too many failed sign-in attempts; try again in {} minute{}", minutes, if minutes == 1 { "" } else { "s" })
+    }
+}
+
+impl std::error::Error for Throttled {}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(15 * 60))
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(max_failures: u32, window: Duration) -> Self {
+        Self {
+            max_failures,
+            window,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fails while `did` is locked out.
+    pub fn check(&self, did: &str) -> Result<(), Throttled> {
+        let failures = self.failures.lock().unwrap();
+        match failures.get(did) {
+            Some(f) if f.count >= self.max_failures && f.since.elapsed() < self.window => {
+                Err(Throttled(self.window - f.since.elapsed()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, did: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        // Forget expired entries so the map only holds recent failures
+        failures.retain(|_, f| f.since.elapsed() < self.window);
+        failures
+            .entry(did.to_string())
+            .or_insert_with(|| Failures { count: 0, since: Instant::now() })
+            .count += 1;
+    }
+
+    pub fn clear(&self, did: &str) {
+        self.failures.lock().unwrap().remove(did);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locks_out_after_max_failures() {
+        let throttle = LoginThrottle::new(2, Duration::from_secs(60));
+        throttle.record_failure("did:plc:alice");
+        assert!(throttle.check("did:plc:alice").is_ok());
+        throttle.record_failure("did:plc:alice");
+        let Throttled(wait) = throttle.check("did:plc:alice").unwrap_err();
+        assert!(wait <= Duration::from_secs(60));
+
+        // Other accounts are unaffected, and signing in resets the count
+        assert!(throttle.check("did:plc:bob").is_ok());
+        throttle.clear("did:plc:alice");
+        assert!(throttle.check("did:plc:alice").is_ok());
+    }
+
+    #[test]
+    fn test_failures_expire() {
+        let throttle = LoginThrottle::new(1, Duration::ZERO);
+        throttle.record_failure("did:plc:alice");
+        assert!(throttle.check("did:plc:alice").is_ok());
+    }
+}
@@ -10,13 +10,17 @@ use tower_sessions_sqlx_store::SqliteStore;
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod backfill;
 mod blob_cache;
+mod blob_warmer;
 mod db;
 mod handlers;
-#[allow(dead_code)]
-mod lexicons;
+mod identity;
+mod login_throttle;
 mod models;
 mod oauth;
+mod pds;
+mod records;
 mod sync;
 mod views;
 
@@ -27,7 +31,14 @@ pub struct AppState {
     pub client_id: String,
     pub sqlite_pool: SqlitePool,
     pub blob_cache: Arc<blob_cache::BlobCacheService>,
+    pub blob_warmer: blob_warmer::BlobWarmer,
     pub admin_token: Option<String>,
+    pub identity: Arc<identity::IdentityResolver>,
+    pub sync_control: sync::SyncControl,
+    pub backfill: Arc<backfill::Backfill>,
+    pub pds: pds::PdsClient,
+    pub app_password_login: bool,
+    pub login_throttle: Arc<login_throttle::LoginThrottle>,
 }
 
 #[tokio::main]
@@ -94,16 +105,180 @@ async fn main() {
         info!("ADMIN_TOKEN not set — admin routes disabled");
     }
 
+    // `jetstream` (default) or `firehose`, which reads com.atproto.sync.subscribeRepos
+    // from a relay instead
+    let sync_source = std::env::var("SYNC_SOURCE").unwrap_or_else(|_| "jetstream".to_string());
+    info!("SYNC_SOURCE: {}", sync_source);
+    let replay_path = std::env::var("JETSTREAM_REPLAY").ok();
+    let sync_stream = if sync_source == "firehose" && replay_path.is_none() {
+        db::CursorStream::Firehose
+    } else {
+        db::CursorStream::Jetstream
+    };
+    let (sync_control, sync_commands) = sync::SyncControl::new(sync_stream);
+    let http_client = reqwest::Client::new();
+
+    // Retry and timeout for requests to PDSes, relays and the PLC directory
+    let mut pds_retry = atproto_api::RetryPolicy::default();
+    pds_retry.max_retries = std::env::var("PDS_MAX_RETRIES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(pds_retry.max_retries);
+    let pds_timeout = std::time::Duration::from_secs(
+        std::env::var("PDS_REQUEST_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30),
+    );
+    info!("PDS_MAX_RETRIES: {}, PDS_REQUEST_TIMEOUT_SECS: {}", pds_retry.max_retries, pds_timeout.as_secs());
+    let pds = pds::PdsClient::new(http_client.clone(), pds_retry, pds_timeout);
+
+    let mut identity_config = identity::IdentityCacheConfig::default();
+    if let Some(ttl) = std::env::var("IDENTITY_CACHE_TTL_SECS").ok().and_then(|v| v.parse().ok()) {
+        identity_config.ttl = std::time::Duration::from_secs(ttl);
+    }
+    // AppView asked to resolve handles that don't resolve via DNS or HTTPS;
+    // set to "none" to rely on DNS and HTTPS only
+    let handle_fallback = match std::env::var("HANDLE_RESOLVER_FALLBACK") {
+        Ok(v) if v.eq_ignore_ascii_case("none") || v.is_empty() => None,
+        Ok(v) => Some(v),
+        Err(_) => Some(atproto_api::identity::DEFAULT_HANDLE_FALLBACK.to_string()),
+    };
+    info!("HANDLE_RESOLVER_FALLBACK: {}", handle_fallback.as_deref().unwrap_or("none"));
+    let mut resolver = pds.identity_resolver();
+    if let Some(fallback) = handle_fallback {
+        resolver = resolver.handle_fallback(fallback);
+    }
+    if let Ok(plc_directory) = std::env::var("PLC_DIRECTORY_URL") {
+        resolver = resolver.plc_directory(plc_directory);
+    }
+    let identity = Arc::new(identity::IdentityResolver::new(
+        resolver.build(),
+        sqlite_pool.clone(),
+        identity_config,
+    ));
+
+    // Backfill discovers repos through a relay's listReposByCollection
+    let mut backfill_config = backfill::BackfillConfig::default();
+    if let Ok(relay_url) = std::env::var("BACKFILL_RELAY_URL") {
+        backfill_config.relay_url = relay_url;
+    }
+    backfill_config.concurrency = std::env::var("BACKFILL_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(backfill_config.concurrency);
+    info!("BACKFILL_RELAY_URL: {}", backfill_config.relay_url);
+    let backfill = Arc::new(backfill::Backfill::new(
+        pds.clone(),
+        identity.clone(),
+        sqlite_pool.clone(),
+        backfill_config,
+    ));
+
+    let mut warmer_config = blob_warmer::BlobWarmerConfig::default();
+    warmer_config.concurrency = std::env::var("BLOB_WARM_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(warmer_config.concurrency);
+    let blob_warmer = blob_warmer::BlobWarmer::start(
+        warmer_config,
+        pds.clone(),
+        identity.clone(),
+        blob_cache.clone(),
+    );
+
+    // Sign-in through createSession for PDSes without working OAuth
+    let app_password_login = std::env::var("APP_PASSWORD_LOGIN")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    info!("APP_PASSWORD_LOGIN: {}", app_password_login);
+
     let state = AppState {
-        http_client: reqwest::Client::new(),
+        http_client,
         base_url,
         client_id,
         sqlite_pool,
         blob_cache,
+        blob_warmer,
         admin_token,
+        identity,
+        sync_control,
+        backfill,
+        pds,
+        app_password_login,
+        login_throttle: Arc::new(login_throttle::LoginThrottle::default()),
     };
 
-    tokio::spawn(sync::run(state.http_client.clone(), state.sqlite_pool.clone(), state.blob_cache.clone()));
+    // Comma-separated Jetstream URLs, e.g. `ws://localhost:6008` for a local stand-in
+    let mut jetstream_config = sync::JetstreamConfig::default();
+    if let Ok(endpoints) = std::env::var("JETSTREAM_ENDPOINTS") {
+        let endpoints: Vec<String> = endpoints
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if !endpoints.is_empty() {
+            jetstream_config.endpoints = endpoints;
+        }
+    }
+    info!("JETSTREAM_ENDPOINTS: {}", jetstream_config.endpoints.join(", "));
+
+    // Compressed mode needs Jetstream's zstd dictionary (pkg/models/zstd_dictionary
+    // in the Jetstream repo)
+    let compress = std::env::var("JETSTREAM_COMPRESS")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+    if compress {
+        let dictionary_path = std::env::var("JETSTREAM_ZSTD_DICTIONARY")
+            .unwrap_or_else(|_| "zstd_dictionary".to_string());
+        info!("JETSTREAM_ZSTD_DICTIONARY: {}", dictionary_path);
+        let dictionary = sync::load_zstd_dictionary(std::path::Path::new(&dictionary_path))
+            .context("JETSTREAM_COMPRESS is set but the zstd dictionary can't be loaded")
+            .unwrap();
+        jetstream_config.zstd_dictionary = Some(dictionary);
+    }
+
+    let mut indexers = sync::IndexerRegistry::new();
+    indexers.register(sync::recipe::RecipeIndexer::new(
+        state.identity.clone(),
+        state.blob_warmer.clone(),
+    ));
+
+    // Record frames for offline replay, or replay a recording instead of
+    // connecting to Jetstream
+    jetstream_config.record_path = std::env::var("JETSTREAM_RECORD").ok().map(Into::into);
+    if let Some(replay_path) = replay_path {
+        info!("JETSTREAM_REPLAY: {}", replay_path);
+        let identity = state.identity.clone();
+        let pool = state.sqlite_pool.clone();
+        tokio::spawn(async move {
+            if let Err(e) = sync::replay(replay_path.as_ref(), identity, pool, indexers).await {
+                tracing::error!("jetstream replay failed: {e}");
+            }
+        });
+    } else if sync_stream == db::CursorStream::Firehose {
+        let mut firehose_config = sync::firehose::FirehoseConfig::default();
+        if let Ok(relay_url) = std::env::var("FIREHOSE_RELAY_URL") {
+            firehose_config.relay_url = relay_url;
+        }
+        info!("FIREHOSE_RELAY_URL: {}", firehose_config.relay_url);
+        tokio::spawn(sync::firehose::run(
+            firehose_config,
+            state.identity.clone(),
+            state.sqlite_pool.clone(),
+            state.pds.clone(),
+            indexers,
+            sync_commands,
+        ));
+    } else {
+        tokio::spawn(sync::run(
+            jetstream_config,
+            state.identity.clone(),
+            state.sqlite_pool.clone(),
+            indexers,
+            sync_commands,
+        ));
+    }
 
     let app = Router::new()
         .route("/", get(handlers::home))
@@ -123,6 +298,7 @@ async fn main() {
             "/login",
             get(handlers::login_page_handler).post(handlers::login_start),
         )
+        .route("/login/app-password", post(handlers::app_password_login))
         .route("/oauth/callback", get(handlers::oauth_callback))
         .route("/logout", post(handlers::logout))
         .route("/profile", get(handlers::profile))
@@ -130,6 +306,8 @@ async fn main() {
         .route("/admin", get(handlers::admin_page).post(handlers::admin_login))
         .route("/admin/cleanup", post(handlers::admin_cleanup))
         .route("/admin/fix-image-cache", post(handlers::admin_fix_image_cache))
+        .route("/admin/cursor", post(handlers::admin_cursor))
+        .route("/admin/backfill", post(handlers::admin_backfill))
         .route("/client-metadata.json", get(handlers::client_metadata))
         .route(
             "/.well-known/oauth-client-metadata",
@@ -1,11 +1,17 @@
 #[derive(Clone, Debug)]
 pub struct AuthorInfo {
+    pub did: String,
     pub handle: String,
 }
 
 impl AuthorInfo {
-    /// Create a basic AuthorInfo from just handle
-    pub fn basic(handle: String) -> Self {
-        Self { handle }
+    /// Create a basic AuthorInfo from DID and handle
+    pub fn basic(did: String, handle: String) -> Self {
+        Self { did, handle }
+    }
+
+    /// Identifier used in profile URLs
+    pub fn actor(&self) -> &str {
+        crate::identity::profile_actor(&self.did, &self.handle)
     }
 }
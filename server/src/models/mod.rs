@@ -4,5 +4,5 @@ pub mod user;
 
 // Re-export all public types for convenience
 pub use author::AuthorInfo;
-pub use recipe::{Comment, Recipe, RecipeDetail};
+pub use recipe::{Comment, Recipe, RecipeDetail, RecipeDraft};
 pub use user::ProfileRecord;
@@ -1,5 +1,6 @@
 use crate::models::AuthorInfo;
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 
 pub struct Recipe {
     pub id: String,
@@ -58,3 +59,19 @@ pub struct Comment {
     pub time_ago: String,
     pub children: Vec<Comment>,
 }
+
+/// What was typed into the new or edit recipe form. Kept in the session when
+/// a submission fails because the user has to sign in again, so the form can
+/// be filled back in afterwards. Images aren't kept.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RecipeDraft {
+    /// Path of the form page to return to
+    pub return_to: String,
+    pub name: String,
+    pub description: String,
+    pub portions: u64,
+    pub prep_time: u64,
+    pub cook_time: u64,
+    pub content: String,
+    pub post_to_bluesky: bool,
+}
@@ -3,6 +3,7 @@ pub mod dpop;
 pub mod pkce;
 pub mod session;
 pub mod state;
+pub mod token;
 
-pub use session::DpopSession;
-pub use state::{AuthenticatedUser, PendingAuth};
+pub use session::{DpopSession, UserSession};
+pub use state::{AuthenticatedUser, Credentials, PendingAuth};
@@ -1,16 +1,40 @@
+use std::sync::RwLock;
+
 use async_trait::async_trait;
-use atproto_api::{Error as AtprotoError, Session};
+use chrono::{DateTime, Utc};
+use atproto_api::{Error as AtprotoError, PasswordSession, PasswordSessionData, Session};
+use futures_util::future::BoxFuture;
 use jsonwebtoken::jwk::Jwk;
 
-use super::dpop;
+use super::{discovery, dpop, token};
+use super::token::TokenResponse;
+
+/// Called with freshly refreshed tokens so they outlive the request.
+pub type PersistTokens = Box<dyn Fn(TokenResponse) -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+/// Called with an app-password session after it refreshed its tokens.
+pub type PersistPasswordSession = Box<dyn Fn(PasswordSessionData) -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
 
 /// DPoP-based session for authenticated ATProto API requests.
 pub struct DpopSession {
     did: String,
     pds_url: String,
-    access_token: String,
+    access_token: RwLock<String>,
     dpop_private_key_pem: String,
     dpop_public_jwk: Jwk,
+    refresher: Option<Refresher>,
+}
+
+/// What `DpopSession::refresh` needs to get a new access token.
+struct Refresher {
+    http: reqwest::Client,
+    client_id: String,
+    /// Held for the whole refresh so concurrent requests don't spend the
+    /// single-use refresh token twice
+    refresh_token: tokio::sync::Mutex<String>,
+    /// When the current access token runs out
+    expires_at: RwLock<DateTime<Utc>>,
+    persist: PersistTokens,
 }
 
 impl DpopSession {
@@ -24,11 +48,82 @@ impl DpopSession {
         Self {
             did: did.into(),
             pds_url: pds_url.into(),
-            access_token: access_token.into(),
+            access_token: RwLock::new(access_token.into()),
             dpop_private_key_pem: dpop_private_key_pem.into(),
             dpop_public_jwk,
+            refresher: None,
         }
     }
+
+    /// Let the session renew its access token with `refresh_token`, both
+    /// ahead of time once `expires_at` has passed and when the PDS rejects it.
+    ///
+    /// New tokens are handed to `persist` so the caller can store them,
+    /// e.g. in the user's web session.
+    pub fn with_refresh(
+        mut self,
+        http: reqwest::Client,
+        client_id: impl Into<String>,
+        refresh_token: impl Into<String>,
+        expires_at: DateTime<Utc>,
+        persist: PersistTokens,
+    ) -> Self {
+        self.refresher = Some(Refresher {
+            http,
+            client_id: client_id.into(),
+            refresh_token: tokio::sync::Mutex::new(refresh_token.into()),
+            expires_at: RwLock::new(expires_at),
+            persist,
+        });
+        self
+    }
+
+    fn access_token(&self) -> String {
+        self.access_token.read().unwrap().clone()
+    }
+
+    /// Replace `stale` with a new access token, unless another request
+    /// already did. Returns whether the session now holds a fresh token.
+    async fn renew(&self, refresher: &Refresher, stale: &str) -> bool {
+        let mut refresh_token = refresher.refresh_token.lock().await;
+        if self.access_token() != stale {
+            // Another request refreshed while we waited for the lock
+            return true;
+        }
+
+        let tokens = async {
+            let metadata = discovery::get_auth_server_metadata(&refresher.http, &self.pds_url).await?;
+            token::refresh_access_token(
+                &refresher.http,
+                &metadata.token_endpoint,
+                &self.dpop_private_key_pem,
+                &self.dpop_public_jwk,
+                &refresher.client_id,
+                &refresh_token,
+            )
+            .await
+        }
+        .await;
+        let tokens = match tokens {
+            Ok(tokens) => tokens,
+            Err(e) => {
+                tracing::warn!("Token refresh for {} failed: {}", self.did, e);
+                return false;
+            }
+        };
+
+        *self.access_token.write().unwrap() = tokens.access_token.clone();
+        *refresher.expires_at.write().unwrap() = Utc::now() + chrono::Duration::seconds(tokens.expires_in as i64);
+        if let Some(next) = &tokens.refresh_token {
+            *refresh_token = next.clone();
+        }
+        if let Err(e) = (refresher.persist)(tokens).await {
+            // The refreshed token still works for the rest of this request
+            tracing::error!("Failed to persist refreshed tokens for {}: {}", self.did, e);
+        }
+        tracing::info!("Token refreshed for {}", self.did);
+        true
+    }
 }
 
 #[async_trait]
@@ -47,12 +142,21 @@ impl Session for DpopSession {
         url: &str,
         nonce: Option<&str>,
     ) -> Result<Vec<(String, String)>, AtprotoError> {
+        let mut access_token = self.access_token();
+        if let Some(refresher) = &self.refresher
+            && Utc::now() >= *refresher.expires_at.read().unwrap()
+        {
+            // Don't spend a request on a token we know has run out; if the
+            // refresh fails, the PDS's rejection sends the user to sign in
+            self.renew(refresher, &access_token).await;
+            access_token = self.access_token();
+        }
         tracing::debug!(
             "Generating auth headers: method={}, url={}, pds_url={}, token_prefix={}, nonce={:?}",
             method,
             url,
             self.pds_url,
-            &self.access_token[..self.access_token.len().min(20)],
+            &access_token[..access_token.len().min(20)],
             nonce
         );
 
@@ -62,15 +166,190 @@ impl Session for DpopSession {
             method,
             url,
             nonce,
-            Some(&self.access_token),
+            Some(&access_token),
         )
         .map_err(|e| AtprotoError::Session(e.to_string()))?;
 
         tracing::debug!("Auth headers generated successfully");
 
         Ok(vec![
-            ("Authorization".to_string(), format!("DPoP {}", self.access_token)),
+            ("Authorization".to_string(), format!("DPoP {}", access_token)),
             ("DPoP".to_string(), dpop_proof),
         ])
     }
+
+    /// A failed refresh returns `Ok(false)`, so the caller sees the original
+    /// auth error and can send the user to sign in again.
+    async fn refresh(&self) -> Result<bool, AtprotoError> {
+        let Some(refresher) = &self.refresher else {
+            return Ok(false);
+        };
+        let rejected = self.access_token();
+        Ok(self.renew(refresher, &rejected).await)
+    }
+}
+
+/// The signed-in user's session, whichever way they signed in.
+pub enum UserSession {
+    OAuth(Box<DpopSession>),
+    AppPassword {
+        session: Box<PasswordSession>,
+        persist: PersistPasswordSession,
+    },
+}
+
+#[async_trait]
+impl Session for UserSession {
+    fn did(&self) -> &str {
+        match self {
+            UserSession::OAuth(session) => session.did(),
+            UserSession::AppPassword { session, .. } => session.did(),
+        }
+    }
+
+    fn pds_url(&self) -> &str {
+        match self {
+            UserSession::OAuth(session) => session.pds_url(),
+            UserSession::AppPassword { session, .. } => session.pds_url(),
+        }
+    }
+
+    async fn get_auth_headers(
+        &self,
+        method: &str,
+        url: &str,
+        nonce: Option<&str>,
+    ) -> Result<Vec<(String, String)>, AtprotoError> {
+        match self {
+            UserSession::OAuth(session) => session.get_auth_headers(method, url, nonce).await,
+            UserSession::AppPassword { session, .. } => session.get_auth_headers(method, url, nonce).await,
+        }
+    }
+
+    async fn refresh(&self) -> Result<bool, AtprotoError> {
+        match self {
+            UserSession::OAuth(session) => session.refresh().await,
+            UserSession::AppPassword { session, persist } => {
+                if !session.refresh().await? {
+                    return Ok(false);
+                }
+                if let Err(e) = persist(session.data()).await {
+                    // The refreshed token still works for the rest of this request
+                    tracing::error!("Failed to persist refreshed session for {}: {}", session.did(), e);
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use axum::routing::{get, post};
+    use axum::{Form, Json, Router};
+
+    use super::*;
+
+    /// A PDS that is its own authorization server. The token endpoint hands
+    /// out `access-N`/`refresh-N` and records the refresh tokens it was sent.
+    async fn mock_auth_server(spent: Arc<Mutex<Vec<String>>>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let issuer = url.clone();
+        let metadata = url.clone();
+        let app = Router::new()
+            .route(
+                "/.well-known/oauth-protected-resource",
+                get(move || async move { Json(serde_json::json!({ "authorization_servers": [issuer] })) }),
+            )
+            .route(
+                "/.well-known/oauth-authorization-server",
+                get(move || async move {
+                    Json(serde_json::json!({
+                        "issuer": metadata,
+                        "authorization_endpoint": format!("{}/authorize", metadata),
+                        "token_endpoint": format!("{}/token", metadata),
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                post(move |Form(form): Form<std::collections::HashMap<String, String>>| async move {
+                    // Slow enough for concurrent refreshes to overlap
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    let n = {
+                        let mut spent = spent.lock().unwrap();
+                        spent.push(form["refresh_token"].clone());
+                        spent.len()
+                    };
+                    Json(serde_json::json!({
+                        "access_token": format!("access-{}", n),
+                        "refresh_token": format!("refresh-{}", n),
+                        "expires_in": 3600,
+                        "sub": "did:plc:alice",
+                    }))
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn session(pds_url: &str, expires_at: DateTime<Utc>, persisted: Arc<Mutex<Vec<String>>>) -> DpopSession {
+        let key = dpop::generate_keypair().unwrap();
+        DpopSession::new("did:plc:alice", pds_url, "access-0", key.private_key_pem, key.public_jwk).with_refresh(
+            reqwest::Client::new(),
+            "https://atchef.test/client-metadata.json",
+            "refresh-0",
+            expires_at,
+            Box::new(move |tokens| {
+                persisted.lock().unwrap().push(tokens.access_token);
+                Box::pin(async { Ok(()) })
+            }),
+        )
+    }
+
+    fn authorization(headers: &[(String, String)]) -> &str {
+        &headers.iter().find(|(name, _)| name == "Authorization").unwrap().1
+    }
+
+    #[tokio::test]
+    async fn concurrent_refreshes_spend_the_refresh_token_once() {
+        let spent = Arc::new(Mutex::new(Vec::new()));
+        let persisted = Arc::new(Mutex::new(Vec::new()));
+        let pds = mock_auth_server(spent.clone()).await;
+        let session = session(&pds, Utc::now() + chrono::Duration::hours(1), persisted.clone());
+
+        let (first, second) = tokio::join!(session.refresh(), session.refresh());
+        assert!(first.unwrap());
+        assert!(second.unwrap());
+
+        assert_eq!(*spent.lock().unwrap(), ["refresh-0"]);
+        assert_eq!(*persisted.lock().unwrap(), ["access-1"]);
+        let headers = session.get_auth_headers("GET", &pds, None).await.unwrap();
+        assert_eq!(authorization(&headers), "DPoP access-1");
+    }
+
+    #[tokio::test]
+    async fn expired_tokens_are_refreshed_before_the_request() {
+        let spent = Arc::new(Mutex::new(Vec::new()));
+        let persisted = Arc::new(Mutex::new(Vec::new()));
+        let pds = mock_auth_server(spent.clone()).await;
+        let session = session(&pds, Utc::now() - chrono::Duration::minutes(1), persisted.clone());
+
+        let headers = session.get_auth_headers("GET", &pds, None).await.unwrap();
+        assert_eq!(authorization(&headers), "DPoP access-1");
+        assert_eq!(*persisted.lock().unwrap(), ["access-1"]);
+
+        // The new token is good for another hour
+        let headers = session.get_auth_headers("GET", &pds, None).await.unwrap();
+        assert_eq!(authorization(&headers), "DPoP access-1");
+
+        // A rejection still refreshes, with the rotated refresh token
+        assert!(session.refresh().await.unwrap());
+        assert_eq!(*spent.lock().unwrap(), ["refresh-0", "refresh-1"]);
+        assert_eq!(*persisted.lock().unwrap(), ["access-1", "access-2"]);
+    }
 }
@@ -21,11 +21,94 @@ pub struct PendingAuth {
 pub struct AuthenticatedUser {
     pub did: String,
     pub handle: String,
-    pub access_token: String,
-    pub refresh_token: Option<String>,
-    pub expires_at: DateTime<Utc>,
-    pub dpop_private_key_pem: String,
-    pub dpop_public_jwk: Jwk,
+    #[serde(flatten)]
+    pub credentials: Credentials,
     pub pds_url: String,
     pub profile: Option<crate::models::ProfileRecord>,
 }
+
+/// How the user signed in.
+///
+/// Untagged and flattened, so sessions stored before app-password login
+/// existed still load as OAuth.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Credentials {
+    OAuth {
+        access_token: String,
+        refresh_token: Option<String>,
+        expires_at: DateTime<Utc>,
+        dpop_private_key_pem: String,
+        dpop_public_jwk: Box<Jwk>,
+    },
+    /// Signed in through com.atproto.server.createSession
+    AppPassword {
+        access_jwt: String,
+        refresh_jwt: String,
+        /// Where the user signed in, if not their PDS
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        service: Option<String>,
+    },
+}
+
+impl AuthenticatedUser {
+    /// Identifier used in profile URLs
+    pub fn actor(&self) -> &str {
+        crate::identity::profile_actor(&self.did, &self.handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stored_oauth_user_still_loads() {
+        // Shape of AuthenticatedUser before app-password login was added
+        let stored = serde_json::json!({
+            "did": "did:plc:abc123",
+            "handle": "alice.test",
+            "access_token": "access",
+            "refresh_token": "refresh",
+            "expires_at": "2026-01-01T00:00:00Z",
+            "dpop_private_key_pem": "pem",
+            "dpop_public_jwk": {
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU",
+                "y": "x_FEzRu9m36HLN_tue659LNpXW6pCyStikYjKIWI5a0"
+            },
+            "pds_url": "https://pds.example.com",
+            "profile": null
+        });
+
+        let user: AuthenticatedUser = serde_json::from_value(stored.clone()).unwrap();
+        let Credentials::OAuth { access_token, refresh_token, .. } = &user.credentials else {
+            panic!("expected OAuth credentials");
+        };
+        assert_eq!(access_token, "access");
+        assert_eq!(refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(serde_json::to_value(&user).unwrap()["access_token"], stored["access_token"]);
+    }
+
+    #[test]
+    fn test_app_password_user_roundtrip() {
+        let user = AuthenticatedUser {
+            did: "did:plc:abc123".into(),
+            handle: "alice.test".into(),
+            credentials: Credentials::AppPassword {
+                access_jwt: "access".into(),
+                refresh_jwt: "refresh".into(),
+                service: Some("https://entryway.example.com".into()),
+            },
+            pds_url: "https://pds.example.com".into(),
+            profile: None,
+        };
+
+        let json = serde_json::to_value(&user).unwrap();
+        assert_eq!(json["access_jwt"], "access");
+        let loaded: AuthenticatedUser = serde_json::from_value(json).unwrap();
+        assert!(matches!(loaded.credentials, Credentials::AppPassword { ref refresh_jwt, .. } if refresh_jwt == "refresh"));
+        assert!(matches!(loaded.credentials, Credentials::AppPassword { ref service, .. } if service.as_deref() == Some("https://entryway.example.com")));
+    }
+}
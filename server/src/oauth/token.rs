@@ -0,0 +1,104 @@
+use jsonwebtoken::jwk::Jwk;
+use serde::Deserialize;
+
+use super::dpop;
+
+#[derive(Clone, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_in: u64,
+    pub sub: String,
+}
+
+/// The authorization code grant the callback redeems.
+pub struct AuthorizationCode<'a> {
+    pub code: &'a str,
+    pub redirect_uri: &'a str,
+    pub code_verifier: &'a str,
+}
+
+/// Exchange an authorization code for tokens at the end of the OAuth flow.
+pub async fn exchange_token(
+    client: &reqwest::Client,
+    token_endpoint: &str,
+    dpop_private_key_pem: &str,
+    dpop_public_jwk: &Jwk,
+    client_id: &str,
+    grant: AuthorizationCode<'_>,
+) -> anyhow::Result<TokenResponse> {
+    let form = [
+        ("grant_type", "authorization_code"),
+        ("code", grant.code),
+        ("redirect_uri", grant.redirect_uri),
+        ("client_id", client_id),
+        ("code_verifier", grant.code_verifier),
+    ];
+    token_request(client, token_endpoint, dpop_private_key_pem, dpop_public_jwk, &form, "token exchange").await
+}
+
+/// Trade a refresh token for a new access token.
+pub async fn refresh_access_token(
+    client: &reqwest::Client,
+    token_endpoint: &str,
+    dpop_private_key_pem: &str,
+    dpop_public_jwk: &Jwk,
+    client_id: &str,
+    refresh_token: &str,
+) -> anyhow::Result<TokenResponse> {
+    let form = [
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", client_id),
+    ];
+    token_request(client, token_endpoint, dpop_private_key_pem, dpop_public_jwk, &form, "token refresh").await
+}
+
+/// POST a grant to the token endpoint, retrying once with the DPoP nonce
+/// the server asks for.
+async fn token_request(
+    client: &reqwest::Client,
+    token_endpoint: &str,
+    dpop_private_key_pem: &str,
+    dpop_public_jwk: &Jwk,
+    form: &[(&str, &str)],
+    action: &str,
+) -> anyhow::Result<TokenResponse> {
+    let mut nonce: Option<String> = None;
+    loop {
+        let dpop_proof = dpop::create_proof(
+            dpop_private_key_pem,
+            dpop_public_jwk,
+            "POST",
+            token_endpoint,
+            nonce.as_deref(),
+            None,
+        )?;
+
+        let response = client
+            .post(token_endpoint)
+            .header("DPoP", &dpop_proof)
+            .form(form)
+            .send()
+            .await?;
+
+        // First attempt without nonce; the server answers 400 with one if it needs it
+        if response.status() == 400
+            && nonce.is_none()
+            && let Some(challenge) = response.headers().get("dpop-nonce")
+        {
+            let challenge = challenge.to_str().unwrap_or_default().to_string();
+            tracing::debug!("Retrying {} with DPoP nonce: {}", action, challenge);
+            nonce = Some(challenge);
+            continue;
+        }
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("{} failed: {} - {}", action, status, body));
+        }
+
+        return response.json().await.map_err(Into::into);
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use atproto_api::{Agent, RetryPolicy, Session};
+
+/// Shared settings for requests to PDSes and relays: one HTTP client and the
+/// configured retry policy and timeout, so page handlers, backfill and blob
+/// warming all back off the same way.
+#[derive(Clone)]
+pub struct PdsClient {
+    http: reqwest::Client,
+    retry: RetryPolicy,
+    timeout: Duration,
+}
+
+impl PdsClient {
+    pub fn new(http: reqwest::Client, retry: RetryPolicy, timeout: Duration) -> Self {
+        Self { http, retry, timeout }
+    }
+
+    /// Build an agent for `session`'s PDS.
+    pub fn agent<S: Session>(&self, session: S) -> Agent<S> {
+        Agent::builder(session)
+            .http_client(self.http.clone())
+            .retry_policy(self.retry.clone())
+            .timeout(self.timeout)
+            .build()
+    }
+
+    /// The identity resolver's builder, with the same client, retry policy
+    /// and timeout.
+    pub fn identity_resolver(&self) -> atproto_api::identity::IdentityResolverBuilder {
+        atproto_api::IdentityResolver::builder()
+            .http_client(self.http.clone())
+            .retry_policy(self.retry.clone())
+            .timeout(self.timeout)
+    }
+}
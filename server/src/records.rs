@@ -0,0 +1,243 @@
+//! Records AtChef stores in users' repos, bound to their collections.
+
+use atproto_api::Collection;
+use serde::{Deserialize, Serialize};
+
+use crate::db;
+
+/// The `eu.atchef.recipe` collection.
+pub struct Recipe;
+
+impl Collection for Recipe {
+    const NSID: &'static str = "eu.atchef.recipe";
+    type Record = RecipeRecord;
+}
+
+/// An `eu.atchef.recipe` record, as written by the app and as read back from
+/// PDSes, Jetstream and `listRecords`. The schema is
+/// `lexicons/eu/atchef/recipe.json`; `validate` checks its limits before a
+/// record is written.
+///
+/// Reading is lenient where the lexicon has changed over time: `portions`
+/// and `time` may be missing, and `image` is kept as JSON so records with
+/// old-style image refs still index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeRecord {
+    /// At most 64 characters and 640 bytes
+    pub name: String,
+    /// Recipe in Cooklang format, at most 3000 characters and 15000 bytes
+    pub content: String,
+    /// At least 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub portions: Option<u64>,
+    /// Total time in minutes, at least 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<u64>,
+    pub created_at: String,
+    /// At most 200 characters and 500 bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Minutes, not negative. Signed so records from other clients that
+    /// break this still read.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prep_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cook_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<serde_json::Value>,
+}
+
+/// A recipe that breaks one of the lexicon's limits.
+#[derive(Debug)]
+pub struct InvalidRecipe(String);
+
+impl std::fmt::Display for InvalidRecipe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidRecipe {}
+
+impl RecipeRecord {
+    /// Check the lexicon's `maxGraphemes`, `maxLength` and `minimum` limits.
+    ///
+    /// Graphemes are counted as `char`s, which is never fewer, so text with
+    /// combining marks can be refused slightly early but nothing the lexicon
+    /// forbids gets through.
+    pub fn validate(&self) -> Result<(), InvalidRecipe> {
+        let too_long = |field: &str, value: &str, max_chars: usize, max_bytes: usize| {
+            let chars = value.chars().count();
+            if chars > max_chars {
+                Err(InvalidRecipe(format!("{} is too long ({} characters, at most {})", field, chars, max_chars)))
+            } else if value.len() > max_bytes {
+                Err(InvalidRecipe(format!("{} is too long ({} bytes, at most {})", field, value.len(), max_bytes)))
+            } else {
+                Ok(())
+            }
+        };
+        too_long("name", &self.name, 64, 640)?;
+        too_long("content", &self.content, 3000, 15000)?;
+        too_long("description", self.description.as_deref().unwrap_or_default(), 200, 500)?;
+        if self.portions == Some(0) {
+            return Err(InvalidRecipe("portions must be at least 1".into()));
+        }
+        if self.time == Some(0) {
+            return Err(InvalidRecipe("time must be at least 1 minute".into()));
+        }
+        if self.prep_time.is_some_and(|t| t < 0) {
+            return Err(InvalidRecipe("prep time can't be negative".into()));
+        }
+        if self.cook_time.is_some_and(|t| t < 0) {
+            return Err(InvalidRecipe("cook time can't be negative".into()));
+        }
+        Ok(())
+    }
+
+    /// Use `blob` as the cover image.
+    pub fn set_image(&mut self, blob: &atproto_api::BlobRef) -> anyhow::Result<()> {
+        self.image = Some(serde_json::to_value(blob)?);
+        Ok(())
+    }
+
+    /// CID of the cover image blob (`image.ref.$link`; old records used `image.cid`).
+    pub fn image_cid(&self) -> Option<String> {
+        let image = self.image.as_ref()?;
+        image
+            .get("ref")
+            .and_then(|r| r.get("$link"))
+            .or_else(|| image.get("cid"))
+            .and_then(|cid| cid.as_str())
+            .map(String::from)
+    }
+
+    pub fn image_mime_type(&self) -> Option<String> {
+        self.image.as_ref()
+            .and_then(|img| img.get("mimeType"))
+            .and_then(|mime| mime.as_str())
+            .map(String::from)
+    }
+
+    /// Upsert the record into the index.
+    pub async fn save<'e>(
+        &self,
+        executor: impl sqlx::SqliteExecutor<'e>,
+        did: &str,
+        handle: &str,
+        rkey: &str,
+    ) -> anyhow::Result<()> {
+        let uri = Recipe::uri(did, &rkey.parse()?).to_string();
+        db::save_recipe(
+            executor,
+            &uri,
+            did,
+            handle,
+            rkey,
+            &self.name,
+            &self.content,
+            self.portions.unwrap_or(0) as u32,
+            self.time.unwrap_or(0) as u32,
+            &self.created_at,
+            self.description.as_deref(),
+            self.prep_time.and_then(|v| u32::try_from(v).ok()),
+            self.cook_time.and_then(|v| u32::try_from(v).ok()),
+            self.image_cid().as_deref(),
+            self.image_mime_type().as_deref(),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_image_ref_is_read() {
+        let record: RecipeRecord = serde_json::from_value(serde_json::json!({
+            "name": "Soup",
+            "content": "Boil @water{1%l}.",
+            "createdAt": "2024-01-01T00:00:00Z",
+            "image": {
+                "$type": "blob",
+                "ref": {"$link": "bafkreisoup"},
+                "mimeType": "image/png",
+                "size": 1234,
+            },
+        }))
+        .unwrap();
+        assert_eq!(record.image_cid().as_deref(), Some("bafkreisoup"));
+        assert_eq!(record.image_mime_type().as_deref(), Some("image/png"));
+    }
+
+    #[test]
+    fn legacy_image_ref_is_read() {
+        let record: RecipeRecord = serde_json::from_value(serde_json::json!({
+            "name": "Soup",
+            "content": "Boil @water{1%l}.",
+            "createdAt": "2024-01-01T00:00:00Z",
+            "image": {"cid": "bafkreisoup", "mimeType": "image/png"},
+        }))
+        .unwrap();
+        assert_eq!(record.image_cid().as_deref(), Some("bafkreisoup"));
+        assert_eq!(record.image_mime_type().as_deref(), Some("image/png"));
+    }
+
+    #[test]
+    fn written_record_matches_lexicon() {
+        let mut record = RecipeRecord {
+            name: "Soup".into(),
+            content: "Boil @water{1%l}.".into(),
+            portions: Some(2),
+            time: Some(20),
+            created_at: "2024-01-01T00:00:00Z".into(),
+            description: None,
+            prep_time: Some(5),
+            cook_time: None,
+            image: None,
+        };
+        let cid = "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku".parse().unwrap();
+        record.set_image(&atproto_api::BlobRef::new(cid, "image/jpeg", 1234)).unwrap();
+
+        let json = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["createdAt"], "2024-01-01T00:00:00Z");
+        assert_eq!(json["prepTime"], 5);
+        assert!(json.get("cookTime").is_none());
+        assert!(json.get("description").is_none());
+        assert_eq!(json["image"]["$type"], "blob");
+        assert_eq!(record.image_cid().as_deref(), Some("bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku"));
+    }
+
+    #[test]
+    fn lexicon_limits_are_validated() {
+        let record = RecipeRecord {
+            name: "Soup".into(),
+            content: "Boil @water{1%l}.".into(),
+            portions: Some(2),
+            time: Some(20),
+            created_at: "2024-01-01T00:00:00Z".into(),
+            description: Some("d".repeat(200)),
+            prep_time: None,
+            cook_time: None,
+            image: None,
+        };
+        assert!(record.validate().is_ok());
+
+        let long_name = RecipeRecord { name: "n".repeat(65), ..record.clone() };
+        assert_eq!(long_name.validate().unwrap_err().to_string(), "name is too long (65 characters, at most 64)");
+        // 64 characters, but 256 bytes
+        let wide_name = RecipeRecord { name: "🍲".repeat(64), ..record.clone() };
+        assert!(wide_name.validate().is_ok());
+        let wide_description = RecipeRecord { description: Some("🍲".repeat(126)), ..record.clone() };
+        assert_eq!(wide_description.validate().unwrap_err().to_string(), "description is too long (504 bytes, at most 500)");
+        let long_description = RecipeRecord { description: Some("d".repeat(201)), ..record.clone() };
+        assert!(long_description.validate().is_err());
+        let no_portions = RecipeRecord { portions: Some(0), ..record.clone() };
+        assert_eq!(no_portions.validate().unwrap_err().to_string(), "portions must be at least 1");
+        let negative_prep = RecipeRecord { prep_time: Some(-5), ..record.clone() };
+        assert_eq!(negative_prep.validate().unwrap_err().to_string(), "prep time can't be negative");
+        let negative_cook = RecipeRecord { cook_time: Some(-1), ..record };
+        assert!(negative_cook.validate().is_err());
+    }
+}
@@ -0,0 +1,472 @@
+//! Consumer for a relay's `com.atproto.sync.subscribeRepos` stream, as an
+//! alternative to Jetstream. Frames are DAG-CBOR rather than JSON and commits
+//! carry their records as CAR blocks, but once decoded the events go through
+//! the same indexers and cursor handling as the Jetstream consumer.
+//!
+//! Records missing from a commit's blocks, because the commit was too big to
+//! carry them or the relay left them out, are fetched from the author's PDS
+//! with `com.atproto.sync.getRecord`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use atproto_api::{AnonymousSession, Car, Cid, dag_cbor};
+use ciborium::Value;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::{
+    AccountEvent, Context, CursorCommand, Disconnect, IdentityEvent, IndexerRegistry, LocalFailure,
+    RecordOp, SkippedCursor, apply_account, apply_commit, apply_cursor_command, apply_identity,
+    backoff, jitter, local,
+};
+use crate::identity::{IdentityResolver, Unavailable};
+use crate::{db, pds::PdsClient};
+
+/// Bluesky's relay, which carries every repo on the network.
+pub const DEFAULT_FIREHOSE_RELAY: &str = "wss://bsky.network";
+
+/// Connection settings for the firehose consumer.
+#[derive(Clone, Debug)]
+pub struct FirehoseConfig {
+    /// Relay base URL (`wss://host`). The subscribeRepos path is appended
+    /// when the URL has none.
+    pub relay_url: String,
+    /// Delay before the first reconnect attempt.
+    pub min_backoff: Duration,
+    /// Upper bound for the reconnect delay.
+    pub max_backoff: Duration,
+}
+
+impl Default for FirehoseConfig {
+    fn default() -> Self {
+        Self {
+            relay_url: DEFAULT_FIREHOSE_RELAY.to_string(),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(120),
+        }
+    }
+}
+
+pub async fn run(
+    config: FirehoseConfig,
+    identity: Arc<IdentityResolver>,
+    pool: SqlitePool,
+    pds: PdsClient,
+    indexers: IndexerRegistry,
+    mut commands: mpsc::UnboundedReceiver<CursorCommand>,
+) {
+    let ctx = Context {
+        identity,
+        pool,
+        indexers,
+        stream: db::CursorStream::Firehose,
+    };
+    let relay = &config.relay_url;
+    let mut failures = 0;
+    loop {
+        let mut received_events = false;
+        let result = connect_and_consume(relay, &ctx, &pds, &mut commands, &mut received_events).await;
+        if received_events {
+            failures = 0;
+        }
+        let delay = match result {
+            Ok(Disconnect::CursorChanged) => continue,
+            Ok(Disconnect::Closed) if received_events => {
+                tracing::info!("firehose connection to {relay} closed, reconnecting");
+                continue;
+            }
+            Ok(Disconnect::Closed) => {
+                failures += 1;
+                let delay = jitter(backoff(failures, config.min_backoff, config.max_backoff));
+                tracing::warn!("firehose {relay} closed without events, retrying in {delay:?}");
+                delay
+            }
+            Err(e) if e.is::<LocalFailure>() => {
+                failures += 1;
+                let delay = jitter(backoff(failures, config.min_backoff, config.max_backoff));
+                tracing::error!("failed to apply firehose event: {e}, retrying in {delay:?}");
+                delay
+            }
+            Err(e) => {
+                failures += 1;
+                let delay = jitter(backoff(failures, config.min_backoff, config.max_backoff));
+                tracing::error!("firehose sync error on {relay}: {e}, retrying in {delay:?}");
+                delay
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            Some(command) = commands.recv() => {
+                if let Err(e) = apply_cursor_command(&ctx, command).await {
+                    tracing::error!("failed to apply cursor command: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Build the subscribeRepos URL for a relay, including the resume cursor.
+fn subscribe_url(relay: &str, cursor: Option<i64>) -> anyhow::Result<url::Url> {
+    let mut url = url::Url::parse(relay)?;
+    if url.path().is_empty() || url.path() == "/" {
+        url.set_path("/xrpc/com.atproto.sync.subscribeRepos");
+    }
+    if let Some(c) = cursor {
+        url.query_pairs_mut().append_pair("cursor", &c.to_string());
+    }
+    Ok(url)
+}
+
+async fn connect_and_consume(
+    relay: &str,
+    ctx: &Context,
+    pds: &PdsClient,
+    commands: &mut mpsc::UnboundedReceiver<CursorCommand>,
+    received_events: &mut bool,
+) -> anyhow::Result<Disconnect> {
+    // The relay resumes right after the given sequence number, so unlike
+    // Jetstream's timestamps there is nothing to rewind
+    let cursor = db::get_cursor(&ctx.pool, ctx.stream).await.map_err(local)?;
+    let url = subscribe_url(relay, cursor)?;
+
+    tracing::info!("connecting to firehose {} (cursor: {:?})", relay, cursor);
+    let (ws_stream, _) = connect_async(url.as_str()).await?;
+    tracing::info!("connected to firehose {}", relay);
+
+    let (_, mut read) = ws_stream.split();
+    let mut skipped = SkippedCursor::new(ctx.stream);
+
+    loop {
+        let msg = tokio::select! {
+            msg = read.next() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
+            Some(command) = commands.recv() => {
+                apply_cursor_command(ctx, command).await.map_err(local)?;
+                return Ok(Disconnect::CursorChanged);
+            }
+        };
+        let Message::Binary(frame) = msg else {
+            continue;
+        };
+        *received_events = true;
+        handle_frame(ctx, pds, &mut skipped, &frame).await?;
+    }
+
+    skipped.flush(&ctx.pool).await.map_err(local)?;
+    Ok(Disconnect::Closed)
+}
+
+/// Decode and apply one frame. Frames that can't be decoded are logged and
+/// skipped. Error frames from the relay are returned as they are, failures to
+/// apply an event, including fetching its records, as [`LocalFailure`].
+async fn handle_frame(
+    ctx: &Context,
+    pds: &PdsClient,
+    skipped: &mut SkippedCursor,
+    frame: &[u8],
+) -> anyhow::Result<()> {
+    let event = match decode_frame(frame, &ctx.indexers) {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::warn!("failed to decode firehose frame: {e}");
+            return Ok(());
+        }
+    };
+
+    let (seq, changed) = match event {
+        FirehoseEvent::Commit { seq, repo, mut ops, missing } => {
+            // Walk backwards so removing an op doesn't shift the ones still to fill
+            for (index, cid) in missing.into_iter().rev() {
+                let op = &mut ops[index];
+                match fetch_record(&ctx.identity, pds, &repo, op, &cid).await.map_err(local)? {
+                    Some(record) => op.record = Some(record),
+                    None => {
+                        ops.remove(index);
+                    }
+                }
+            }
+            (seq, apply_commit(ctx, &repo, seq, ops).await.map_err(local)?)
+        }
+        FirehoseEvent::Identity { seq, identity } => {
+            (seq, apply_identity(ctx, seq, identity).await.map_err(local)?)
+        }
+        FirehoseEvent::Account { seq, account } => {
+            (seq, apply_account(ctx, seq, account).await.map_err(local)?)
+        }
+        FirehoseEvent::Other { seq: Some(seq) } => (seq, false),
+        FirehoseEvent::Other { seq: None } => return Ok(()),
+        FirehoseEvent::Error { error, message } => {
+            bail!("relay sent {}: {}", error, message.unwrap_or_default())
+        }
+    };
+    if changed {
+        skipped.clear();
+    } else {
+        skipped.skip(&ctx.pool, seq).await.map_err(local)?;
+    }
+    Ok(())
+}
+
+/// A decoded subscribeRepos frame.
+enum FirehoseEvent {
+    /// A repo commit, reduced to the writes to collections we index.
+    /// `missing` lists the ops whose records weren't in the commit's blocks,
+    /// with the CID each record should have.
+    Commit { seq: i64, repo: String, ops: Vec<RecordOp>, missing: Vec<(usize, Cid)> },
+    Identity { seq: i64, identity: IdentityEvent },
+    Account { seq: i64, account: AccountEvent },
+    /// An event type we don't act on, e.g. `#sync` or `#info`.
+    Other { seq: Option<i64> },
+    /// An error frame; the relay closes the connection after sending one.
+    Error { error: String, message: Option<String> },
+}
+
+#[derive(Deserialize)]
+struct FrameHeader {
+    op: i64,
+    t: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Seq {
+    seq: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct CommitBody {
+    seq: i64,
+    repo: String,
+    #[serde(rename = "tooBig", default)]
+    too_big: bool,
+    /// CAR file with the blocks of the commit, including its records
+    blocks: Value,
+    ops: Vec<CommitOp>,
+}
+
+#[derive(Deserialize)]
+struct CommitOp {
+    action: String,
+    /// `collection/rkey`
+    path: String,
+    /// The new record's CID; null for deletes
+    cid: Option<Value>,
+}
+
+/// Split a frame into its header and body, which are two DAG-CBOR values
+/// back to back, and decode the body according to the header's type.
+fn decode_frame(frame: &[u8], indexers: &IndexerRegistry) -> anyhow::Result<FirehoseEvent> {
+    let mut reader = frame;
+    let header: FrameHeader = ciborium::from_reader(&mut reader)?;
+    let body: Value = ciborium::from_reader(&mut reader)?;
+
+    if header.op == -1 {
+        let body: ErrorBody = body.deserialized()?;
+        return Ok(FirehoseEvent::Error { error: body.error, message: body.message });
+    }
+    match header.t.as_deref() {
+        Some("#commit") => decode_commit(body.deserialized()?, indexers),
+        Some("#identity") => Ok(FirehoseEvent::Identity {
+            seq: body.deserialized::<Seq>()?.seq.ok_or_else(|| anyhow!("identity event without seq"))?,
+            identity: body.deserialized()?,
+        }),
+        Some("#account") => Ok(FirehoseEvent::Account {
+            seq: body.deserialized::<Seq>()?.seq.ok_or_else(|| anyhow!("account event without seq"))?,
+            account: body.deserialized()?,
+        }),
+        _ => Ok(FirehoseEvent::Other { seq: body.deserialized::<Seq>()?.seq }),
+    }
+}
+
+/// Pick out the writes to indexed collections and look up their records in
+/// the commit's CAR blocks. The CAR is only parsed when one of them is there,
+/// which for most of the network's traffic it isn't. Records the blocks don't
+/// hold are listed as missing, to be fetched from the PDS.
+fn decode_commit(commit: CommitBody, indexers: &IndexerRegistry) -> anyhow::Result<FirehoseEvent> {
+    let wanted: Vec<CommitOp> = commit
+        .ops
+        .into_iter()
+        .filter(|op| op.path.split_once('/').is_some_and(|(collection, _)| indexers.get(collection).is_some()))
+        .collect();
+    if wanted.is_empty() {
+        return Ok(FirehoseEvent::Commit { seq: commit.seq, repo: commit.repo, ops: Vec::new(), missing: Vec::new() });
+    }
+
+    // A commit that is too big carries no record blocks at all
+    let blocks = match &commit.blocks {
+        _ if commit.too_big => Car::default(),
+        Value::Bytes(car) => Car::parse(car)?,
+        _ => bail!("commit blocks are not a byte string"),
+    };
+    let mut ops = Vec::with_capacity(wanted.len());
+    let mut missing = Vec::new();
+    for op in wanted {
+        let (collection, rkey) = op.path.split_once('/').expect("filtered above");
+        let record = match op.cid.filter(|_| op.action != "delete") {
+            Some(link) => {
+                let link = dag_cbor::Value::try_from(link)?;
+                let cid = link.as_link().ok_or_else(|| anyhow!("op cid is not a link"))?;
+                if blocks.get(cid).is_none() {
+                    missing.push((ops.len(), cid.clone()));
+                    None
+                } else {
+                    Some(blocks.decode(cid)?.to_json())
+                }
+            }
+            None => None,
+        };
+        ops.push(RecordOp {
+            operation: op.action,
+            collection: collection.to_string(),
+            rkey: rkey.to_string(),
+            record,
+        });
+    }
+    Ok(FirehoseEvent::Commit { seq: commit.seq, repo: commit.repo, ops, missing })
+}
+
+/// Fetch the record an op wrote from the repo's PDS.
+///
+/// Returns `None` when the op should be dropped: the record has changed or
+/// been deleted since, so a later commit carries its current state, or the
+/// repo can't be reached for reasons that won't pass. Outages are errors, so
+/// the event is retried from the saved cursor.
+async fn fetch_record(
+    identity: &Arc<IdentityResolver>,
+    pds: &PdsClient,
+    did: &str,
+    op: &RecordOp,
+    cid: &Cid,
+) -> anyhow::Result<Option<serde_json::Value>> {
+    let pds_url = match identity.get_pds_url(did).await {
+        Ok(url) => url,
+        Err(e) if e.is::<Unavailable>() => return Err(e),
+        Err(e) => {
+            tracing::warn!("not fetching {}/{}/{}: {e}", did, op.collection, op.rkey);
+            return Ok(None);
+        }
+    };
+    let agent = pds.agent(AnonymousSession::new(did, pds_url));
+    let car = match agent.sync().get_record(did, &op.collection, &op.rkey).await {
+        Ok(car) => car,
+        Err(e) if e.is_retryable() || matches!(e, atproto_api::Error::Http(_)) => {
+            return Err(anyhow!("fetching {}/{}/{} failed: {e}", did, op.collection, op.rkey));
+        }
+        Err(e) => {
+            tracing::warn!("not indexing {}/{}/{}: {e}", did, op.collection, op.rkey);
+            return Ok(None);
+        }
+    };
+    match car.get(cid) {
+        Some(block) if cid.verify(block) => Ok(Some(dag_cbor::decode(block)?.to_json())),
+        Some(_) => {
+            tracing::warn!("{} returned a block that does not match {}", agent.pds_url(), cid);
+            Ok(None)
+        }
+        None => {
+            tracing::debug!("{}/{}/{} changed since {}, skipping", did, op.collection, op.rkey, cid);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SkippedCursor, handle_frame};
+    use crate::db::{self, CursorStream};
+    use crate::sync::Context;
+    use crate::sync::tests::{context_for, pds_client, recipes, seed_recipe};
+
+    const CREATE: &[u8] = include_bytes!("../../tests/fixtures/firehose/create.bin");
+    const UPDATE_DELETE: &[u8] = include_bytes!("../../tests/fixtures/firehose/update_delete.bin");
+    const POST: &[u8] = include_bytes!("../../tests/fixtures/firehose/post.bin");
+    const MISSING_BLOCK: &[u8] = include_bytes!("../../tests/fixtures/firehose/missing_block.bin");
+    const TOO_BIG: &[u8] = include_bytes!("../../tests/fixtures/firehose/too_big.bin");
+    const IDENTITY: &[u8] = include_bytes!("../../tests/fixtures/firehose/identity.bin");
+    const ACCOUNT: &[u8] = include_bytes!("../../tests/fixtures/firehose/account.bin");
+    const ERROR: &[u8] = include_bytes!("../../tests/fixtures/firehose/error.bin");
+
+    async fn replay(ctx: &Context, frames: &[&[u8]]) -> anyhow::Result<()> {
+        let mut skipped = SkippedCursor::new(ctx.stream);
+        let pds = pds_client();
+        for frame in frames {
+            handle_frame(ctx, &pds, &mut skipped, frame).await?;
+        }
+        skipped.flush(&ctx.pool).await
+    }
+
+    #[tokio::test]
+    async fn commits_are_indexed_from_car_blocks() {
+        let ctx = context_for(CursorStream::Firehose).await;
+
+        replay(&ctx, &[CREATE]).await.unwrap();
+        let image: (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT image_cid, image_mime_type FROM recipes WHERE rkey = '3k2'")
+                .fetch_one(&ctx.pool)
+                .await
+                .unwrap();
+        assert!(image.0.unwrap().starts_with("bafkrei"));
+        assert_eq!(image.1.as_deref(), Some("image/png"));
+
+        replay(&ctx, &[UPDATE_DELETE, POST]).await.unwrap();
+        assert_eq!(
+            recipes(&ctx.pool).await,
+            [("did:plc:alice".into(), "3k1".into(), "alice.test".into(), "Fluffy pancakes".into())]
+        );
+        // The post isn't indexed, but its sequence number is still flushed,
+        // and the Jetstream cursor is left alone
+        assert_eq!(db::get_cursor(&ctx.pool, CursorStream::Firehose).await.unwrap(), Some(103));
+        assert_eq!(db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn missing_records_are_fetched_from_the_pds() {
+        for frame in [MISSING_BLOCK, TOO_BIG] {
+            let ctx = context_for(CursorStream::Firehose).await;
+
+            replay(&ctx, &[frame]).await.unwrap();
+
+            assert_eq!(
+                recipes(&ctx.pool).await,
+                [("did:plc:alice".into(), "3k3".into(), "alice.test".into(), "Crumpets".into())]
+            );
+            assert_eq!(db::get_cursor(&ctx.pool, CursorStream::Firehose).await.unwrap(), Some(104));
+        }
+    }
+
+    #[tokio::test]
+    async fn identity_and_account_events() {
+        let ctx = context_for(CursorStream::Firehose).await;
+        seed_recipe(&ctx.pool, "did:plc:alice", "alice.old", "3k1").await;
+        seed_recipe(&ctx.pool, "did:plc:bob", "bob.test", "3k2").await;
+
+        replay(&ctx, &[IDENTITY, ACCOUNT]).await.unwrap();
+
+        let visible = db::get_all_recipes(&ctx.pool).await.unwrap();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].author_did, "did:plc:alice");
+        assert_eq!(visible[0].author_handle, "alice.test");
+        assert_eq!(db::get_cursor(&ctx.pool, CursorStream::Firehose).await.unwrap(), Some(106));
+    }
+
+    #[tokio::test]
+    async fn error_frame_ends_the_connection() {
+        let ctx = context_for(CursorStream::Firehose).await;
+
+        let err = replay(&ctx, &[ERROR]).await.unwrap_err();
+        assert!(err.to_string().contains("ConsumerTooSlow"));
+    }
+}
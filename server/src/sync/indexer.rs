@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use sqlx::SqliteConnection;
+
+/// Where a record change came from.
+pub struct RecordCommit<'a> {
+    pub did: &'a str,
+    pub rkey: &'a str,
+    /// Side effects that must only happen once the write is durable.
+    pub after_commit: &'a AfterCommit,
+}
+
+/// Work queued by indexer hooks, run once the event's transaction has
+/// committed. Dropped without running if the transaction rolls back.
+#[derive(Default)]
+pub struct AfterCommit {
+    actions: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+}
+
+impl AfterCommit {
+    pub fn push(&self, action: impl FnOnce() + Send + 'static) {
+        self.actions.lock().unwrap().push(Box::new(action));
+    }
+
+    pub(super) fn run(self) {
+        for action in self.actions.into_inner().unwrap() {
+            action();
+        }
+    }
+}
+
+/// Stores one record collection in the index.
+///
+/// Each hook runs inside the transaction that also advances the sync cursor,
+/// and returns whether it changed the index. Returning `Ok(false)` rolls the
+/// transaction back and the event counts as skipped; returning `Err` leaves
+/// the cursor where it was so the event is retried after reconnecting.
+/// Anything outside the database goes through `RecordCommit::after_commit`.
+#[async_trait]
+pub trait Indexer: Send + Sync {
+    /// NSID of the collection this indexer handles, e.g. `eu.atchef.recipe`.
+    fn collection(&self) -> &'static str;
+
+    async fn on_create(
+        &self,
+        conn: &mut SqliteConnection,
+        commit: &RecordCommit<'_>,
+        record: serde_json::Value,
+    ) -> anyhow::Result<bool>;
+
+    /// Defaults to `on_create`, for indexers that upsert.
+    async fn on_update(
+        &self,
+        conn: &mut SqliteConnection,
+        commit: &RecordCommit<'_>,
+        record: serde_json::Value,
+    ) -> anyhow::Result<bool> {
+        self.on_create(conn, commit, record).await
+    }
+
+    async fn on_delete(&self, conn: &mut SqliteConnection, commit: &RecordCommit<'_>) -> anyhow::Result<bool>;
+}
+
+/// Indexers by collection. The registered collections are what the consumer
+/// asks Jetstream for as `wantedCollections`.
+#[derive(Default)]
+pub struct IndexerRegistry {
+    indexers: BTreeMap<&'static str, Arc<dyn Indexer>>,
+}
+
+impl IndexerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an indexer, replacing any previous one for its collection.
+    pub fn register(&mut self, indexer: impl Indexer + 'static) {
+        self.indexers.insert(indexer.collection(), Arc::new(indexer));
+    }
+
+    pub fn get(&self, collection: &str) -> Option<&Arc<dyn Indexer>> {
+        self.indexers.get(collection)
+    }
+
+    pub fn collections(&self) -> Vec<&'static str> {
+        self.indexers.keys().copied().collect()
+    }
+}
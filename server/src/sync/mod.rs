@@ -0,0 +1,755 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use rand::Rng;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+use zstd::dict::DecoderDictionary;
+
+use crate::db;
+use crate::identity::{INVALID_HANDLE, IdentityResolver, Unavailable};
+
+pub mod firehose;
+mod indexer;
+pub mod recipe;
+
+pub use indexer::{AfterCommit, Indexer, IndexerRegistry, RecordCommit};
+
+#[cfg(test)]
+mod tests;
+
+/// Public Jetstream instances run by Bluesky, in order of preference.
+pub const DEFAULT_JETSTREAM_ENDPOINTS: &[&str] = &[
+    "wss://jetstream2.us-east.bsky.network",
+    "wss://jetstream1.us-east.bsky.network",
+    "wss://jetstream1.us-west.bsky.network",
+    "wss://jetstream2.us-west.bsky.network",
+];
+
+/// How far to rewind the saved cursor when reconnecting, as recommended by
+/// the Jetstream docs, so no event is lost across a dropped connection.
+const CURSOR_REWIND_US: i64 = 5_000_000;
+
+/// Events that don't touch the index don't need their own transaction; their
+/// cursor is persisted at most this often.
+const SKIPPED_CURSOR_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Admin request to move the sync cursor.
+#[derive(Debug)]
+pub enum CursorCommand {
+    /// Forget the cursor and resume from the live tail.
+    Reset,
+    /// Replay from the given time (microseconds since the Unix epoch). Only
+    /// Jetstream cursors are times.
+    RewindTo(i64),
+}
+
+/// Handle for steering the running consumer from request handlers.
+///
+/// Cursor changes are applied by the consumer task itself, so they can't race
+/// with the cursor updates it makes while processing events.
+#[derive(Clone)]
+pub struct SyncControl {
+    commands: mpsc::UnboundedSender<CursorCommand>,
+    stream: db::CursorStream,
+}
+
+impl SyncControl {
+    /// `stream` is the cursor of the consumer that will receive the commands.
+    pub fn new(stream: db::CursorStream) -> (Self, mpsc::UnboundedReceiver<CursorCommand>) {
+        let (commands, rx) = mpsc::unbounded_channel();
+        (Self { commands, stream }, rx)
+    }
+
+    /// The cursor the running consumer advances.
+    pub fn stream(&self) -> db::CursorStream {
+        self.stream
+    }
+
+    /// Ask the consumer to move its cursor and reconnect.
+    pub fn send(&self, command: CursorCommand) -> anyhow::Result<()> {
+        // Firehose cursors are sequence numbers, which can't be derived from a time
+        if matches!(command, CursorCommand::RewindTo(_)) && self.stream != db::CursorStream::Jetstream {
+            anyhow::bail!("rewinding to a time is only supported when syncing from jetstream");
+        }
+        self.commands
+            .send(command)
+            .map_err(|_| anyhow::anyhow!("sync consumer is not running"))
+    }
+}
+
+/// Connection settings for the Jetstream consumer.
+#[derive(Clone, Debug)]
+pub struct JetstreamConfig {
+    /// Jetstream base URLs (`wss://host` or `ws://host:port`), tried in order.
+    /// A `/subscribe` path is appended when the URL has none.
+    pub endpoints: Vec<String>,
+    /// Delay before the first reconnect attempt.
+    pub min_backoff: Duration,
+    /// Upper bound for the reconnect delay.
+    pub max_backoff: Duration,
+    /// Jetstream's published zstd dictionary. When set, the consumer asks for
+    /// compressed frames (`compress=true`) and decodes them with it.
+    pub zstd_dictionary: Option<Vec<u8>>,
+    /// Append every received frame to this JSONL file, for use with `replay`.
+    pub record_path: Option<PathBuf>,
+}
+
+impl Default for JetstreamConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: DEFAULT_JETSTREAM_ENDPOINTS.iter().map(|s| s.to_string()).collect(),
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(120),
+            zstd_dictionary: None,
+            record_path: None,
+        }
+    }
+}
+
+/// Health bookkeeping for a single Jetstream endpoint.
+struct Endpoint {
+    url: String,
+    consecutive_failures: u32,
+    unhealthy_until: Option<Instant>,
+}
+
+/// Picks which endpoint to connect to and how long to wait between attempts.
+///
+/// An endpoint that fails is put on a cooldown that grows with its number of
+/// consecutive failures, so the next attempt fails over to the next healthy
+/// endpoint in preference order. Once every endpoint is cooling down, the one
+/// that recovers first is used.
+struct EndpointPool {
+    endpoints: Vec<Endpoint>,
+    current: usize,
+    min_backoff: Duration,
+    max_backoff: Duration,
+    /// Failed attempts since the last connection that delivered events.
+    streak: u32,
+}
+
+impl EndpointPool {
+    fn new(config: &JetstreamConfig) -> Self {
+        let urls: Vec<String> = if config.endpoints.is_empty() {
+            DEFAULT_JETSTREAM_ENDPOINTS.iter().map(|s| s.to_string()).collect()
+        } else {
+            config.endpoints.clone()
+        };
+        Self {
+            endpoints: urls
+                .into_iter()
+                .map(|url| Endpoint { url, consecutive_failures: 0, unhealthy_until: None })
+                .collect(),
+            current: 0,
+            min_backoff: config.min_backoff,
+            max_backoff: config.max_backoff,
+            streak: 0,
+        }
+    }
+
+    /// Select the preferred healthy endpoint and return its URL.
+    fn select(&mut self) -> &str {
+        let now = Instant::now();
+        let healthy = self
+            .endpoints
+            .iter()
+            .position(|e| e.unhealthy_until.is_none_or(|until| until <= now));
+        self.current = healthy.unwrap_or_else(|| {
+            self.endpoints
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, e)| e.unhealthy_until)
+                .map(|(i, _)| i)
+                .unwrap_or(0)
+        });
+        &self.endpoints[self.current].url
+    }
+
+    /// The connection delivered events, so the endpoint is healthy again.
+    fn mark_healthy(&mut self) {
+        let endpoint = &mut self.endpoints[self.current];
+        endpoint.consecutive_failures = 0;
+        endpoint.unhealthy_until = None;
+        self.streak = 0;
+    }
+
+    /// Record a failed attempt and return how long to wait before the next one.
+    fn mark_failed(&mut self) -> Duration {
+        let cooldown = {
+            let endpoint = &mut self.endpoints[self.current];
+            endpoint.consecutive_failures += 1;
+            backoff(endpoint.consecutive_failures, self.min_backoff, self.max_backoff)
+        };
+        self.endpoints[self.current].unhealthy_until = Some(Instant::now() + cooldown);
+        self.streak += 1;
+        jitter(backoff(self.streak, self.min_backoff, self.max_backoff))
+    }
+}
+
+/// Exponential backoff: `min * 2^(attempt - 1)`, capped at `max`.
+fn backoff(attempt: u32, min: Duration, max: Duration) -> Duration {
+    let exp = attempt.saturating_sub(1).min(16);
+    min.saturating_mul(1 << exp).min(max)
+}
+
+/// Randomise a delay to somewhere between half and all of it, so many
+/// instances restarting together don't reconnect in lockstep.
+fn jitter(delay: Duration) -> Duration {
+    let half = delay / 2;
+    half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+}
+
+/// First four bytes of a trained zstd dictionary.
+const ZSTD_DICTIONARY_MAGIC: [u8; 4] = [0x37, 0xa4, 0x30, 0xec];
+
+/// Read Jetstream's zstd dictionary (`pkg/models/zstd_dictionary` in the
+/// Jetstream repo). Anything that isn't a trained dictionary is refused, since
+/// every compressed frame would fail to decode with it.
+pub fn load_zstd_dictionary(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let dictionary = std::fs::read(path)?;
+    if !dictionary.starts_with(&ZSTD_DICTIONARY_MAGIC) {
+        anyhow::bail!("{} is not a zstd dictionary", path.display());
+    }
+    Ok(dictionary)
+}
+
+/// Turns websocket messages into event JSON, decompressing binary frames when
+/// compressed mode is on.
+struct FrameDecoder {
+    dictionary: Option<DecoderDictionary<'static>>,
+}
+
+impl FrameDecoder {
+    fn new(config: &JetstreamConfig) -> Self {
+        Self {
+            dictionary: config.zstd_dictionary.as_deref().map(DecoderDictionary::copy),
+        }
+    }
+
+    fn compressed(&self) -> bool {
+        self.dictionary.is_some()
+    }
+
+    /// Returns `None` for control frames and binary frames we can't decode.
+    fn decode(&self, msg: Message) -> anyhow::Result<Option<String>> {
+        match msg {
+            Message::Text(text) => Ok(Some(text.to_string())),
+            Message::Binary(data) => {
+                let Some(dictionary) = &self.dictionary else {
+                    return Ok(None);
+                };
+                let mut decoder = zstd::stream::read::Decoder::with_prepared_dictionary(&data[..], dictionary)?;
+                let mut text = String::new();
+                decoder.read_to_string(&mut text)?;
+                Ok(Some(text))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Build the subscribe URL for an endpoint, including wanted collections and
+/// the resume cursor.
+fn subscribe_url(
+    endpoint: &str,
+    collections: &[&str],
+    cursor: Option<i64>,
+    compress: bool,
+) -> anyhow::Result<url::Url> {
+    let mut url = url::Url::parse(endpoint)?;
+    if url.path().is_empty() || url.path() == "/" {
+        url.set_path("/subscribe");
+    }
+    {
+        let mut query = url.query_pairs_mut();
+        for collection in collections {
+            query.append_pair("wantedCollections", collection);
+        }
+        if let Some(c) = cursor {
+            query.append_pair("cursor", &c.to_string());
+        }
+        if compress {
+            query.append_pair("compress", "true");
+        }
+    }
+    Ok(url)
+}
+
+#[derive(Deserialize)]
+struct JetstreamEvent {
+    did: String,
+    time_us: i64,
+    kind: String,
+    commit: Option<RecordOp>,
+    identity: Option<IdentityEvent>,
+    account: Option<AccountEvent>,
+}
+
+/// A handle change. Jetstream and the firehose use the same field names.
+#[derive(Deserialize)]
+struct IdentityEvent {
+    did: String,
+    handle: Option<String>,
+}
+
+/// A hosting status change, shaped the same in Jetstream and the firehose.
+#[derive(Deserialize)]
+struct AccountEvent {
+    did: String,
+    active: bool,
+    status: Option<String>,
+}
+
+/// One record write. Jetstream's `commit` object deserializes straight into
+/// this; the firehose builds it from the ops of a repo commit.
+#[derive(Deserialize)]
+struct RecordOp {
+    operation: String,
+    collection: String,
+    rkey: String,
+    record: Option<serde_json::Value>,
+}
+
+/// Everything the consumer needs to apply events to the index.
+struct Context {
+    identity: Arc<IdentityResolver>,
+    pool: SqlitePool,
+    indexers: IndexerRegistry,
+    /// Which saved cursor events advance.
+    stream: db::CursorStream,
+}
+
+/// An event couldn't be applied locally, e.g. a database error or an identity
+/// lookup that failed transiently. It says nothing about the endpoint, so it
+/// doesn't count against the endpoint's health; the consumer reconnects and
+/// the event is retried from the saved cursor.
+#[derive(Debug)]
+struct LocalFailure(anyhow::Error);
+
+impl std::fmt::Display for LocalFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.0)
+    }
+}
+
+impl std::error::Error for LocalFailure {}
+
+fn local(e: anyhow::Error) -> anyhow::Error {
+    LocalFailure(e).into()
+}
+
+pub async fn run(
+    config: JetstreamConfig,
+    identity: Arc<IdentityResolver>,
+    pool: SqlitePool,
+    indexers: IndexerRegistry,
+    mut commands: mpsc::UnboundedReceiver<CursorCommand>,
+) {
+    let ctx = Context {
+        identity,
+        pool,
+        indexers,
+        stream: db::CursorStream::Jetstream,
+    };
+    let mut recorder = match &config.record_path {
+        Some(path) => match FrameRecorder::open(path).await {
+            Ok(r) => {
+                tracing::info!("recording jetstream frames to {}", path.display());
+                Some(r)
+            }
+            Err(e) => {
+                tracing::error!("failed to open {} for recording: {e}", path.display());
+                None
+            }
+        },
+        None => None,
+    };
+    let mut endpoints = EndpointPool::new(&config);
+    let mut local_failures = 0;
+    let decoder = FrameDecoder::new(&config);
+    if decoder.compressed() {
+        tracing::info!("jetstream compression enabled");
+    }
+    loop {
+        let endpoint = endpoints.select().to_string();
+        let mut received_events = false;
+        let result = connect_and_consume(
+            &endpoint,
+            &decoder,
+            &ctx,
+            &mut recorder,
+            &mut commands,
+            &mut received_events,
+        )
+        .await;
+        if received_events {
+            endpoints.mark_healthy();
+        }
+        if result.is_ok() {
+            local_failures = 0;
+        }
+        let delay = match result {
+            Ok(Disconnect::CursorChanged) => continue,
+            Ok(Disconnect::Closed) if received_events => {
+                tracing::info!("jetstream connection to {endpoint} closed, reconnecting");
+                continue;
+            }
+            Ok(Disconnect::Closed) => {
+                let delay = endpoints.mark_failed();
+                tracing::warn!("jetstream {endpoint} closed without events, retrying in {delay:?}");
+                delay
+            }
+            Err(e) if e.is::<LocalFailure>() => {
+                local_failures += 1;
+                let delay = jitter(backoff(local_failures, config.min_backoff, config.max_backoff));
+                tracing::error!("failed to apply jetstream event: {e}, retrying in {delay:?}");
+                delay
+            }
+            Err(e) => {
+                let delay = endpoints.mark_failed();
+                tracing::error!("jetstream sync error on {endpoint}: {e}, retrying in {delay:?}");
+                delay
+            }
+        };
+        // Admin cursor changes still apply while we're backing off
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            Some(command) = commands.recv() => {
+                if let Err(e) = apply_cursor_command(&ctx, command).await {
+                    tracing::error!("failed to apply cursor command: {e}");
+                }
+            }
+        }
+    }
+}
+
+/// Why `connect_and_consume` returned without an error.
+enum Disconnect {
+    /// The server closed the stream.
+    Closed,
+    /// An admin moved the cursor; reconnect from the new position right away.
+    CursorChanged,
+}
+
+async fn apply_cursor_command(ctx: &Context, command: CursorCommand) -> anyhow::Result<()> {
+    match (command, ctx.stream) {
+        (CursorCommand::Reset, stream) => {
+            db::clear_cursor(&ctx.pool, stream).await?;
+            tracing::info!("{stream:?} cursor reset, resuming from live tail");
+        }
+        (CursorCommand::RewindTo(time_us), db::CursorStream::Jetstream) => {
+            db::save_cursor(&ctx.pool, db::CursorStream::Jetstream, time_us).await?;
+            tracing::info!("jetstream cursor rewound to {time_us}");
+        }
+        // Firehose cursors are sequence numbers, which can't be derived from a time
+        (CursorCommand::RewindTo(_), db::CursorStream::Firehose) => {
+            tracing::warn!("rewinding to a time is only supported when syncing from jetstream");
+        }
+    }
+    Ok(())
+}
+
+async fn connect_and_consume(
+    endpoint: &str,
+    decoder: &FrameDecoder,
+    ctx: &Context,
+    recorder: &mut Option<FrameRecorder>,
+    commands: &mut mpsc::UnboundedReceiver<CursorCommand>,
+    received_events: &mut bool,
+) -> anyhow::Result<Disconnect> {
+    // Replaying a few seconds is harmless (every write is an idempotent upsert
+    // or delete keyed by DID + rkey) and covers events that were in flight
+    // when the previous connection dropped.
+    let pool = &ctx.pool;
+    let cursor = db::get_cursor(pool, ctx.stream).await.map_err(local)?.map(|c| c - CURSOR_REWIND_US);
+    let url = subscribe_url(endpoint, &ctx.indexers.collections(), cursor, decoder.compressed())?;
+
+    tracing::info!("connecting to jetstream {} (cursor: {:?})", endpoint, cursor);
+    let (ws_stream, _) = connect_async(url.as_str()).await?;
+    tracing::info!("connected to jetstream {}", endpoint);
+
+    let (_, mut read) = ws_stream.split();
+    let mut skipped = SkippedCursor::new(ctx.stream);
+
+    loop {
+        let msg = tokio::select! {
+            msg = read.next() => match msg {
+                Some(msg) => msg?,
+                None => break,
+            },
+            Some(command) = commands.recv() => {
+                apply_cursor_command(ctx, command).await.map_err(local)?;
+                return Ok(Disconnect::CursorChanged);
+            }
+        };
+        let text = match decoder.decode(msg) {
+            Ok(Some(text)) => text,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("failed to decompress jetstream frame: {e}");
+                continue;
+            }
+        };
+        *received_events = true;
+
+        if let Some(r) = recorder
+            && let Err(e) = r.record(&text).await
+        {
+            tracing::error!("failed to record jetstream frame, recording stopped: {e}");
+            *recorder = None;
+        }
+        handle_frame(ctx, &mut skipped, &text).await?;
+    }
+
+    skipped.flush(pool).await.map_err(local)?;
+    Ok(Disconnect::Closed)
+}
+
+/// Appends received frames to a JSONL file, one frame per line.
+struct FrameRecorder {
+    file: tokio::fs::File,
+}
+
+impl FrameRecorder {
+    async fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(Self { file })
+    }
+
+    async fn record(&mut self, frame: &str) -> anyhow::Result<()> {
+        let mut line = frame.trim_end().to_string();
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Feed frames recorded with `JetstreamConfig::record_path` through the same
+/// event handling as the live consumer, then return.
+pub async fn replay(
+    path: &Path,
+    identity: Arc<IdentityResolver>,
+    pool: SqlitePool,
+    indexers: IndexerRegistry,
+) -> anyhow::Result<()> {
+    let ctx = Context {
+        identity,
+        pool,
+        indexers,
+        stream: db::CursorStream::Jetstream,
+    };
+    let frames = tokio::fs::read_to_string(path).await?;
+    let count = replay_frames(&ctx, &frames).await?;
+    tracing::info!("replayed {} jetstream frames from {}", count, path.display());
+    Ok(())
+}
+
+async fn replay_frames(ctx: &Context, frames: &str) -> anyhow::Result<usize> {
+    let mut skipped = SkippedCursor::new(ctx.stream);
+    let mut count = 0;
+    for frame in frames.lines().filter(|line| !line.trim().is_empty()) {
+        handle_frame(ctx, &mut skipped, frame).await?;
+        count += 1;
+    }
+    skipped.flush(&ctx.pool).await?;
+    Ok(count)
+}
+
+/// Cursor of the newest event that didn't change the index and so wasn't
+/// committed in its own transaction. Flushed periodically.
+struct SkippedCursor {
+    stream: db::CursorStream,
+    cursor: Option<i64>,
+    last_flush: Instant,
+}
+
+impl SkippedCursor {
+    fn new(stream: db::CursorStream) -> Self {
+        Self {
+            stream,
+            cursor: None,
+            last_flush: Instant::now(),
+        }
+    }
+
+    /// Forget the pending cursor; a newer one was just committed.
+    fn clear(&mut self) {
+        self.cursor = None;
+    }
+
+    async fn skip(&mut self, pool: &SqlitePool, cursor: i64) -> anyhow::Result<()> {
+        self.cursor = Some(cursor);
+        if self.last_flush.elapsed() >= SKIPPED_CURSOR_FLUSH_INTERVAL {
+            self.flush(pool).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self, pool: &SqlitePool) -> anyhow::Result<()> {
+        if let Some(cursor) = self.cursor.take() {
+            db::save_cursor(pool, self.stream, cursor).await?;
+        }
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+}
+
+/// Parse and apply one frame. Frames that aren't valid events are logged and
+/// skipped; failures to apply an event are returned as [`LocalFailure`].
+async fn handle_frame(ctx: &Context, skipped: &mut SkippedCursor, text: &str) -> anyhow::Result<()> {
+    let event: JetstreamEvent = match serde_json::from_str(text) {
+        Ok(e) => e,
+        Err(e) => {
+            tracing::warn!("failed to parse jetstream event: {e}");
+            return Ok(());
+        }
+    };
+
+    let time_us = event.time_us;
+    if apply_event(ctx, event).await.map_err(local)? {
+        skipped.clear();
+    } else {
+        skipped.skip(&ctx.pool, time_us).await.map_err(local)?;
+    }
+    Ok(())
+}
+
+/// Apply a single event to the index.
+///
+/// Returns `Ok(true)` when the event changed the index, in which case the
+/// cursor was advanced in the same transaction. Returns `Ok(false)` for events
+/// that were skipped. A database error or a transient identity failure is
+/// returned as `Err` without moving the cursor, so the event is retried after
+/// reconnecting.
+async fn apply_event(
+    ctx: &Context,
+    event: JetstreamEvent,
+) -> anyhow::Result<bool> {
+    match event.kind.as_str() {
+        "commit" => match event.commit {
+            Some(commit) => apply_commit(ctx, &event.did, event.time_us, vec![commit]).await,
+            None => Ok(false),
+        },
+        "identity" => match event.identity {
+            Some(update) => apply_identity(ctx, event.time_us, update).await,
+            None => Ok(false),
+        },
+        "account" => match event.account {
+            Some(account) => apply_account(ctx, event.time_us, account).await,
+            None => Ok(false),
+        },
+        _ => Ok(false),
+    }
+}
+
+/// A handle change. Jetstream sends these for every account on the network,
+/// so only DIDs we already know about result in a write.
+async fn apply_identity(
+    ctx: &Context,
+    cursor: i64,
+    identity: IdentityEvent,
+) -> anyhow::Result<bool> {
+    let pool = &ctx.pool;
+    if !db::is_known_author(pool, &identity.did).await? {
+        return Ok(false);
+    }
+    ctx.identity.invalidate(&identity.did, identity.handle.as_deref()).await?;
+
+    // The event's handle is only a hint; take the one that verifies both ways
+    let handle = match ctx.identity.resolve_did_to_handle(&identity.did).await {
+        Ok(h) => h,
+        // Leaves the cursor in place, so the event is retried
+        Err(e) if e.is::<Unavailable>() => return Err(e),
+        // The DID no longer resolves, so no handle can be verified for it
+        Err(e) => {
+            tracing::warn!("failed to resolve DID {}: {e}", identity.did);
+            INVALID_HANDLE.to_string()
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+    db::update_author_handle(&mut tx, &identity.did, &handle).await?;
+    db::save_cursor(&mut *tx, ctx.stream, cursor).await?;
+    tx.commit().await?;
+    tracing::info!("updated handle for {} to {}", identity.did, handle);
+    Ok(true)
+}
+
+/// Hosting status change: hide recipes of deactivated or taken-down accounts,
+/// purge deleted ones, and restore them when the account becomes active again.
+async fn apply_account(ctx: &Context, cursor: i64, account: AccountEvent) -> anyhow::Result<bool> {
+    let pool = &ctx.pool;
+    if !db::is_known_author(pool, &account.did).await? {
+        return Ok(false);
+    }
+
+    let mut tx = pool.begin().await?;
+    match (account.active, account.status.as_deref()) {
+        (true, _) => {
+            db::set_author_hidden(&mut tx, &account.did, false).await?;
+            tracing::info!("account {} reactivated, recipes restored", account.did);
+        }
+        (false, Some("deleted")) => {
+            db::purge_author(&mut tx, &account.did).await?;
+            tracing::info!("account {} deleted, recipes purged", account.did);
+        }
+        (false, Some("deactivated" | "takendown" | "suspended")) => {
+            db::set_author_hidden(&mut tx, &account.did, true).await?;
+            tracing::info!("account {} is {:?}, recipes hidden", account.did, account.status);
+        }
+        // Transient states like `desynchronized` or `throttled` don't affect visibility
+        (false, _) => return Ok(false),
+    }
+    db::save_cursor(&mut *tx, ctx.stream, cursor).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Hand each record write of a commit to the indexer registered for its
+/// collection. The hooks and the cursor update share one transaction.
+async fn apply_commit(
+    ctx: &Context,
+    did: &str,
+    cursor: i64,
+    ops: Vec<RecordOp>,
+) -> anyhow::Result<bool> {
+    let mut tx = ctx.pool.begin().await?;
+    let after_commit = AfterCommit::default();
+    let mut changed = false;
+    for op in ops {
+        let Some(indexer) = ctx.indexers.get(&op.collection) else {
+            continue;
+        };
+        let event = RecordCommit {
+            did,
+            rkey: &op.rkey,
+            after_commit: &after_commit,
+        };
+        changed |= match (op.operation.as_str(), op.record) {
+            ("create", Some(record)) => indexer.on_create(&mut tx, &event, record).await?,
+            ("update", Some(record)) => indexer.on_update(&mut tx, &event, record).await?,
+            ("delete", _) => indexer.on_delete(&mut tx, &event).await?,
+            _ => false,
+        };
+    }
+    // Nothing was written; dropping the transaction rolls it back
+    if !changed {
+        return Ok(false);
+    }
+    db::save_cursor(&mut *tx, ctx.stream, cursor).await?;
+    tx.commit().await?;
+    after_commit.run();
+    Ok(true)
+}
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use atproto_api::Collection;
+use sqlx::SqliteConnection;
+
+use super::{Indexer, RecordCommit};
+use crate::records::{Recipe, RecipeRecord};
+use crate::identity::{INVALID_HANDLE, IdentityResolver, Unavailable};
+use crate::{blob_warmer::BlobWarmer, db};
+
+/// Indexes `eu.atchef.recipe` records and queues their cover images for
+/// warming.
+pub struct RecipeIndexer {
+    identity: Arc<IdentityResolver>,
+    blob_warmer: BlobWarmer,
+}
+
+impl RecipeIndexer {
+    pub fn new(identity: Arc<IdentityResolver>, blob_warmer: BlobWarmer) -> Self {
+        Self { identity, blob_warmer }
+    }
+}
+
+#[async_trait]
+impl Indexer for RecipeIndexer {
+    fn collection(&self) -> &'static str {
+        Recipe::NSID
+    }
+
+    async fn on_create(
+        &self,
+        conn: &mut SqliteConnection,
+        commit: &RecordCommit<'_>,
+        record: serde_json::Value,
+    ) -> anyhow::Result<bool> {
+        let record: RecipeRecord = match serde_json::from_value(record) {
+            Ok(r) => r,
+            Err(e) => {
+                tracing::warn!("failed to parse recipe record: {e}");
+                return Ok(false);
+            }
+        };
+        let handle = match self.identity.resolve_did_to_handle(commit.did).await {
+            Ok(h) => h,
+            // Leaves the cursor in place, so the event is retried
+            Err(e) if e.is::<Unavailable>() => return Err(e),
+            // The DID can't be resolved at all. Index the recipe anyway; the
+            // author's next identity event fills in the handle.
+            Err(e) => {
+                tracing::warn!("failed to resolve DID {}, indexing without handle: {e}", commit.did);
+                INVALID_HANDLE.to_string()
+            }
+        };
+        record.save(&mut *conn, commit.did, &handle, commit.rkey).await?;
+
+        if let Some(cid) = record.image_cid() {
+            let mime_type = record.image_mime_type().unwrap_or_else(|| "image/jpeg".to_string());
+            let (warmer, did) = (self.blob_warmer.clone(), commit.did.to_string());
+            commit.after_commit.push(move || warmer.enqueue(&did, &cid, &mime_type));
+        }
+        Ok(true)
+    }
+
+    async fn on_delete(&self, conn: &mut SqliteConnection, commit: &RecordCommit<'_>) -> anyhow::Result<bool> {
+        db::delete_recipe(&mut *conn, commit.rkey, commit.did).await?;
+        Ok(true)
+    }
+}
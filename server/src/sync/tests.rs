@@ -0,0 +1,428 @@
+//! Replays the hand-written Jetstream recordings in `tests/fixtures/jetstream`
+//! against an in-memory database. Identity lookups go to a local PLC stand-in
+//! and a stub DNS resolver, so nothing here touches the network.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use atproto_api::RetryPolicy;
+use atproto_api::identity::StaticTxtResolver;
+use axum::extract::{Path, Query};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router, http::StatusCode, routing::get};
+use sqlx::{SqliteConnection, SqlitePool};
+use tokio_tungstenite::tungstenite::Message;
+
+use super::{
+    Context, CursorCommand, FrameDecoder, Indexer, IndexerRegistry, JetstreamConfig, LocalFailure, RecordCommit,
+    RecordOp, SyncControl, apply_commit, recipe::RecipeIndexer, replay_frames,
+};
+use crate::blob_cache::BlobCacheService;
+use crate::db::CursorStream;
+use crate::blob_warmer::{BlobWarmer, BlobWarmerConfig};
+use crate::identity::{IdentityCacheConfig, IdentityResolver};
+use crate::pds::PdsClient;
+
+const COMMITS: &str = include_str!("../../tests/fixtures/jetstream/commits.jsonl");
+const MALFORMED: &str = include_str!("../../tests/fixtures/jetstream/malformed.jsonl");
+const IDENTITY: &str = include_str!("../../tests/fixtures/jetstream/identity.jsonl");
+const ACCOUNT: &str = include_str!("../../tests/fixtures/jetstream/account.jsonl");
+
+/// `com.atproto.sync.getRecord` proof for Alice's recipe 3k3, whose block
+/// the firehose fixtures `missing_block.bin` and `too_big.bin` leave out.
+const GET_RECORD: &[u8] = include_bytes!("../../tests/fixtures/firehose/get_record.car");
+
+/// A PLC directory serving DID documents, and the PDS they point to. Mallory's
+/// document claims Alice's handle, which DNS says belongs to Alice.
+async fn mock_plc() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let pds = url.clone();
+    let did_document = move |Path(did): Path<String>| async move {
+        let handle = match did.as_str() {
+            "did:plc:alice" | "did:plc:mallory" => "alice.test",
+            "did:plc:bob" => "bob.test",
+            "did:plc:flaky" => return Err(StatusCode::SERVICE_UNAVAILABLE),
+            _ => return Err(StatusCode::NOT_FOUND),
+        };
+        Ok(Json(serde_json::json!({
+            "id": did,
+            "alsoKnownAs": [format!("at://{}", handle)],
+            "service": [{
+                "id": "#atproto_pds",
+                "type": "AtprotoPersonalDataServer",
+                "serviceEndpoint": pds,
+            }],
+        })))
+    };
+    async fn get_record(Query(params): Query<HashMap<String, String>>) -> Response {
+        let path = (params["did"].as_str(), params["collection"].as_str(), params["rkey"].as_str());
+        if path == ("did:plc:alice", "eu.atchef.recipe", "3k3") {
+            GET_RECORD.into_response()
+        } else {
+            let body = serde_json::json!({ "error": "RecordNotFound", "message": "Could not locate record" });
+            (StatusCode::BAD_REQUEST, Json(body)).into_response()
+        }
+    }
+
+    let app = Router::new()
+        .route("/xrpc/com.atproto.sync.getRecord", get(get_record))
+        .route("/{did}", get(did_document));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+/// PDS requests without retries, so failures show up right away.
+pub(super) fn pds_client() -> PdsClient {
+    PdsClient::new(reqwest::Client::new(), RetryPolicy::none(), Duration::from_secs(5))
+}
+
+async fn context() -> Context {
+    context_for(CursorStream::Jetstream).await
+}
+
+pub(super) async fn context_for(stream: CursorStream) -> Context {
+    let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+    crate::db::init_db(&pool).await.unwrap();
+
+    let pds = pds_client();
+    let dns = Arc::new(StaticTxtResolver::new(&[
+        ("_atproto.alice.test.", &["did=did:plc:alice"]),
+        ("_atproto.bob.test.", &["did=did:plc:bob"]),
+    ]));
+    let resolver = pds
+        .identity_resolver()
+        .dns(dns)
+        .plc_directory(mock_plc().await)
+        .build();
+    let identity = Arc::new(IdentityResolver::new(resolver, pool.clone(), IdentityCacheConfig::default()));
+    let blob_cache = Arc::new(BlobCacheService::new(Arc::new(pool.clone()), 10));
+    let blob_warmer = BlobWarmer::start(
+        BlobWarmerConfig {
+            max_attempts: 1,
+            ..Default::default()
+        },
+        pds,
+        identity.clone(),
+        blob_cache,
+    );
+
+    let mut indexers = IndexerRegistry::new();
+    indexers.register(RecipeIndexer::new(identity.clone(), blob_warmer));
+    Context {
+        identity,
+        pool,
+        indexers,
+        stream,
+    }
+}
+
+pub(super) async fn recipes(pool: &SqlitePool) -> Vec<(String, String, String, String)> {
+    sqlx::query_as("SELECT author_did, rkey, author_handle, name FROM recipes ORDER BY author_did, rkey")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+pub(super) async fn seed_recipe(pool: &SqlitePool, did: &str, handle: &str, rkey: &str) {
+    crate::db::save_recipe(
+        pool,
+        &format!("at://{}/eu.atchef.recipe/{}", did, rkey),
+        did,
+        handle,
+        rkey,
+        "Seeded",
+        "Boil @water{1%l}.",
+        1,
+        1,
+        "2025-01-01T00:00:00Z",
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn create_update_delete() {
+    let ctx = context().await;
+
+    assert_eq!(replay_frames(&ctx, COMMITS).await.unwrap(), 6);
+
+    assert_eq!(
+        recipes(&ctx.pool).await,
+        [
+            ("did:plc:alice".into(), "3k1".into(), "alice.test".into(), "Fluffy pancakes".into()),
+            // Mallory's claim to alice.test doesn't verify
+            ("did:plc:mallory".into(), "3k9".into(), "handle.invalid".into(), "Definitely Alice's".into()),
+        ]
+    );
+    let (prep, cook): (Option<i64>, Option<i64>) =
+        sqlx::query_as("SELECT prep_time, cook_time FROM recipes WHERE rkey = '3k1'")
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert_eq!((prep, cook), (Some(10), Some(15)));
+    // The trailing post isn't indexed, but its cursor is still flushed
+    assert_eq!(crate::db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), Some(1005));
+}
+
+/// Logs each hook it runs and, once committed, each note it indexed. A note
+/// with rkey `fail` fails like a database error would.
+struct NoteIndexer {
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl Indexer for NoteIndexer {
+    fn collection(&self) -> &'static str {
+        "com.example.note"
+    }
+
+    async fn on_create(
+        &self,
+        _conn: &mut SqliteConnection,
+        commit: &RecordCommit<'_>,
+        _record: serde_json::Value,
+    ) -> anyhow::Result<bool> {
+        self.log.lock().unwrap().push(format!("create {}", commit.rkey));
+        if commit.rkey == "fail" {
+            anyhow::bail!("database is locked");
+        }
+        let (log, rkey) = (self.log.clone(), commit.rkey.to_string());
+        commit.after_commit.push(move || log.lock().unwrap().push(format!("committed {rkey}")));
+        Ok(true)
+    }
+
+    async fn on_delete(&self, _conn: &mut SqliteConnection, commit: &RecordCommit<'_>) -> anyhow::Result<bool> {
+        self.log.lock().unwrap().push(format!("delete {}", commit.rkey));
+        Ok(true)
+    }
+}
+
+fn note_frame(time_us: i64, operation: &str, collection: &str, rkey: &str) -> String {
+    serde_json::json!({
+        "did": "did:plc:alice",
+        "time_us": time_us,
+        "kind": "commit",
+        "commit": {"operation": operation, "collection": collection, "rkey": rkey, "record": {"text": "hi"}},
+    })
+    .to_string()
+}
+
+#[tokio::test]
+async fn commits_are_routed_by_collection() {
+    let mut ctx = context().await;
+    let log = Arc::new(Mutex::new(Vec::new()));
+    ctx.indexers.register(NoteIndexer { log: log.clone() });
+    assert_eq!(ctx.indexers.collections(), ["com.example.note", "eu.atchef.recipe"]);
+
+    let frames = [
+        note_frame(5000, "create", "com.example.note", "n1"),
+        COMMITS.lines().next().unwrap().to_string(),
+        note_frame(5001, "create", "app.bsky.feed.post", "p1"),
+        note_frame(5002, "delete", "com.example.note", "n1"),
+    ];
+    replay_frames(&ctx, &frames.join("\n")).await.unwrap();
+
+    assert_eq!(*log.lock().unwrap(), ["create n1", "committed n1", "delete n1"]);
+    let rkeys: Vec<String> = recipes(&ctx.pool).await.into_iter().map(|r| r.1).collect();
+    assert_eq!(rkeys, ["3k1"]);
+}
+
+#[tokio::test]
+async fn after_commit_work_is_dropped_on_failure() {
+    let mut ctx = context().await;
+    let log = Arc::new(Mutex::new(Vec::new()));
+    ctx.indexers.register(NoteIndexer { log: log.clone() });
+
+    // Two writes in one Jetstream commit can't happen, so go through the
+    // firehose's multi-op path directly
+    let ops = ["n1", "fail"]
+        .into_iter()
+        .map(|rkey| RecordOp {
+            operation: "create".into(),
+            collection: "com.example.note".into(),
+            rkey: rkey.into(),
+            record: Some(serde_json::json!({"text": "hi"})),
+        })
+        .collect();
+    assert!(apply_commit(&ctx, "did:plc:alice", 6000, ops).await.is_err());
+
+    assert_eq!(*log.lock().unwrap(), ["create n1", "create fail"]);
+    assert_eq!(crate::db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn image_cid_is_stored() {
+    let ctx = context().await;
+    let frames: Vec<&str> = COMMITS.lines().collect();
+
+    replay_frames(&ctx, frames[1]).await.unwrap();
+
+    let image: (Option<String>, Option<String>) =
+        sqlx::query_as("SELECT image_cid, image_mime_type FROM recipes WHERE rkey = '3k2'")
+            .fetch_one(&ctx.pool)
+            .await
+            .unwrap();
+    assert_eq!(image, (Some("bafkreisoup".into()), Some("image/png".into())));
+}
+
+#[tokio::test]
+async fn malformed_frames_are_skipped() {
+    let ctx = context().await;
+
+    replay_frames(&ctx, MALFORMED).await.unwrap();
+
+    let rkeys: Vec<String> = recipes(&ctx.pool).await.into_iter().map(|r| r.1).collect();
+    assert_eq!(rkeys, ["good1"]);
+    assert_eq!(crate::db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), Some(2004));
+}
+
+#[tokio::test]
+async fn identity_updates_known_authors() {
+    let ctx = context().await;
+    seed_recipe(&ctx.pool, "did:plc:alice", "alice.old", "3k1").await;
+
+    replay_frames(&ctx, IDENTITY).await.unwrap();
+
+    assert_eq!(recipes(&ctx.pool).await[0].2, "alice.test");
+    assert_eq!(crate::db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), Some(3001));
+}
+
+#[tokio::test]
+async fn unresolvable_identity_is_retried_or_invalidated() {
+    let ctx = context().await;
+    seed_recipe(&ctx.pool, "did:plc:flaky", "flaky.test", "3k1").await;
+    seed_recipe(&ctx.pool, "did:plc:gone", "gone.test", "3k2").await;
+
+    // The directory is down: the event fails without moving the cursor
+    let flaky = r#"{"did":"did:plc:flaky","time_us":4000,"kind":"identity","identity":{"did":"did:plc:flaky","handle":"flaky.test","seq":1}}"#;
+    let err = replay_frames(&ctx, flaky).await.unwrap_err();
+    assert!(err.is::<LocalFailure>());
+    assert_eq!(crate::db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), None);
+
+    // The DID doesn't exist any more: its handle can't be verified
+    let gone = r#"{"did":"did:plc:gone","time_us":4001,"kind":"identity","identity":{"did":"did:plc:gone","handle":"gone.test","seq":2}}"#;
+    replay_frames(&ctx, gone).await.unwrap();
+    assert_eq!(recipes(&ctx.pool).await[1].2, "handle.invalid");
+    assert_eq!(crate::db::get_cursor(&ctx.pool, CursorStream::Jetstream).await.unwrap(), Some(4001));
+}
+
+#[tokio::test]
+async fn account_status_hides_and_purges() {
+    let ctx = context().await;
+    seed_recipe(&ctx.pool, "did:plc:alice", "alice.test", "3k1").await;
+    seed_recipe(&ctx.pool, "did:plc:bob", "bob.test", "3k2").await;
+    let frames: Vec<&str> = ACCOUNT.lines().collect();
+
+    replay_frames(&ctx, frames[0]).await.unwrap();
+    let visible = crate::db::get_all_recipes(&ctx.pool).await.unwrap();
+    assert_eq!(visible.len(), 1);
+    assert_eq!(visible[0].author_did, "did:plc:bob");
+
+    replay_frames(&ctx, &frames[1..].join("\n")).await.unwrap();
+    let visible = crate::db::get_all_recipes(&ctx.pool).await.unwrap();
+    assert_eq!(visible.len(), 1);
+    assert_eq!(visible[0].author_did, "did:plc:alice");
+    assert_eq!(recipes(&ctx.pool).await.len(), 1);
+}
+
+#[tokio::test]
+async fn hidden_accounts_stay_hidden() {
+    let ctx = context().await;
+    seed_recipe(&ctx.pool, "did:plc:alice", "alice.test", "3k1").await;
+    seed_recipe(&ctx.pool, "did:plc:bob", "bob.test", "3k2").await;
+    sqlx::query("UPDATE recipes SET image_cid = 'bafkreicover'").execute(&ctx.pool).await.unwrap();
+    seed_recipe(&ctx.pool, "did:plc:bob", "bob.test", "3k4").await;
+    sqlx::query("UPDATE recipes SET image_cid = 'bafkreibob' WHERE rkey = '3k4'").execute(&ctx.pool).await.unwrap();
+    sqlx::query("INSERT INTO blob_cache (cid, data, mime_type, size) VALUES ('bafkreicover', x'00', 'image/png', 1), ('bafkreibob', x'00', 'image/png', 1)")
+        .execute(&ctx.pool)
+        .await
+        .unwrap();
+    let frames: Vec<&str> = ACCOUNT.lines().collect();
+
+    // Alice deactivates: recipes saved afterwards are hidden too
+    replay_frames(&ctx, frames[0]).await.unwrap();
+    assert!(crate::db::is_author_hidden(&ctx.pool, "did:plc:alice").await.unwrap());
+    seed_recipe(&ctx.pool, "did:plc:alice", "alice.test", "3k3").await;
+    let visible = crate::db::get_all_recipes(&ctx.pool).await.unwrap();
+    assert_eq!(visible.len(), 2);
+    // Bob still shows the shared image
+    assert!(!crate::db::is_blob_hidden(&ctx.pool, "bafkreicover").await.unwrap());
+
+    // Alice comes back with both recipes; Bob is deleted, which purges his
+    // recipes but keeps him hidden and known
+    replay_frames(&ctx, frames[1..].join("\n").as_str()).await.unwrap();
+    assert!(!crate::db::is_author_hidden(&ctx.pool, "did:plc:alice").await.unwrap());
+    assert_eq!(crate::db::get_all_recipes(&ctx.pool).await.unwrap().len(), 2);
+    assert!(crate::db::is_author_hidden(&ctx.pool, "did:plc:bob").await.unwrap());
+    assert!(crate::db::is_known_author(&ctx.pool, "did:plc:bob").await.unwrap());
+    // Only the image nobody else uses leaves the cache
+    let cached: Vec<String> = sqlx::query_scalar("SELECT cid FROM blob_cache").fetch_all(&ctx.pool).await.unwrap();
+    assert_eq!(cached, ["bafkreicover"]);
+}
+
+/// Train a dictionary on the fixture frames, the way Jetstream trains its own
+/// on live traffic.
+fn train_dictionary() -> Vec<u8> {
+    let samples: Vec<String> = (0..200)
+        .flat_map(|i| {
+            [COMMITS, IDENTITY, ACCOUNT]
+                .into_iter()
+                .flat_map(str::lines)
+                .map(move |frame| frame.replace("\"time_us\":", &format!("\"time_us\":{i}")))
+        })
+        .collect();
+    zstd::dict::from_samples(&samples, 4096).unwrap()
+}
+
+#[test]
+fn compressed_frames_are_decoded() {
+    let dictionary = train_dictionary();
+    let path = std::env::temp_dir().join(format!("atchef-dictionary-{}", std::process::id()));
+    std::fs::write(&path, &dictionary).unwrap();
+    let config = JetstreamConfig {
+        zstd_dictionary: Some(super::load_zstd_dictionary(&path).unwrap()),
+        ..Default::default()
+    };
+    std::fs::remove_file(&path).unwrap();
+    let decoder = FrameDecoder::new(&config);
+
+    let frame = COMMITS.lines().next().unwrap();
+    let mut compressor = zstd::bulk::Compressor::with_dictionary(3, &dictionary).unwrap();
+    let compressed = compressor.compress(frame.as_bytes()).unwrap();
+    let text = decoder.decode(Message::Binary(compressed.into())).unwrap();
+    assert_eq!(text.as_deref(), Some(frame));
+
+    // Text frames pass through; garbage is an error rather than a panic
+    let text = decoder.decode(Message::Text(frame.into())).unwrap();
+    assert_eq!(text.as_deref(), Some(frame));
+    assert!(decoder.decode(Message::Binary(b"not zstd".to_vec().into())).is_err());
+}
+
+#[test]
+fn files_that_are_not_dictionaries_are_refused() {
+    let path = std::env::temp_dir().join(format!("atchef-not-a-dictionary-{}", std::process::id()));
+    std::fs::write(&path, COMMITS).unwrap();
+    assert!(super::load_zstd_dictionary(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+    assert!(super::load_zstd_dictionary(&path).is_err());
+}
+
+#[test]
+fn firehose_cursors_cannot_be_rewound_to_a_time() {
+    let (jetstream, mut jetstream_commands) = SyncControl::new(CursorStream::Jetstream);
+    jetstream.send(CursorCommand::RewindTo(1_000)).unwrap();
+    assert!(matches!(jetstream_commands.try_recv(), Ok(CursorCommand::RewindTo(1_000))));
+
+    let (firehose, mut firehose_commands) = SyncControl::new(CursorStream::Firehose);
+    assert!(firehose.send(CursorCommand::RewindTo(1_000)).is_err());
+    firehose.send(CursorCommand::Reset).unwrap();
+    assert!(matches!(firehose_commands.try_recv(), Ok(CursorCommand::Reset)));
+}
@@ -271,6 +271,13 @@ li {
 .login-form button:hover { 
   background: var(--color-accent-hover); 
 }
+.app-password-login { 
+  margin-top: 20px; 
+}
+.app-password-login summary { 
+  cursor: pointer; 
+  margin-bottom: 10px; 
+}
 .error { 
   color: var(--color-error); 
   margin-bottom: 10px; 
@@ -767,7 +774,7 @@ pub fn recipe_list(recipes: &[Recipe], user: Option<&crate::oauth::Authenticated
         @for recipe in recipes {
             div class="recipe-item" {
                 div class="recipe-title" {
-                    a href=(format!("/profile/{}/recipe/{}", recipe.author.handle, recipe.id)) { (&recipe.name) }
+                    a href=(format!("/profile/{}/recipe/{}", recipe.author.actor(), recipe.id)) { (&recipe.name) }
                 }
                 div class="recipe-meta" {
                     "by " (render_author_link(&recipe.author)) " · " (&recipe.time_ago)
@@ -959,7 +966,7 @@ document.querySelectorAll('#recipe-ingredients li[data-ingredient]').forEach(fun
 
 fn render_author_link(author: &AuthorInfo) -> Markup {
     html! {
-        a href=(format!("/profile/{}", author.handle)) {
+        a href=(format!("/profile/{}", author.actor())) {
             (author.handle)
         }
     }
@@ -1062,15 +1069,15 @@ document.addEventListener('DOMContentLoaded', function() {
             @for recipe in recipes {
                 div class="recipe-item" {
                     div class="recipe-title" {
-                        a href=(format!("/profile/{}/recipe/{}", handle, recipe.id)) { (&recipe.name) }
+                        a href=(format!("/profile/{}/recipe/{}", recipe.author.actor(), recipe.id)) { (&recipe.name) }
                     }
                     div class="recipe-meta" {
                         (&recipe.time_ago)
                         @if is_owner {
                             " · "
-                            a href=(format!("/profile/{}/recipe/{}/edit", handle, recipe.id)) class="recipe-action" { "edit" }
+                            a href=(format!("/profile/{}/recipe/{}/edit", recipe.author.actor(), recipe.id)) class="recipe-action" { "edit" }
                             " · "
-                            form method="post" action=(format!("/profile/{}/recipe/{}/delete", handle, recipe.id)) style="display:inline;" {
+                            form method="post" action=(format!("/profile/{}/recipe/{}/delete", recipe.author.actor(), recipe.id)) style="display:inline;" {
                                 button type="submit" class="recipe-action recipe-action-delete" onclick="return confirm('Delete this recipe?')" { "delete" }
                             }
                         }
@@ -1081,7 +1088,7 @@ document.addEventListener('DOMContentLoaded', function() {
     }
 }
 
-pub fn login_page(error: Option<&str>) -> Markup {
+pub fn login_page(error: Option<&str>, app_password: bool) -> Markup {
     html! {
         h1 { "Sign in" }
         p { "Sign in with your Bluesky account to share recipes." }
@@ -1094,10 +1101,23 @@ pub fn login_page(error: Option<&str>) -> Markup {
             input type="text" name="handle" placeholder="you.bsky.social" required autofocus;
             button type="submit" { "Sign in" }
         }
+
+        @if app_password {
+            details class="app-password-login" {
+                summary { "Sign in with an app password" }
+                p class="form-note" { "For accounts whose PDS doesn't support OAuth. Create an app password in your account settings; never use your main password." }
+                form method="post" action="/login/app-password" class="login-form" {
+                    input type="text" name="handle" placeholder="you.example.com" required;
+                    input type="password" name="password" placeholder="xxxx-xxxx-xxxx-xxxx" autocomplete="off" required;
+                    button type="submit" { "Sign in" }
+                }
+            }
+        }
     }
 }
 
-pub fn recipe_form_page(error: Option<&str>) -> Markup {
+/// The new recipe form, blank or filled in from a draft.
+pub fn recipe_form_page(draft: Option<&crate::models::RecipeDraft>, error: Option<&str>) -> Markup {
     html! {
         h1 { "New Recipe" }
         p { "Create a new recipe using Cooklang format." }
@@ -1109,12 +1129,14 @@ pub fn recipe_form_page(error: Option<&str>) -> Markup {
         form method="post" action="/recipe/new" class="recipe-form" enctype="multipart/form-data" {
             div class="form-group" {
                 label for="name" { "Recipe Name" }
-                input type="text" id="name" name="name" placeholder="e.g., Perfect Sourdough Bread" required;
+                input type="text" id="name" name="name" placeholder="e.g., Perfect Sourdough Bread" value=[draft.map(|d| &d.name)] required;
             }
 
             div class="form-group" {
                 label for="description" { "Description" }
-                textarea id="description" name="description" rows="2" placeholder="A brief description of this recipe..." style="min-height: auto;" {}
+                textarea id="description" name="description" rows="2" placeholder="A brief description of this recipe..." style="min-height: auto;" {
+                    @if let Some(draft) = draft { (draft.description) }
+                }
             }
 
             div class="form-group" {
@@ -1129,15 +1151,15 @@ pub fn recipe_form_page(error: Option<&str>) -> Markup {
             div class="form-row" {
                 div class="form-group" {
                     label for="portions" { "Servings" }
-                    input type="number" id="portions" name="portions" min="1" value="4" required;
+                    input type="number" id="portions" name="portions" min="1" value=(draft.map_or(4, |d| d.portions)) required;
                 }
                 div class="form-group" {
                     label for="prep_time" { "Prep (min)" }
-                    input type="number" id="prep_time" name="prep_time" min="0" value="15" required;
+                    input type="number" id="prep_time" name="prep_time" min="0" value=(draft.map_or(15, |d| d.prep_time)) required;
                 }
                 div class="form-group" {
                     label for="cook_time" { "Cook (min)" }
-                    input type="number" id="cook_time" name="cook_time" min="0" value="30" required;
+                    input type="number" id="cook_time" name="cook_time" min="0" value=(draft.map_or(30, |d| d.cook_time)) required;
                 }
             }
 
@@ -1149,7 +1171,11 @@ pub fn recipe_form_page(error: Option<&str>) -> Markup {
                 }
                 div class="editor-panel active" data-panel="write" {
                     textarea id="content" name="content" rows="15" placeholder="Write your recipe in Cooklang format..." required {
-                        "Mix @bread flour{500%g} and @water{350%g}.\n\nAdd @sourdough starter{100%g} and @salt{10%g}.\n\nBake in #Dutch oven{} for ~{25%minutes}."
+                        @if let Some(draft) = draft {
+                            (draft.content)
+                        } @else {
+                            "Mix @bread flour{500%g} and @water{350%g}.\n\nAdd @sourdough starter{100%g} and @salt{10%g}.\n\nBake in #Dutch oven{} for ~{25%minutes}."
+                        }
                     }
                 }
                 div class="editor-panel" data-panel="preview" {
@@ -1172,7 +1198,7 @@ pub fn recipe_form_page(error: Option<&str>) -> Markup {
             }
 
             div class="form-group" style="display:flex;align-items:center;gap:8px;" {
-                input type="checkbox" id="post_to_bluesky" name="post_to_bluesky" value="1" checked;
+                input type="checkbox" id="post_to_bluesky" name="post_to_bluesky" value="1" checked[draft.is_none_or(|d| d.post_to_bluesky)];
                 label for="post_to_bluesky" style="margin:0;font-weight:normal;" { "Also post to Bluesky" }
             }
 
@@ -1189,12 +1215,7 @@ pub fn recipe_form_page(error: Option<&str>) -> Markup {
 pub fn edit_recipe_form_page(
     handle: &str,
     rkey: &str,
-    name: &str,
-    description: &str,
-    portions: u64,
-    prep_time: u64,
-    cook_time: u64,
-    content: &str,
+    draft: &crate::models::RecipeDraft,
     error: Option<&str>,
 ) -> Markup {
     let action = format!("/profile/{}/recipe/{}/edit", handle, rkey);
@@ -1208,12 +1229,12 @@ pub fn edit_recipe_form_page(
         form method="post" action=(action) class="recipe-form" enctype="multipart/form-data" {
             div class="form-group" {
                 label for="name" { "Recipe Name" }
-                input type="text" id="name" name="name" value=(name) required;
+                input type="text" id="name" name="name" value=(draft.name) required;
             }
 
             div class="form-group" {
                 label for="description" { "Description" }
-                textarea id="description" name="description" rows="2" style="min-height: auto;" { (description) }
+                textarea id="description" name="description" rows="2" style="min-height: auto;" { (draft.description) }
             }
 
             div class="form-group" {
@@ -1224,15 +1245,15 @@ pub fn edit_recipe_form_page(
             div class="form-row" {
                 div class="form-group" {
                     label for="portions" { "Servings" }
-                    input type="number" id="portions" name="portions" min="1" value=(portions) required;
+                    input type="number" id="portions" name="portions" min="1" value=(draft.portions) required;
                 }
                 div class="form-group" {
                     label for="prep_time" { "Prep (min)" }
-                    input type="number" id="prep_time" name="prep_time" min="0" value=(prep_time) required;
+                    input type="number" id="prep_time" name="prep_time" min="0" value=(draft.prep_time) required;
                 }
                 div class="form-group" {
                     label for="cook_time" { "Cook (min)" }
-                    input type="number" id="cook_time" name="cook_time" min="0" value=(cook_time) required;
+                    input type="number" id="cook_time" name="cook_time" min="0" value=(draft.cook_time) required;
                 }
             }
 
@@ -1243,7 +1264,7 @@ pub fn edit_recipe_form_page(
                     button type="button" class="editor-tab" data-tab="preview" { "Preview" }
                 }
                 div class="editor-panel active" data-panel="write" {
-                    textarea id="content" name="content" rows="15" required { (content) }
+                    textarea id="content" name="content" rows="15" required { (draft.content) }
                 }
                 div class="editor-panel" data-panel="preview" {
                     div class="preview-section" {
@@ -1280,7 +1301,7 @@ pub fn chefs_page(users: &[UserRow]) -> Markup {
             ul class="chef-list" {
                 @for user in users {
                     li class="chef-item" {
-                        a href=(format!("/profile/{}", user.handle)) { (user.handle) }
+                        a href=(format!("/profile/{}", crate::identity::profile_actor(&user.did, &user.handle))) { (user.handle) }
                         span class="meta" { " · joined " (format_time_ago(&user.joined_at)) }
                     }
                 }
@@ -1664,11 +1685,18 @@ pub fn admin_login_page(error: Option<&str>) -> Markup {
     }
 }
 
-pub fn admin_dashboard_page(recipe_count: i64, blob_count: i64) -> Markup {
+pub fn admin_dashboard_page(
+    recipe_count: i64,
+    blob_count: i64,
+    warm_queue: usize,
+    stream: crate::db::CursorStream,
+    cursor: Option<i64>,
+    backfill: &crate::backfill::BackfillStatus,
+) -> Markup {
     html! {
         h1 { "Admin" }
         div class="recipe-meta" style="margin-bottom:24px;" {
-            (recipe_count) " cached recipes · " (blob_count) " cached blobs"
+            (recipe_count) " cached recipes · " (blob_count) " cached blobs · " (warm_queue) " images queued for warming"
         }
         div style="display:flex;flex-direction:column;gap:16px;" {
             div class="welcome-card" {
@@ -1685,6 +1713,53 @@ pub fn admin_dashboard_page(recipe_count: i64, blob_count: i64) -> Markup {
                     button type="submit" class="btn-primary" { "Fix image cache" }
                 }
             }
+            div class="welcome-card" {
+                @match stream {
+                    crate::db::CursorStream::Jetstream => {
+                        h2 style="margin-top:0;" { "Jetstream cursor" }
+                        p {
+                            @if let Some(cursor) = cursor.and_then(chrono::DateTime::from_timestamp_micros) {
+                                "Last processed event: " (cursor.format("%Y-%m-%d %H:%M:%S UTC")) " (" (format_time_ago(&cursor)) ")."
+                            } @else {
+                                "No cursor saved. The consumer follows the live tail."
+                            }
+                        }
+                        p { "Rewind to replay events from a point in time (UTC), or reset to skip to the live tail." }
+                        form method="post" action="/admin/cursor" style="display:flex;gap:8px;align-items:center;" {
+                            input type="hidden" name="action" value="rewind";
+                            input type="datetime-local" name="timestamp" required;
+                            button type="submit" class="btn-primary" { "Rewind" }
+                        }
+                    }
+                    crate::db::CursorStream::Firehose => {
+                        h2 style="margin-top:0;" { "Firehose cursor" }
+                        p {
+                            @if let Some(seq) = cursor {
+                                "Last processed sequence number: " (seq) "."
+                            } @else {
+                                "No cursor saved. The consumer follows the live tail."
+                            }
+                        }
+                        p { "Reset to skip to the live tail. Firehose cursors are sequence numbers, so there is no rewinding to a point in time." }
+                    }
+                }
+                form method="post" action="/admin/cursor" style="margin-top:8px;" {
+                    input type="hidden" name="action" value="reset";
+                    button type="submit" class="btn-secondary" onclick="return confirm('Reset the cursor? Events since the last one processed will be skipped.')" { "Reset" }
+                }
+            }
+            div class="welcome-card" {
+                h2 style="margin-top:0;" { "Backfill" }
+                p {
+                    @if backfill.running { "Running. " }
+                    (backfill.done) " repos done · " (backfill.pending) " pending · " (backfill.failed) " failed · " (backfill.records) " recipes imported"
+                }
+                p { "Import existing recipes from the network. Leave the list empty to discover every repo through the relay, or enter DIDs (one per line) to re-fetch just those repos. Interrupted runs resume where they stopped." }
+                form method="post" action="/admin/backfill" {
+                    textarea name="dids" rows="3" placeholder="did:plc:..." style="width:100%;margin-bottom:8px;" {}
+                    button type="submit" class="btn-primary" disabled[backfill.running] { "Start backfill" }
+                }
+            }
         }
     }
 }